@@ -0,0 +1,61 @@
+// Compiles every shader under shaders/ to SPIR-V in OUT_DIR, src/shaders.rs embeds the results.
+// Shader errors fail the build with the compiler diagnostics.
+
+use std::path::{Path, PathBuf};
+
+#[path = "src/shader_compiler.rs"]
+mod shader_compiler;
+
+use shader_compiler::{SHADER_DIR, shader_kind};
+
+fn collect_shaders(directory: &Path, shaders: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+
+        if path.is_dir() {
+            collect_shaders(&path, shaders);
+        } else if shader_kind(&path).is_some() {
+            shaders.push(path);
+        }
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", SHADER_DIR);
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+
+    let mut shaders = vec![];
+    collect_shaders(Path::new(SHADER_DIR), &mut shaders);
+    shaders.sort();
+
+    let mut failed = false;
+    for path in shaders {
+        // The ray tracing stages are empty placeholders for now
+        if std::fs::read_to_string(&path).unwrap().trim().is_empty() {
+            continue;
+        }
+
+        match shader_compiler::compile(&path) {
+            Ok(artifact) => {
+                if artifact.get_num_warnings() > 0 {
+                    for warning in artifact.get_warning_messages().lines() {
+                        println!("cargo:warning={}", warning);
+                    }
+                }
+
+                let output = out_dir.join(format!("{}.spv", path.display()));
+                std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+                std::fs::write(output, artifact.as_binary_u8()).unwrap();
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
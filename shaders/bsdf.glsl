@@ -0,0 +1,142 @@
+#define MATERIAL_LAMBERTIAN 0
+#define MATERIAL_METAL 1
+#define MATERIAL_DIELECTRIC 2
+#define MATERIAL_EMMISIVE 3
+#define MATERIAL_INTERFACE 4
+#define MATERIAL_SUBSURFACE 5
+
+#define DISPERSION_NONE 0
+#define DISPERSION_CAUCHY 1
+#define DISPERSION_SELLMEIER 2
+
+// All directions point away from the surface, 'wo' towards the viewer and 'wi' towards the next vertex.
+// The normal is expected to be on the same side as 'wo'.
+
+struct BsdfSample {
+    vec3 direction;
+
+    // Bsdf * cos(theta) / pdf, the factor the path throughput gets multiplied by
+    vec3 weight;
+    float pdf;
+
+    // Delta lobes can't be evaluated or hit by chance, EvalBsdf and BsdfPdf return 0 for them
+    bool specular;
+    // The direction depends on the wavelength it was sampled for
+    bool dispersive;
+    // How likely the wavelengths were to sample the direction relative to the hero, see WavelengthsToRGB
+    vec4 wavelengthPdfs;
+    bool valid;
+};
+
+float SchlickReflectance(float cosine, float idx)
+{
+    float r0 = (1.0-idx) / (1.0+idx);
+    r0 = r0*r0;
+
+    return r0 + (1.0-r0)* pow((1.0 - cosine), 5);
+}
+
+// Fraction of light a dielectric reflects, all of it past the critical angle
+float DielectricReflectance(float cosTheta, float refractionRatio)
+{
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+    return refractionRatio * sinTheta > 1.0 ? 1.0 : SchlickReflectance(cosTheta, refractionRatio);
+}
+
+// Wavelength in nanometers, the dispersion coefficients expect micrometers
+float DielectricIor(Material material, float wavelength)
+{
+    float l = wavelength * 0.001;
+    float l2 = l * l;
+
+    if (material.dispersion == DISPERSION_CAUCHY)
+        return material.dispersionB.x + material.dispersionB.y / l2;
+    else if (material.dispersion == DISPERSION_SELLMEIER)
+        return sqrt(1.0 + dot(material.dispersionB.xyz, l2 / (l2 - material.dispersionC.xyz)));
+
+    return material.ior;
+}
+
+vec3 EvalBsdf(Material material, vec3 normal, vec3 wo, vec3 wi)
+{
+    if (material.type == MATERIAL_LAMBERTIAN && dot(normal, wo) > 0.0 && dot(normal, wi) > 0.0)
+        return material.color / PI;
+
+    return vec3(0.0);
+}
+
+float BsdfPdf(Material material, vec3 normal, vec3 wo, vec3 wi)
+{
+    if (material.type == MATERIAL_LAMBERTIAN && dot(normal, wo) > 0.0)
+        return max(dot(normal, wi), 0.0) / PI;
+
+    return 0.0;
+}
+
+// Dispersive dielectrics sample for the hero wavelength in 'wavelengths.x'
+BsdfSample SampleBsdf(Material material, HitResult hit, vec3 wo, vec4 wavelengths, inout SamplerState samplerState)
+{
+    BsdfSample result = BsdfSample(vec3(0.0), vec3(0.0), 0.0, false, false, vec4(1.0), false);
+
+    if (material.type == MATERIAL_LAMBERTIAN)
+    {
+        result.direction = CosineSampleHemisphere(Sample2D(samplerState), hit.normal);
+        result.pdf = BsdfPdf(material, hit.normal, wo, result.direction);
+
+        if (result.pdf > 0.0)
+        {
+            result.weight = EvalBsdf(material, hit.normal, wo, result.direction) * dot(hit.normal, result.direction) / result.pdf;
+            result.valid = true;
+        }
+    }
+    else if (material.type == MATERIAL_METAL)
+    {
+        result.direction = normalize(reflect(-wo, hit.normal) + material.fuzz * UniformSampleHemisphere(Sample2D(samplerState), hit.normal));
+        result.weight = material.color;
+        result.specular = true;
+        result.valid = dot(result.direction, hit.normal) > 0.0;
+    }
+    else if (material.type == MATERIAL_DIELECTRIC || material.type == MATERIAL_SUBSURFACE)
+    {
+        float ior = DielectricIor(material, wavelengths.x);
+        float refractionRatio = hit.front ? 1.0 / ior : ior;
+
+        float cosTheta = min(dot(wo, hit.normal), 1.0);
+        float reflectance = DielectricReflectance(cosTheta, refractionRatio);
+        bool reflects = reflectance > Sample1D(samplerState);
+
+        result.direction = reflects ? reflect(-wo, hit.normal) : refract(-wo, hit.normal, refractionRatio);
+        // The colour of subsurface materials comes from the random walk inside
+        result.weight = material.type == MATERIAL_SUBSURFACE ? vec3(1.0) : material.color;
+        result.specular = true;
+        result.dispersive = material.dispersion != DISPERSION_NONE;
+        result.valid = true;
+
+        // The companions reflect into the same direction, just as likely as they reflect. They refract
+        // into directions of their own though, which the hero's refraction never samples.
+        if (result.dispersive && reflects)
+        {
+            vec4 iors = vec4(ior, DielectricIor(material, wavelengths.y), DielectricIor(material, wavelengths.z), DielectricIor(material, wavelengths.w));
+            vec4 refractionRatios = hit.front ? 1.0 / iors : iors;
+
+            result.wavelengthPdfs = vec4(
+                reflectance,
+                DielectricReflectance(cosTheta, refractionRatios.y),
+                DielectricReflectance(cosTheta, refractionRatios.z),
+                DielectricReflectance(cosTheta, refractionRatios.w)
+            ) / reflectance;
+        }
+        else if (result.dispersive)
+            result.wavelengthPdfs = vec4(1.0, 0.0, 0.0, 0.0);
+    }
+    else if (material.type == MATERIAL_INTERFACE)
+    {
+        // Invisible boundary of a medium, the ray just passes through
+        result.direction = -wo;
+        result.weight = vec3(1.0);
+        result.specular = true;
+        result.valid = true;
+    }
+
+    return result;
+}
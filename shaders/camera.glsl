@@ -0,0 +1,13 @@
+// Needs scene.glsl and intersection.glsl. Rays start at the camera and go through `uv` on the image plane,
+// (0, 0) being the lower left corner and (1, 1) the upper right one.
+
+Ray CameraRay(vec2 uv)
+{
+    return Ray(
+        camera.position, 
+        normalize(camera.lowerLeftCorner + 
+        uv.x * camera.horizontal + 
+        uv.y * camera.vertical - 
+        camera.position)
+    );
+}
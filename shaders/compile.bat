@@ -1,4 +0,0 @@
-%VULKAN_SDK%/Bin/glslc.exe %~dp0\main.comp -o %~dp0\main.spv
-%VULKAN_SDK%/Bin/glslc.exe %~dp0\preview.comp -o %~dp0\preview.spv
-
-pause
\ No newline at end of file
@@ -0,0 +1,123 @@
+// The operation types and the bounds of the evaluation come from scene.glsl, see src/csg.rs.
+// src/csg.rs also has a copy of CombineIntervals for the tests, keep them in sync.
+
+#define CSG_END 3.402823e38
+
+// Sorted, disjoint spans of the ray that are inside a CSG subtree
+struct IntervalList {
+    Interval intervals[CSG_MAX_INTERVALS];
+    uint count;
+};
+
+bool CsgInside(uint operation, bool a, bool b)
+{
+    if (operation == CSG_UNION)
+        return a || b;
+    else if (operation == CSG_INTERSECTION)
+        return a && b;
+
+    return a && !b;
+}
+
+// Even indices are the entries of the intervals, odd ones their exits
+float Boundary(IntervalList list, uint index, out vec3 normal)
+{
+    Interval interval = list.intervals[index / 2];
+    bool entry = index % 2 == 0;
+
+    normal = entry ? interval.nIn : interval.nOut;
+    return entry ? interval.tIn : interval.tOut;
+}
+
+// Sweeps over the boundaries of both lists in order, keeping the spans where the operation says inside
+IntervalList CombineIntervals(uint operation, IntervalList a, IntervalList b)
+{
+    IntervalList result;
+    result.count = 0;
+
+    bool insideA = false;
+    bool insideB = false;
+    bool inside = false;
+
+    uint i = 0;
+    uint j = 0;
+    while (i < 2 * a.count || j < 2 * b.count)
+    {
+        vec3 nA, nB;
+        float tA = i < 2 * a.count ? Boundary(a, i, nA) : CSG_END;
+        float tB = j < 2 * b.count ? Boundary(b, j, nB) : CSG_END;
+
+        float t;
+        vec3 n;
+        if (tA <= tB)
+        {
+            insideA = !insideA;
+            t = tA;
+            n = nA;
+            i++;
+        }
+        else
+        {
+            // The surface of a subtracted solid faces into it
+            insideB = !insideB;
+            t = tB;
+            n = operation == CSG_DIFFERENCE ? -nB : nB;
+            j++;
+        }
+
+        bool nowInside = CsgInside(operation, insideA, insideB);
+        if (result.count < CSG_MAX_INTERVALS)
+        {
+            if (nowInside && !inside)
+            {
+                result.intervals[result.count].tIn = t;
+                result.intervals[result.count].nIn = n;
+            }
+            else if (!nowInside && inside)
+            {
+                result.intervals[result.count].tOut = t;
+                result.intervals[result.count].nOut = n;
+                result.count++;
+            }
+        }
+        inside = nowInside;
+    }
+
+    return result;
+}
+
+// Evaluates the postfix node list of the object on a small stack of interval lists
+bool IntersectCsg(CsgObject object, Ray ray, float tMin, float tMax, out float t, out vec3 normal)
+{
+    IntervalList stack[CSG_MAX_DEPTH];
+    uint top = 0;
+
+    for (uint i = 0; i < object.nodeCount; i++)
+    {
+        Primitive node = csgNodes[object.firstNode + i];
+
+        if (node.type >= CSG_UNION)
+        {
+            top--;
+            stack[top - 1] = CombineIntervals(node.type, stack[top - 1], stack[top]);
+        }
+        else
+        {
+            Interval interval;
+            stack[top].count = PrimitiveInterval(node, ray, interval) ? 1 : 0;
+            stack[top].intervals[0] = interval;
+            top++;
+        }
+    }
+
+    t = tMax;
+    normal = vec3(0.0);
+
+    for (uint i = 0; i < stack[0].count; i++)
+    {
+        ConsiderHit(stack[0].intervals[i].tIn, stack[0].intervals[i].nIn, tMin, t, normal);
+        ConsiderHit(stack[0].intervals[i].tOut, stack[0].intervals[i].nOut, tMin, t, normal);
+    }
+
+    return t < tMax;
+}
@@ -0,0 +1,284 @@
+// Ray intersection with everything in the WorldBuffer, needs scene.glsl.
+
+struct Ray {
+    vec3 origin;
+    vec3 direction;
+};
+
+// Cutout opacity is compared against this, it's up to the including shader how noisy it is.
+// Called once for every candidate hit on a triangle with an opacity below one.
+float AlphaThreshold();
+
+struct HitResult {
+    vec3 point;
+    vec3 normal;
+
+    uint material;
+    uint medium;
+
+    float t;
+
+    bool front;
+    bool hit;
+};
+
+vec3 AtRay(Ray ray, float t)
+{
+    return ray.origin + ray.direction * t;
+}
+
+#include "primitive.glsl"
+#include "csg.glsl"
+#include "sdf.glsl"
+#include "opacity.glsl"
+
+HitResult NoHit(float tMax)
+{
+    return HitResult(
+        vec3(0.0, 0.0, 0.0),
+        vec3(0.0, 0.0, 0.0),
+        0,
+        NO_MEDIUM,
+        tMax,
+        false,
+        false
+    );
+}
+
+// Fills in a hit from the outward normal of the surface
+HitResult SurfaceHit(Ray ray, float t, vec3 n, uint material, uint medium)
+{
+    HitResult hitResult;
+
+    hitResult.material = material;
+    hitResult.medium = medium;
+    hitResult.hit = true;
+    hitResult.t = t;
+    hitResult.point = AtRay(ray, t);
+    hitResult.front = dot(ray.direction, n) < 0.0;
+    hitResult.normal = hitResult.front ? n : -n;
+
+    return hitResult;
+}
+
+HitResult HitTriangle(Vertex v0, Vertex v1, Vertex v2, uint material, uint medium, Ray ray, float tMin, float tMax) 
+{
+    vec3 edge01 = v1.position.xyz - v0.position.xyz;
+    vec3 edge02 = v2.position.xyz - v0.position.xyz;
+
+    vec3 h = cross(ray.direction, edge02);
+    float a = dot(edge01, h);
+
+    HitResult hitResult = NoHit(tMax);
+
+    // Ray is parallel to the triangle
+    if (a > -0.0000001 && a < 0.0000001)
+        return hitResult;
+
+    vec3 s = ray.origin - v0.position.xyz;
+    float u = dot(s, h) / a;
+
+    if (u < 0.0 || u > 1.0)
+        return hitResult;
+
+    vec3 q = cross(s, edge01);
+    float v = dot(ray.direction, q) / a;
+
+    if (v < 0.0 || u + v > 1.0)
+        return hitResult;
+
+    float w = 1.0 - u - v;
+
+    // At this stage we can compute t to find out where the intersection point is on the line.
+    float t = dot(edge02, q) / a;
+
+    // This means that there is a line intersection but not a ray intersection.
+    if (t <= tMin || t >= tMax)
+        return hitResult;
+
+    // The winding of the triangle can't be trusted, the outside is where the vertex normals point
+    vec3 geometricNormal = cross(edge01, edge02);
+    if (dot(geometricNormal, v0.normal.xyz + v1.normal.xyz + v2.normal.xyz) < 0.0)
+        geometricNormal = -geometricNormal;
+
+    bool front = dot(ray.direction, geometricNormal) < 0.0;
+    if (!front && materials[material].doubleSided == 0)
+        return hitResult;
+
+    vec2 uv = w * vec2(v0.position.w, v0.normal.w) + u * vec2(v1.position.w, v1.normal.w) + v * vec2(v2.position.w, v2.normal.w);
+    float opacity = SampleOpacity(materials[material], uv);
+    if (opacity < 1.0 && opacity < AlphaThreshold())
+        return hitResult;
+
+    vec3 normal = normalize(w * v0.normal.xyz + u * v1.normal.xyz + v * v2.normal.xyz);
+
+    hitResult.material = material;
+    hitResult.medium = medium;
+    hitResult.t = t;
+    hitResult.front = front;
+    hitResult.normal = front ? normal : -normal;
+    hitResult.point = AtRay(ray, t);
+    hitResult.hit = true;
+
+    return hitResult;
+}
+
+// Slab test against the bounds of a BVH node, only whether the ray enters them within (tMin, tMax)
+bool HitBounds(vec3 boundsMin, vec3 boundsMax, Ray ray, vec3 inverseDirection, float tMin, float tMax)
+{
+    vec3 t0 = (boundsMin - ray.origin) * inverseDirection;
+    vec3 t1 = (boundsMax - ray.origin) * inverseDirection;
+
+    vec3 near = min(t0, t1);
+    vec3 far = max(t0, t1);
+
+    return max(max(near.x, near.y), max(near.z, tMin)) <= min(min(far.x, far.y), min(far.z, tMax));
+}
+
+HitResult HitSphere(Sphere sphere, Ray ray, float tMin, float tMax) 
+{
+    vec3 oc = ray.origin - sphere.position;
+    float a = dot(ray.direction, ray.direction);
+    float half_b = dot(oc, ray.direction);
+    float c = dot(oc, oc) - sphere.radius * sphere.radius;
+
+    float discriminant = half_b * half_b - a * c;
+
+    if (discriminant < 0.0) 
+        return NoHit(tMax);
+    
+    float sqrtd = sqrt(discriminant);
+
+    float root = (-half_b - sqrtd) / a;
+    if (root < tMin || tMax < root) {
+        root = (-half_b + sqrtd) / a;
+        if (root < tMin || tMax < root)
+            return NoHit(tMax);
+    }
+
+    vec3 n = (AtRay(ray, root) - sphere.position) / sphere.radius;
+
+    return SurfaceHit(ray, root, n, sphere.material, sphere.medium);
+}
+
+HitResult HitPrimitive(Primitive primitive, Ray ray, float tMin, float tMax) 
+{
+    float t;
+    vec3 n;
+    if (!IntersectPrimitive(primitive, ray, tMin, tMax, t, n))
+        return NoHit(tMax);
+
+    return SurfaceHit(ray, t, n, primitive.material, primitive.medium);
+}
+
+HitResult HitCsg(CsgObject object, Ray ray, float tMin, float tMax) 
+{
+    float t;
+    vec3 n;
+    if (!IntersectCsg(object, ray, tMin, tMax, t, n))
+        return NoHit(tMax);
+
+    return SurfaceHit(ray, t, n, object.material, object.medium);
+}
+
+HitResult HitSdf(SdfObject object, Ray ray, float tMin, float tMax) 
+{
+    float t;
+    vec3 n;
+    if (!IntersectSdf(object, ray, tMin, tMax, t, n))
+        return NoHit(tMax);
+
+    return SurfaceHit(ray, t, n, object.material, object.medium);
+}
+
+HitResult HitWorld(Ray ray, float tMin, float tMax) 
+{
+    HitResult result = NoHit(tMax);
+
+    for (uint i = 0; i < sphereCount; i++) 
+    {
+        HitResult current = HitSphere(spheres[i], ray, tMin, result.t);
+
+        if (current.hit)
+            result = current;
+    }
+
+    for (uint i = 0; i < primitiveCount; i++) 
+    {
+        HitResult current = HitPrimitive(primitives[i], ray, tMin, result.t);
+
+        if (current.hit)
+            result = current;
+    }
+
+    for (uint i = 0; i < csgObjectCount; i++) 
+    {
+        HitResult current = HitCsg(csgObjects[i], ray, tMin, result.t);
+
+        if (current.hit)
+            result = current;
+    }
+
+    for (uint i = 0; i < sdfObjectCount; i++) 
+    {
+        HitResult current = HitSdf(sdfObjects[i], ray, tMin, result.t);
+
+        if (current.hit)
+            result = current;
+    }
+
+    for (uint i = 0; i < meshCount; i++) 
+    {
+        Mesh mesh = meshes[i];
+        if (mesh.indexCount < 3)
+            continue;
+
+        // The direction isn't renormalized, so t means the same along both rays
+        Ray objectRay = Ray((mesh.worldToObject * vec4(ray.origin, 1.0)).xyz, (mesh.worldToObject * vec4(ray.direction, 0.0)).xyz);
+        vec3 inverseDirection = 1.0 / objectRay.direction;
+        mat3 normalToWorld = transpose(mat3(mesh.worldToObject));
+
+        // Depth first, the right children wait on the stack while the left ones are visited
+        uint stack[BVH_MAX_DEPTH];
+        uint stackSize = 0;
+        uint node = bvhRoots[i];
+
+        while (true)
+        {
+            BvhNode bvhNode = bvhNodes[node];
+
+            if (HitBounds(bvhNode.min, bvhNode.max, objectRay, inverseDirection, tMin, result.t))
+            {
+                if (bvhNode.triangleCount == 0)
+                {
+                    stack[stackSize++] = bvhNode.first + 1;
+                    node = bvhNode.first;
+                    continue;
+                }
+
+                for (uint j = bvhNode.first; j < bvhNode.first + bvhNode.triangleCount * 3; j += 3) 
+                {
+                    Vertex v0 = vertices[indices[j+0]];
+                    Vertex v1 = vertices[indices[j+1]];
+                    Vertex v2 = vertices[indices[j+2]];
+
+                    HitResult current = HitTriangle(v0, v1, v2, mesh.materialIndex, mesh.medium, objectRay, tMin, result.t);
+
+                    if (current.hit)
+                    {
+                        current.point = AtRay(ray, current.t);
+                        current.normal = normalize(normalToWorld * current.normal);
+                        result = current;
+                    }
+                }
+            }
+
+            if (stackSize == 0)
+                break;
+
+            node = stack[--stackSize];
+        }
+    }
+
+    return result;
+}
@@ -0,0 +1,73 @@
+// Not a ?:, glslang stores its result without converting from the buffer's layout, which is invalid SPIR-V
+Medium GetMedium(uint index)
+{
+    if (index == NO_MEDIUM)
+        return fog;
+
+    return media[index];
+}
+
+// Medium filling an object that was just entered, subsurface materials carry their own
+Medium InteriorMedium(Material material, uint index)
+{
+    if (material.type == MATERIAL_SUBSURFACE)
+        return Medium(material.absorption, material.anisotropy, material.scattering);
+
+    return GetMedium(index);
+}
+
+bool IsVacuum(Medium medium)
+{
+    return all(equal(medium.absorption + medium.scattering, vec3(0.0)));
+}
+
+// Direction scattered around 'direction', the direction of propagation. The phase function
+// is sampled exactly, so the sample's weight is always one.
+vec3 SampleHenyeyGreenstein(vec3 direction, float g, vec2 u)
+{
+    float cosTheta;
+    if (abs(g) < 0.001)
+        cosTheta = 1.0 - 2.0 * u.x;
+    else
+    {
+        float sq = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.x);
+        cosTheta = (1.0 + g * g - sq * sq) / (2.0 * g);
+    }
+
+    float sinTheta = sqrt(max(0.0, 1.0 - cosTheta * cosTheta));
+    float phi = 2.0 * PI * u.y;
+
+    vec3 tangent, bitangent;
+    BuildBasis(direction, tangent, bitangent);
+
+    return sinTheta * cos(phi) * tangent + sinTheta * sin(phi) * bitangent + cosTheta * direction;
+}
+
+// Free-flight sampling of a homogeneous medium up to 'tMax'. The distance is sampled from one
+// randomly picked colour channel and weighted by the pdf averaged over all three (spectral MIS).
+// Returns true when the ray scattered, 't' is then the distance of the scattering event.
+bool SampleMedium(Medium medium, float tMax, inout vec3 throughput, out float t, inout SamplerState samplerState)
+{
+    vec3 sigmaT = medium.absorption + medium.scattering;
+    vec2 u = Sample2D(samplerState);
+
+    uint channel = min(uint(u.x * 3.0), 2u);
+    t = sigmaT[channel] > 0.0 ? -log(1.0 - u.y) / sigmaT[channel] : tMax;
+
+    bool scattered = t < tMax;
+    t = min(t, tMax);
+
+    vec3 transmittance = exp(-sigmaT * t);
+    vec3 density = scattered ? sigmaT * transmittance : transmittance;
+    float pdf = (density.x + density.y + density.z) / 3.0;
+
+    if (pdf <= 0.0)
+    {
+        throughput = vec3(0.0);
+        return false;
+    }
+
+    throughput *= (scattered ? transmittance * medium.scattering : transmittance) / pdf;
+
+    return scattered;
+}
@@ -0,0 +1,15 @@
+// Masks are nearest sampled and wrap around, v runs up the image like in OBJ files
+float SampleOpacity(Material material, vec2 uv)
+{
+    if (material.opacityMask == NO_OPACITY_MASK)
+        return material.opacity;
+
+    uvec4 mask = opacityMasks[material.opacityMask]; // First texel, width, height
+    vec2 wrapped = fract(vec2(uv.x, 1.0 - uv.y));
+    uvec2 texel = min(uvec2(wrapped * vec2(mask.yz)), mask.yz - 1u);
+
+    uint index = mask.x + texel.y * mask.y + texel.x;
+    uint value = (opacityTexels[index / 4] >> (8 * (index % 4))) & 0xFFu;
+
+    return material.opacity * float(value) / 255.0;
+}
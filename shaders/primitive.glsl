@@ -0,0 +1,255 @@
+#define PRIMITIVE_PLANE 0
+#define PRIMITIVE_BOX 1
+#define PRIMITIVE_DISK 2
+#define PRIMITIVE_CYLINDER 3
+#define PRIMITIVE_CONE 4
+#define PRIMITIVE_QUAD 5
+#define PRIMITIVE_SPHERE 6
+
+#define PRIMITIVE_INFINITY 1e30
+
+// Intersection routines for the analytic primitives, the CPU reference lives in src/primitive.rs.
+// All of them report the closest hit within (tMin, t) and an outward facing normal.
+
+void ConsiderHit(float root, vec3 n, float tMin, inout float t, inout vec3 normal)
+{
+    if (root > tMin && root < t)
+    {
+        t = root;
+        normal = n;
+    }
+}
+
+bool IntersectDisk(vec3 center, vec3 normal, float radius, Ray ray, out float t)
+{
+    float denom = dot(normal, ray.direction);
+    if (abs(denom) < 1e-8)
+        return false;
+
+    t = dot(center - ray.origin, normal) / denom;
+    vec3 p = ray.origin + ray.direction * t - center;
+
+    return dot(p, p) <= radius * radius;
+}
+
+// Entry and exit of a ray through a convex solid, over the whole line rather than just (tMin, tMax).
+// The normals point away from the solid. Plane primitives are the half-space behind the plane here.
+struct Interval {
+    float tIn;
+    float tOut;
+    vec3 nIn;
+    vec3 nOut;
+};
+
+void ExtendInterval(float root, vec3 n, inout Interval interval)
+{
+    if (root < interval.tIn)
+    {
+        interval.tIn = root;
+        interval.nIn = n;
+    }
+    if (root > interval.tOut)
+    {
+        interval.tOut = root;
+        interval.nOut = n;
+    }
+}
+
+// Slab test in the frame of the box, axes in data[1..3].xyz and half extents in their w
+bool BoxInterval(Primitive primitive, Ray ray, out Interval interval)
+{
+    vec3 axes[3] = vec3[3](primitive.data[1].xyz, primitive.data[2].xyz, primitive.data[3].xyz);
+    vec3 o = ray.origin - primitive.data[0].xyz;
+
+    interval = Interval(-PRIMITIVE_INFINITY, PRIMITIVE_INFINITY, vec3(0.0), vec3(0.0));
+
+    for (uint i = 0; i < 3; i++)
+    {
+        float localOrigin = dot(o, axes[i]);
+        float localDirection = dot(ray.direction, axes[i]);
+        float halfExtent = primitive.data[i + 1].w;
+
+        float t0 = (-halfExtent - localOrigin) / localDirection;
+        float t1 = (halfExtent - localOrigin) / localDirection;
+
+        if (min(t0, t1) > interval.tIn)
+        {
+            interval.tIn = min(t0, t1);
+            interval.nIn = axes[i] * -sign(localDirection);
+        }
+        if (max(t0, t1) < interval.tOut)
+        {
+            interval.tOut = max(t0, t1);
+            interval.nOut = axes[i] * sign(localDirection);
+        }
+    }
+
+    return interval.tIn <= interval.tOut;
+}
+
+// Capped cylinder or cone, base and radius in data[0], axis and height in data[1]
+bool CylinderInterval(Primitive primitive, Ray ray, bool cone, out Interval interval)
+{
+    vec3 base = primitive.data[0].xyz;
+    float radius = primitive.data[0].w;
+    vec3 axis = primitive.data[1].xyz;
+    float height = primitive.data[1].w;
+
+    vec3 oc = ray.origin - base;
+    float oy = dot(oc, axis);
+    float dy = dot(ray.direction, axis);
+    vec3 ocPerp = oc - axis * oy;
+    vec3 dPerp = ray.direction - axis * dy;
+
+    // Both shapes are convex, so the interval spans from the closest to the furthest surface crossing
+    interval = Interval(PRIMITIVE_INFINITY, -PRIMITIVE_INFINITY, vec3(0.0), vec3(0.0));
+
+    // |p_perp|² = r(y)², with r(y) shrinking linearly to zero at the apex for cones
+    float k = cone ? radius / height : 0.0;
+    float m = cone ? height - oy : 0.0;
+
+    float a = dot(dPerp, dPerp) - k * k * dy * dy;
+    float halfB = dot(ocPerp, dPerp) + k * k * m * dy;
+    float c = dot(ocPerp, ocPerp) - (cone ? k * k * m * m : radius * radius);
+
+    float discriminant = halfB * halfB - a * c;
+    if (discriminant >= 0.0 && abs(a) > 1e-8)
+    {
+        float sqrtd = sqrt(discriminant);
+        float roots[2] = float[2]((-halfB - sqrtd) / a, (-halfB + sqrtd) / a);
+
+        for (uint i = 0; i < 2; i++)
+        {
+            float y = oy + roots[i] * dy;
+            if (y >= 0.0 && y <= height)
+            {
+                vec3 radial = normalize(ocPerp + dPerp * roots[i]);
+                ExtendInterval(roots[i], cone ? normalize(radial * height + axis * radius) : radial, interval);
+            }
+        }
+    }
+
+    float capT;
+    if (IntersectDisk(base, -axis, radius, ray, capT))
+        ExtendInterval(capT, -axis, interval);
+    if (!cone && IntersectDisk(base + axis * height, axis, radius, ray, capT))
+        ExtendInterval(capT, axis, interval);
+
+    return interval.tIn <= interval.tOut;
+}
+
+bool SphereInterval(Primitive primitive, Ray ray, out Interval interval)
+{
+    vec3 center = primitive.data[0].xyz;
+    float radius = primitive.data[0].w;
+
+    vec3 oc = ray.origin - center;
+    float a = dot(ray.direction, ray.direction);
+    float halfB = dot(oc, ray.direction);
+    float c = dot(oc, oc) - radius * radius;
+
+    float discriminant = halfB * halfB - a * c;
+    if (discriminant < 0.0)
+        return false;
+
+    float sqrtd = sqrt(discriminant);
+    float tIn = (-halfB - sqrtd) / a;
+    float tOut = (-halfB + sqrtd) / a;
+
+    interval = Interval(
+        tIn,
+        tOut,
+        (ray.origin + ray.direction * tIn - center) / radius,
+        (ray.origin + ray.direction * tOut - center) / radius
+    );
+
+    return true;
+}
+
+bool HalfSpaceInterval(Primitive primitive, Ray ray, out Interval interval)
+{
+    vec3 n = primitive.data[1].xyz;
+    float distance = dot(ray.origin - primitive.data[0].xyz, n);
+    float denom = dot(n, ray.direction);
+
+    if (abs(denom) < 1e-8)
+    {
+        interval = Interval(-PRIMITIVE_INFINITY, PRIMITIVE_INFINITY, vec3(0.0), vec3(0.0));
+        return distance <= 0.0;
+    }
+
+    float t = -distance / denom;
+    if (denom < 0.0)
+        interval = Interval(t, PRIMITIVE_INFINITY, n, vec3(0.0));
+    else
+        interval = Interval(-PRIMITIVE_INFINITY, t, vec3(0.0), n);
+
+    return true;
+}
+
+// Only defined for the solid primitives, disks and quads have no inside
+bool PrimitiveInterval(Primitive primitive, Ray ray, out Interval interval)
+{
+    if (primitive.type == PRIMITIVE_PLANE)
+        return HalfSpaceInterval(primitive, ray, interval);
+    else if (primitive.type == PRIMITIVE_BOX)
+        return BoxInterval(primitive, ray, interval);
+    else if (primitive.type == PRIMITIVE_CYLINDER || primitive.type == PRIMITIVE_CONE)
+        return CylinderInterval(primitive, ray, primitive.type == PRIMITIVE_CONE, interval);
+    else if (primitive.type == PRIMITIVE_SPHERE)
+        return SphereInterval(primitive, ray, interval);
+
+    return false;
+}
+
+bool IntersectPrimitive(Primitive primitive, Ray ray, float tMin, float tMax, out float t, out vec3 normal)
+{
+    t = tMax;
+    normal = vec3(0.0);
+
+    if (primitive.type == PRIMITIVE_PLANE)
+    {
+        vec3 n = primitive.data[1].xyz;
+        float denom = dot(n, ray.direction);
+        if (abs(denom) > 1e-8)
+            ConsiderHit(dot(primitive.data[0].xyz - ray.origin, n) / denom, n, tMin, t, normal);
+    }
+    else if (primitive.type == PRIMITIVE_DISK)
+    {
+        float diskT;
+        if (IntersectDisk(primitive.data[0].xyz, primitive.data[1].xyz, primitive.data[0].w, ray, diskT))
+            ConsiderHit(diskT, primitive.data[1].xyz, tMin, t, normal);
+    }
+    else if (primitive.type == PRIMITIVE_QUAD)
+    {
+        vec3 corner = primitive.data[0].xyz;
+        vec3 u = primitive.data[1].xyz;
+        vec3 v = primitive.data[2].xyz;
+        vec3 n = cross(u, v);
+
+        float denom = dot(n, ray.direction);
+        if (abs(denom) > 1e-8)
+        {
+            float root = dot(corner - ray.origin, n) / denom;
+            vec3 p = ray.origin + ray.direction * root - corner;
+
+            vec3 w = n / dot(n, n);
+            float alpha = dot(w, cross(p, v));
+            float beta = dot(w, cross(u, p));
+
+            if (alpha >= 0.0 && alpha <= 1.0 && beta >= 0.0 && beta <= 1.0)
+                ConsiderHit(root, normalize(n), tMin, t, normal);
+        }
+    }
+    else
+    {
+        Interval interval;
+        if (PrimitiveInterval(primitive, ray, interval))
+        {
+            ConsiderHit(interval.tIn, interval.nIn, tMin, t, normal);
+            ConsiderHit(interval.tOut, interval.nOut, tMin, t, normal);
+        }
+    }
+
+    return t < tMax;
+}
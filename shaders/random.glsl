@@ -1,11 +1,12 @@
+// The constants come from scene.glsl, the sampler tests in src/sampler.rs use the same ones
 void hash(inout uint seed)
 {
-    seed ^= 2747636419u;
-    seed *= 2654435769u;
+    seed ^= HASH_XOR;
+    seed *= HASH_MULTIPLIER;
     seed ^= seed >> 16u;
-    seed *= 2654435769u;
+    seed *= HASH_MULTIPLIER;
     seed ^= seed >> 16u;
-    seed *= 2654435769u;
+    seed *= HASH_MULTIPLIER;
 }
 float RandomFloat(inout uint seed)
 {
@@ -0,0 +1,171 @@
+// The sampler types and the hash constants come from scene.glsl, see src/sampler.rs
+
+#define PI 3.1415926
+
+struct SamplerState {
+    uint type;
+    uint seed;
+    uint pixelSeed;
+    uvec2 pixel;
+    uint index;
+    uint dimension;
+};
+
+float UintToFloat(uint x)
+{
+    return float(x >> 8) * (1.0 / 16777216.0);
+}
+
+// Second dimension of the Sobol sequence, the first one is just the bit reversed index
+uint SobolSecondDimension(uint index)
+{
+    uint v = 1u << 31;
+    uint result = 0u;
+
+    for (; index != 0u; index >>= 1)
+    {
+        if ((index & 1u) != 0u)
+            result ^= v;
+
+        v ^= v >> 1;
+    }
+
+    return result;
+}
+
+// Hash based Owen scrambling, "Practical Hash-based Owen Scrambling" (Burley 2020)
+uint LaineKarrasPermutation(uint x, uint seed)
+{
+    x += seed;
+    x ^= x * LAINE_KARRAS_MULTIPLIER_0;
+    x ^= x * LAINE_KARRAS_MULTIPLIER_1;
+    x ^= x * LAINE_KARRAS_MULTIPLIER_2;
+    x ^= x * LAINE_KARRAS_MULTIPLIER_3;
+
+    return x;
+}
+uint NestedUniformScramble(uint x, uint seed)
+{
+    x = bitfieldReverse(x);
+    x = LaineKarrasPermutation(x, seed);
+    x = bitfieldReverse(x);
+
+    return x;
+}
+
+// Each dimension pair gets its own shuffle and scramble, so any number of 2D samples can be drawn
+vec2 ShuffledScrambledSobol2D(uint index, uint seed)
+{
+    index = NestedUniformScramble(index, seed);
+
+    uint x = bitfieldReverse(index);
+    uint y = SobolSecondDimension(index);
+
+    hash(seed);
+    x = NestedUniformScramble(x, seed);
+    hash(seed);
+    y = NestedUniformScramble(y, seed);
+
+    return vec2(UintToFloat(x), UintToFloat(y));
+}
+
+// Blue noise tile lookup, shifted per dimension and animated over samples with the golden ratio
+float BlueNoise(uvec2 pixel, uint index, uint dimension)
+{
+    uint offset = dimension;
+    hash(offset);
+
+    uvec2 p = (pixel + uvec2(offset, offset >> 16)) % BLUE_NOISE_SIZE;
+    float value = (float(blueNoise[p.y * BLUE_NOISE_SIZE + p.x]) + 0.5) / float(BLUE_NOISE_SIZE * BLUE_NOISE_SIZE);
+
+    // Stepped in fixed point, float(index) would lose the fraction of index times the golden ratio in long renders
+    float golden = float((index * BLUE_NOISE_STEP) >> 8) / 16777216.0;
+    return fract(value + golden);
+}
+
+SamplerState CreateSampler(uint type, uvec2 pixel, uint frameIndex)
+{
+    uint pixelSeed = pixel.x * 1973u + pixel.y * 9277u;
+    hash(pixelSeed);
+
+    return SamplerState(
+        type,
+        (pixel.x+51252) * 758217 + (pixel.y+146728) * 184214798 + frameIndex * 124124,
+        pixelSeed,
+        pixel,
+        0,
+        0
+    );
+}
+
+void StartSample(inout SamplerState state, uint index)
+{
+    state.index = index;
+    state.dimension = 0;
+}
+
+vec2 Sample2D(inout SamplerState state)
+{
+    vec2 result;
+
+    if (state.type == SAMPLER_SOBOL)
+        result = ShuffledScrambledSobol2D(state.index, state.pixelSeed ^ (state.dimension * SOBOL_DIMENSION_SEED));
+    else if (state.type == SAMPLER_BLUE_NOISE)
+        result = vec2(
+            BlueNoise(state.pixel, state.index, state.dimension * 2),
+            BlueNoise(state.pixel, state.index, state.dimension * 2 + 1)
+        );
+    else
+        result = vec2(RandomFloat(state.seed), RandomFloat(state.seed));
+
+    state.dimension++;
+
+    return min(result, vec2(0.99999994));
+}
+
+float Sample1D(inout SamplerState state)
+{
+    return Sample2D(state).x;
+}
+
+// Independent stream for a varying number of draws, e.g. the opacity tests along one ray.
+// Takes up one dimension of `state`, the fork's own dimensions start far above the ones paths use.
+SamplerState ForkSampler(inout SamplerState state)
+{
+    SamplerState fork = state;
+    fork.seed ^= FORK_SEED;
+    fork.dimension = ++state.dimension << 16;
+
+    return fork;
+}
+
+void BuildBasis(vec3 normal, out vec3 tangent, out vec3 bitangent)
+{
+    tangent = normalize(abs(normal.x) > 0.9 ? cross(normal, vec3(0.0, 1.0, 0.0)) : cross(normal, vec3(1.0, 0.0, 0.0)));
+    bitangent = cross(normal, tangent);
+}
+
+vec3 UniformSampleHemisphere(vec2 u, vec3 normal)
+{
+    float z = u.x;
+    float r = sqrt(max(0.0, 1.0 - z * z));
+    float phi = 2.0 * PI * u.y;
+
+    vec3 tangent, bitangent;
+    BuildBasis(normal, tangent, bitangent);
+
+    return r * cos(phi) * tangent + r * sin(phi) * bitangent + z * normal;
+}
+
+// Malley's method, pdf = cos(theta) / PI
+vec3 CosineSampleHemisphere(vec2 u, vec3 normal)
+{
+    float r = sqrt(u.x);
+    float phi = 2.0 * PI * u.y;
+    float z = sqrt(max(0.0, 1.0 - u.x));
+
+    vec3 tangent, bitangent;
+    BuildBasis(normal, tangent, bitangent);
+
+    return r * cos(phi) * tangent + r * sin(phi) * bitangent + z * normal;
+}
@@ -0,0 +1,168 @@
+// Generated from the gpu_struct! declarations in src, don't edit by hand.
+// Run the tests with OXITRACE_UPDATE_LAYOUT=1 to regenerate it.
+
+#define MAX_MESHES 64
+#define MAX_VERTICES 4096
+#define MAX_INDICES 16384
+#define MAX_BVH_NODES 5461
+#define MAX_MATERIALS 64
+#define MAX_SPHERES 64
+#define MAX_MEDIA 16
+#define MAX_PRIMITIVES 64
+#define MAX_CSG_NODES 128
+#define MAX_CSG_OBJECTS 16
+#define MAX_SDF_INSTRUCTIONS 128
+#define MAX_SDF_OBJECTS 16
+#define MAX_OPACITY_MASKS 16
+#define MAX_OPACITY_TEXELS 262144
+#define CSG_MAX_DEPTH 4
+#define CSG_MAX_INTERVALS 4
+#define CSG_UNION 100
+#define CSG_INTERSECTION 101
+#define CSG_DIFFERENCE 102
+#define SDF_MAX_DEPTH 8
+#define SDF_MAX_NESTING 2
+#define SDF_SPHERE 0
+#define SDF_BOX 1
+#define SDF_TORUS 2
+#define SDF_UNION 10
+#define SDF_REPEAT_BEGIN 20
+#define SDF_REPEAT_END 21
+#define SDF_DISPLACE 30
+#define BVH_MAX_DEPTH 32
+#define BLUE_NOISE_SIZE 64
+#define SAMPLES_PER_PIXEL 80
+#define SAMPLER_RANDOM 0
+#define SAMPLER_SOBOL 1
+#define SAMPLER_BLUE_NOISE 2
+#define HASH_XOR 2747636419u
+#define HASH_MULTIPLIER 2654435769u
+#define LAINE_KARRAS_MULTIPLIER_0 0x6c50b47cu
+#define LAINE_KARRAS_MULTIPLIER_1 0xb82f1e52u
+#define LAINE_KARRAS_MULTIPLIER_2 0xc7afe638u
+#define LAINE_KARRAS_MULTIPLIER_3 0x8d22f6e6u
+#define SOBOL_DIMENSION_SEED 0x9e3779b9u
+#define FORK_SEED 0x5bd1e995u
+#define BLUE_NOISE_STEP 0x9e3779b9u
+#define NO_MEDIUM 0xFFFFFFFFu
+#define NO_OPACITY_MASK 0xFFFFFFFFu
+
+struct Vertex {
+    vec4 position;
+    vec4 normal;
+};
+
+struct Mesh {
+    uint vertexCount;
+    uint indexCount;
+    uint firstIndex;
+    uint materialIndex;
+    uint medium;
+    mat4 worldToObject;
+};
+
+struct BvhNode {
+    vec3 min;
+    uint first;
+    vec3 max;
+    uint triangleCount;
+};
+
+struct Camera {
+    vec3 position;
+    vec3 horizontal;
+    vec3 vertical;
+    vec3 lowerLeftCorner;
+    vec2 size;
+    uint frameIndex;
+    float exposure;
+    uint samplerType;
+};
+
+struct Material {
+    uint type;
+    vec3 color;
+    float fuzz;
+    float emmision;
+    float ior;
+    uint dispersion;
+    vec3 absorption;
+    vec4 dispersionB;
+    vec4 dispersionC;
+    vec3 scattering;
+    float anisotropy;
+    float opacity;
+    uint opacityMask;
+    uint doubleSided;
+};
+
+struct Sphere {
+    vec3 position;
+    float radius;
+    uint material;
+    uint medium;
+};
+
+struct Medium {
+    vec3 absorption;
+    float anisotropy;
+    vec3 scattering;
+};
+
+struct Primitive {
+    uint type;
+    uint material;
+    uint medium;
+    uint padding;
+    vec4 data[4];
+};
+
+struct CsgObject {
+    uint firstNode;
+    uint nodeCount;
+    uint material;
+    uint medium;
+};
+
+struct SdfInstruction {
+    uint op;
+    uint padding[3];
+    vec4 data[2];
+};
+
+struct SdfObject {
+    uint firstInstruction;
+    uint instructionCount;
+    uint material;
+    uint medium;
+    vec4 bounds;
+    float stepScale;
+};
+
+layout(set = 0, std430, binding = 1) buffer WorldBuffer {
+    Material materials[MAX_MATERIALS];
+    Sphere spheres[MAX_SPHERES];
+    uint sphereCount;
+    Vertex vertices[MAX_VERTICES];
+    uint indices[MAX_INDICES];
+    Mesh meshes[MAX_MESHES];
+    uint meshCount;
+    BvhNode bvhNodes[MAX_BVH_NODES];
+    uint bvhRoots[MAX_MESHES];
+    Medium media[MAX_MEDIA];
+    Medium fog;
+    Primitive primitives[MAX_PRIMITIVES];
+    uint primitiveCount;
+    Primitive csgNodes[MAX_CSG_NODES];
+    CsgObject csgObjects[MAX_CSG_OBJECTS];
+    uint csgObjectCount;
+    SdfInstruction sdfInstructions[MAX_SDF_INSTRUCTIONS];
+    SdfObject sdfObjects[MAX_SDF_OBJECTS];
+    uint sdfObjectCount;
+    uvec4 opacityMasks[MAX_OPACITY_MASKS];
+    uint opacityTexels[MAX_OPACITY_TEXELS / 4];
+};
+
+layout(set = 0, binding = 2) uniform CameraBuffer {
+    Camera camera;
+};
@@ -0,0 +1,125 @@
+// The op codes and the stack sizes come from scene.glsl, see src/sdf.rs.
+// src/sdf.rs also has a copy of EvaluateSdf for the tests, keep them in sync.
+
+#define SDF_MAX_STEPS 128
+#define SDF_EPSILON 0.0001
+
+// Polynomial smooth minimum, "smooth minimum" (Quilez)
+float SmoothMin(float a, float b, float k)
+{
+    if (k <= 0.0)
+        return min(a, b);
+
+    float h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+    return mix(b, a, h) - k * h * (1.0 - h);
+}
+
+// Runs the postfix instructions of the object, leaves push distances and unions pop two of them
+float EvaluateSdf(SdfObject object, vec3 position)
+{
+    float stack[SDF_MAX_DEPTH];
+    uint top = 0;
+
+    // Repetitions fold the point for their subtree and restore it afterwards
+    vec3 points[SDF_MAX_NESTING];
+    uint nesting = 0;
+    vec3 p = position;
+
+    for (uint i = 0; i < object.instructionCount; i++)
+    {
+        SdfInstruction instruction = sdfInstructions[object.firstInstruction + i];
+        vec4 data0 = instruction.data[0];
+        vec4 data1 = instruction.data[1];
+
+        if (instruction.op == SDF_SPHERE)
+            stack[top++] = length(p - data0.xyz) - data0.w;
+        else if (instruction.op == SDF_BOX)
+        {
+            vec3 q = abs(p - data0.xyz) - data1.xyz;
+            stack[top++] = length(max(q, 0.0)) + min(max(q.x, max(q.y, q.z)), 0.0);
+        }
+        else if (instruction.op == SDF_TORUS)
+        {
+            vec3 local = p - data0.xyz;
+            vec2 q = vec2(length(local.xz) - data0.w, local.y);
+            stack[top++] = length(q) - data1.x;
+        }
+        else if (instruction.op == SDF_UNION)
+        {
+            top--;
+            stack[top - 1] = SmoothMin(stack[top - 1], stack[top], data0.x);
+        }
+        else if (instruction.op == SDF_REPEAT_BEGIN)
+        {
+            points[nesting++] = p;
+            p -= data0.xyz * clamp(round(p / data0.xyz), -data1.xyz, data1.xyz);
+        }
+        else if (instruction.op == SDF_REPEAT_END)
+            p = points[--nesting];
+        else if (instruction.op == SDF_DISPLACE)
+        {
+            vec3 s = sin(data0.y * p);
+            stack[top - 1] += data0.x * s.x * s.y * s.z;
+        }
+    }
+
+    return stack[0];
+}
+
+// Tetrahedral finite differences of the field, points away from the surface
+vec3 SdfNormal(SdfObject object, vec3 p)
+{
+    const vec2 k = vec2(1.0, -1.0);
+    const float h = 0.0005;
+
+    return normalize(
+        k.xyy * EvaluateSdf(object, p + k.xyy * h) +
+        k.yyx * EvaluateSdf(object, p + k.yyx * h) +
+        k.yxy * EvaluateSdf(object, p + k.yxy * h) +
+        k.xxx * EvaluateSdf(object, p + k.xxx * h)
+    );
+}
+
+// Sphere tracing inside the bounding sphere of the object. Rays starting inside the surface,
+// like refracted ones, trace the negated field so they find their way out.
+bool IntersectSdf(SdfObject object, Ray ray, float tMin, float tMax, out float t, out vec3 normal)
+{
+    t = tMax;
+    normal = vec3(0.0);
+
+    vec3 oc = ray.origin - object.bounds.xyz;
+    float a = dot(ray.direction, ray.direction);
+    float halfB = dot(oc, ray.direction);
+    float c = dot(oc, oc) - object.bounds.w * object.bounds.w;
+
+    float discriminant = halfB * halfB - a * c;
+    if (discriminant < 0.0)
+        return false;
+
+    float start = max(tMin, (-halfB - sqrt(discriminant)) / a);
+    float end = min(tMax, (-halfB + sqrt(discriminant)) / a);
+    if (start >= end)
+        return false;
+
+    // Distances are along the surface, t is in units of the direction's length
+    float stepScale = object.stepScale / sqrt(a);
+    float side = EvaluateSdf(object, ray.origin + ray.direction * start) < 0.0 ? -1.0 : 1.0;
+
+    float current = start;
+    for (uint i = 0; i < SDF_MAX_STEPS && current < end; i++)
+    {
+        vec3 p = ray.origin + ray.direction * current;
+        float distance = side * EvaluateSdf(object, p);
+
+        if (distance < SDF_EPSILON)
+        {
+            t = current;
+            normal = SdfNormal(object, p);
+            return true;
+        }
+
+        current += distance * stepScale;
+    }
+
+    return false;
+}
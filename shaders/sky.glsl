@@ -0,0 +1,8 @@
+// Vertical gradient from white at the horizon to blue overhead
+vec3 SkyColor(vec3 direction)
+{
+    float sky = 0.5 * (normalize(direction).y + 1.0);
+    vec3 skyColor = (1.0-sky) * vec3(1.0, 1.0, 1.0) + sky*vec3(0.25, 0.4, 1.0);
+
+    return skyColor;
+}
@@ -0,0 +1,57 @@
+#define WAVELENGTH_MIN 380.0
+#define WAVELENGTH_MAX 780.0
+// The hero wavelength and its companions
+#define WAVELENGTH_COUNT 4
+
+// Single lobe fit of the CIE 1931 colour matching functions, "Simple Analytic Approximations
+// to the CIE XYZ Color Matching Functions" (Wyman et al. 2013)
+float CieLobe(float wavelength, float mean, float sigmaLow, float sigmaHigh)
+{
+    float t = (wavelength - mean) / (wavelength < mean ? sigmaLow : sigmaHigh);
+    return exp(-0.5 * t * t);
+}
+
+vec3 WavelengthToXYZ(float wavelength)
+{
+    return vec3(
+        1.056 * CieLobe(wavelength, 599.8, 37.9, 31.0) + 0.362 * CieLobe(wavelength, 442.0, 16.0, 26.7) - 0.065 * CieLobe(wavelength, 501.1, 20.4, 26.2),
+        0.821 * CieLobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * CieLobe(wavelength, 530.9, 16.3, 31.1),
+        1.217 * CieLobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * CieLobe(wavelength, 459.0, 26.0, 13.8)
+    );
+}
+
+// Linear sRGB weight of a uniformly sampled wavelength, scaled so that its expected value is white.
+// Spectral colours lie outside of sRGB, they are clamped to it and the scale is the one of the clamped weights.
+vec3 WavelengthToRGB(float wavelength)
+{
+    vec3 xyz = WavelengthToXYZ(wavelength);
+
+    vec3 rgb = vec3(
+         3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+         0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z
+    );
+
+    return max(rgb, 0.0) * vec3(2.27037, 3.46661, 3.65978);
+}
+
+// Hero wavelength sampling, "Hero Wavelength Spectral Sampling" (Wilkie et al. 2014). The hero in x
+// is uniform, the companions follow it at equal spacing and wrap around the visible range.
+vec4 SampleWavelengths(float u)
+{
+    vec4 offsets = fract(u + vec4(0.0, 1.0, 2.0, 3.0) / float(WAVELENGTH_COUNT));
+    return mix(vec4(WAVELENGTH_MIN), vec4(WAVELENGTH_MAX), offsets);
+}
+
+// RGB weight of a path that carried all the wavelengths, 'pdfs' is how likely each of them was to sample
+// the path relative to the hero. Balance heuristic over the wavelengths, the dielectric lobes are sampled
+// in proportion to their value, so a wavelength's throughput relative to the hero is its pdf ratio too.
+vec3 WavelengthsToRGB(vec4 wavelengths, vec4 pdfs)
+{
+    vec3 rgb = WavelengthToRGB(wavelengths.x) * pdfs.x
+             + WavelengthToRGB(wavelengths.y) * pdfs.y
+             + WavelengthToRGB(wavelengths.z) * pdfs.z
+             + WavelengthToRGB(wavelengths.w) * pdfs.w;
+
+    return rgb / dot(pdfs, vec4(1.0));
+}
@@ -0,0 +1,8 @@
+#define GAMMA 2.2
+
+// Exponential exposure curve followed by gamma correction for the rgba8 render target
+vec3 Tonemap(vec3 color, float exposure)
+{
+    color = vec3(1.0) - exp(-color * exposure);
+    return pow(color, vec3(1.0/GAMMA));
+}
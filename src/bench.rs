@@ -0,0 +1,139 @@
+use serde::Serialize;
+
+use crate::{
+    frame_stats::FrameStats,
+    renderer::Renderer,
+    sampler::SAMPLES_PER_PIXEL,
+    scenes::BuiltinScene,
+    vk::{debug_messenger::ValidationSettings, error::*, physical_device::DeviceSelector},
+};
+
+// Keep the defaults when comparing reports, results at other sizes aren't comparable
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BenchSettings {
+    pub width: u32,
+    pub height: u32,
+    // Each adds SAMPLES_PER_PIXEL samples to every pixel
+    pub frames: u32,
+    pub scenes: Vec<BuiltinScene>,
+}
+
+impl Default for BenchSettings {
+    fn default() -> Self {
+        Self {
+            width: 320,
+            height: 240,
+            frames: 4,
+            scenes: BuiltinScene::ALL.to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchReport {
+    pub device: String,
+    pub supports_timestamps: bool,
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub results: Vec<BenchResult>,
+}
+
+// The GPU timings are summed over all frames of the scene
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchResult {
+    pub scene: String,
+    // Traced through the meshes' BVHs, a scene without any only tests the analytic shapes
+    pub triangles: u32,
+    pub wall_ms: f64,
+    pub gpu_ms: f64,
+    pub trace_ms: f64,
+    pub samples: u64,
+    pub rays: u64,
+    pub samples_per_second: f64,
+    pub rays_per_second: f64,
+    // Changes whenever the image does, so a faster result can be told apart from one that renders something else
+    pub image_hash: String,
+}
+
+// Renders every scene once to warm up, then again for the timings, with the same sample indices every run
+pub fn run(device_selector: &DeviceSelector, validation: &ValidationSettings, settings: &BenchSettings) -> VkResult<BenchReport> {
+    let mut renderer = Renderer::headless(device_selector, validation, settings.width, settings.height)?;
+
+    let mut results = Vec::with_capacity(settings.scenes.len());
+    for &scene in settings.scenes.iter() {
+        let built_scene = scene.build()?;
+        renderer.bind_scene(&built_scene)?;
+        let camera = scene.camera(settings.width, settings.height);
+
+        renderer.render_to_image(&camera, 1)?;
+        renderer.take_frame_stats();
+
+        let start = std::time::Instant::now();
+        let image = renderer.render_to_image(&camera, settings.frames)?;
+        let wall_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        let result = bench_result(scene, built_scene.triangle_count(), wall_ms, &renderer.take_frame_stats(), &image);
+        log::info!("{}: {:.1} ms, {:.1} Mrays/s", result.scene, result.wall_ms, result.rays_per_second / 1e6);
+        results.push(result);
+    }
+
+    Ok(BenchReport {
+        device: renderer.device_name().to_string(),
+        supports_timestamps: renderer.supports_timestamps(),
+        width: settings.width,
+        height: settings.height,
+        samples_per_pixel: settings.frames * SAMPLES_PER_PIXEL,
+        results,
+    })
+}
+
+fn bench_result(scene: BuiltinScene, triangles: u32, wall_ms: f64, stats: &[FrameStats], image: &image::RgbaImage) -> BenchResult {
+    let total = stats.iter().fold(FrameStats::default(), |total, stats| FrameStats {
+        gpu_ms: total.gpu_ms + stats.gpu_ms,
+        trace_ms: total.trace_ms + stats.trace_ms,
+        samples: total.samples + stats.samples,
+        rays: total.rays + stats.rays,
+        ..total
+    });
+
+    BenchResult {
+        scene: scene.name().to_string(),
+        triangles,
+        wall_ms,
+        gpu_ms: total.gpu_ms,
+        trace_ms: total.trace_ms,
+        samples: total.samples,
+        rays: total.rays,
+        samples_per_second: total.samples_per_second(),
+        rays_per_second: total.rays_per_second(),
+        image_hash: image_hash(image),
+    }
+}
+
+// 64 bit FNV-1a over the size and the pixels, stable across platforms and Rust versions unlike std's hasher
+pub fn image_hash(image: &image::RgbaImage) -> String {
+    let header = [image.width().to_le_bytes(), image.height().to_le_bytes()].concat();
+
+    let hash = header.iter().chain(image.as_raw().iter()).fold(0xcbf29ce484222325u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_hash_depends_on_pixels_and_size() {
+        let black = image::RgbaImage::new(4, 2);
+        let mut white = black.clone();
+        white.put_pixel(3, 1, image::Rgba([255; 4]));
+
+        assert_eq!(image_hash(&black), image_hash(&image::RgbaImage::new(4, 2)));
+        assert_ne!(image_hash(&black), image_hash(&white));
+        assert_ne!(image_hash(&black), image_hash(&image::RgbaImage::new(2, 4)));
+    }
+}
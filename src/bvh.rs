@@ -0,0 +1,245 @@
+use cgmath::{ElementWise, Vector3, vec3};
+
+use crate::scene::Vertex;
+
+// Size of the traversal stack in intersection.glsl. Median splits keep the trees balanced,
+// one over MAX_INDICES / 3 triangles is about 13 levels deep.
+pub const BVH_MAX_DEPTH: usize = 32;
+
+// Splitting smaller leaves costs more in traversal than testing their triangles does
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+gpu_struct! {
+    #[derive(Clone, Debug, Copy, Default, PartialEq)]
+    pub struct BvhNode as "BvhNode" {
+        min: [f32; 3] => "vec3 min",
+        // Inner nodes: the left child, the right one follows it. Leaves: where their indices start in the index buffer
+        first: u32 => "uint first",
+        max: [f32; 3] => "vec3 max",
+        // Zero for inner nodes
+        triangle_count: u32 => "uint triangleCount",
+    }
+}
+
+impl BvhNode {
+    pub fn is_leaf(&self) -> bool {
+        self.triangle_count > 0
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Triangle {
+    indices: [u32; 3],
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    centroid: Vector3<f32>,
+}
+
+// Bounding volume hierarchy over the triangles of one mesh in the space of its vertices, so moving
+// the mesh with Mesh::with_transform keeps it valid. The triangles in `indices` are reordered so every
+// leaf covers a contiguous range of them. `first_index` is where `indices` start in the index buffer
+// and `first_node` where the returned nodes go in the node buffer, the root is the first of them.
+//
+// Leaves hold at least two triangles unless the mesh has a single one, so there are never more nodes
+// than triangles. Meshes without triangles get no nodes at all.
+pub fn build(vertices: &[Vertex], indices: &mut [u32], first_index: u32, first_node: u32) -> Vec<BvhNode> {
+    let position = |index: u32| vertices[index as usize].position();
+
+    let mut triangles: Vec<Triangle> = indices.chunks_exact(3).map(|triangle| {
+        let [a, b, c] = [position(triangle[0]), position(triangle[1]), position(triangle[2])];
+        let min = vec3(a.x.min(b.x).min(c.x), a.y.min(b.y).min(c.y), a.z.min(b.z).min(c.z));
+        let max = vec3(a.x.max(b.x).max(c.x), a.y.max(b.y).max(c.y), a.z.max(b.z).max(c.z));
+
+        Triangle { indices: [triangle[0], triangle[1], triangle[2]], min, max, centroid: (a + b + c) / 3.0 }
+    }).collect();
+
+    if triangles.is_empty() {
+        return vec![];
+    }
+
+    let mut nodes = vec![BvhNode::default()];
+    build_node(&mut nodes, 0, &mut triangles, first_index, first_node);
+
+    for (i, triangle) in triangles.iter().enumerate() {
+        indices[i * 3..i * 3 + 3].copy_from_slice(&triangle.indices);
+    }
+
+    nodes
+}
+
+fn build_node(nodes: &mut Vec<BvhNode>, node: usize, triangles: &mut [Triangle], first_index: u32, first_node: u32) {
+    let (min, max) = triangles.iter().fold((triangles[0].min, triangles[0].max), |(min, max), triangle| (
+        vec3(min.x.min(triangle.min.x), min.y.min(triangle.min.y), min.z.min(triangle.min.z)),
+        vec3(max.x.max(triangle.max.x), max.y.max(triangle.max.y), max.z.max(triangle.max.z)),
+    ));
+
+    if triangles.len() <= MAX_LEAF_TRIANGLES {
+        nodes[node] = BvhNode { min: min.into(), first: first_index, max: max.into(), triangle_count: triangles.len() as u32 };
+        return;
+    }
+
+    // Split at the median centroid along the axis the centroids spread the most on
+    let (centroid_min, centroid_max) = triangles.iter().fold((triangles[0].centroid, triangles[0].centroid), |(min, max), triangle| (
+        vec3(min.x.min(triangle.centroid.x), min.y.min(triangle.centroid.y), min.z.min(triangle.centroid.z)),
+        vec3(max.x.max(triangle.centroid.x), max.y.max(triangle.centroid.y), max.z.max(triangle.centroid.z)),
+    ));
+    let extent = centroid_max.sub_element_wise(centroid_min);
+    let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+
+    let middle = triangles.len() / 2;
+    triangles.select_nth_unstable_by(middle, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+
+    let left = nodes.len();
+    nodes.push(BvhNode::default());
+    nodes.push(BvhNode::default());
+    nodes[node] = BvhNode { min: min.into(), first: first_node + left as u32, max: max.into(), triangle_count: 0 };
+
+    let (left_triangles, right_triangles) = triangles.split_at_mut(middle);
+    build_node(nodes, left, left_triangles, first_index, first_node);
+    build_node(nodes, left + 1, right_triangles, first_index + middle as u32 * 3, first_node);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    // Unit cube grid of `size` * `size` quads in the xz plane, bumped up and down so the bounds aren't flat
+    fn grid(size: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = vec![];
+        for z in 0..=size {
+            for x in 0..=size {
+                let y = ((x * 7 + z * 3) % 5) as f32 * 0.05;
+                vertices.push(Vertex::new(vec3(x as f32 / size as f32, y, z as f32 / size as f32), vec3(0.0, 1.0, 0.0)));
+            }
+        }
+
+        let mut indices = vec![];
+        for z in 0..size {
+            for x in 0..size {
+                let a = z * (size + 1) + x;
+                let b = a + size + 1;
+                indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    fn triangle_hit(vertices: &[Vertex], triangle: &[u32], origin: Vector3<f32>, direction: Vector3<f32>) -> Option<f32> {
+        let position = |index: u32| vertices[index as usize].position();
+        let (v0, v1, v2) = (position(triangle[0]), position(triangle[1]), position(triangle[2]));
+
+        // Möller-Trumbore, the same as HitTriangle in intersection.glsl
+        let (edge01, edge02) = (v1 - v0, v2 - v0);
+        let h = direction.cross(edge02);
+        let a = edge01.dot(h);
+        if a.abs() < 1e-7 {
+            return None;
+        }
+
+        let s = origin - v0;
+        let u = s.dot(h) / a;
+        let q = s.cross(edge01);
+        let v = direction.dot(q) / a;
+        let t = edge02.dot(q) / a;
+
+        (u >= 0.0 && v >= 0.0 && u + v <= 1.0 && t > 0.0).then_some(t)
+    }
+
+    fn bounds_hit(node: &BvhNode, origin: Vector3<f32>, direction: Vector3<f32>, t_max: f32) -> bool {
+        let (mut near, mut far) = (0.0f32, t_max);
+        for i in 0..3 {
+            let t0 = (node.min[i] - origin[i]) / direction[i];
+            let t1 = (node.max[i] - origin[i]) / direction[i];
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        near <= far
+    }
+
+    // Stack based traversal like the one in HitWorld
+    fn closest_hit(nodes: &[BvhNode], vertices: &[Vertex], indices: &[u32], origin: Vector3<f32>, direction: Vector3<f32>) -> Option<f32> {
+        let mut closest: Option<f32> = None;
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            let node = &nodes[node];
+            if !bounds_hit(node, origin, direction, closest.unwrap_or(f32::MAX)) {
+                continue;
+            }
+
+            if node.is_leaf() {
+                for triangle in 0..node.triangle_count {
+                    let first = (node.first + triangle * 3) as usize;
+                    let t = triangle_hit(vertices, &indices[first..first + 3], origin, direction);
+                    if let Some(t) = t {
+                        closest = Some(closest.map_or(t, |closest| closest.min(t)));
+                    }
+                }
+            } else {
+                stack.extend_from_slice(&[node.first as usize, node.first as usize + 1]);
+                assert!(stack.len() <= BVH_MAX_DEPTH);
+            }
+        }
+
+        closest
+    }
+
+    #[test]
+    fn leaves_cover_every_triangle_once() {
+        let (vertices, mut indices) = grid(20);
+        let mut triangles: Vec<[u32; 3]> = indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+
+        let nodes = build(&vertices, &mut indices, 0, 0);
+        assert!(nodes.len() <= triangles.len());
+
+        let mut covered = vec![0; triangles.len()];
+        for node in nodes.iter().filter(|node| node.is_leaf()) {
+            assert!(node.triangle_count as usize <= MAX_LEAF_TRIANGLES);
+            assert_eq!(node.first % 3, 0);
+            for triangle in 0..node.triangle_count {
+                covered[(node.first / 3 + triangle) as usize] += 1;
+            }
+        }
+        assert!(covered.iter().all(|&count| count == 1));
+
+        // Only the order changes
+        let mut reordered: Vec<[u32; 3]> = indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+        triangles.sort();
+        reordered.sort();
+        assert_eq!(triangles, reordered);
+    }
+
+    #[test]
+    fn offsets_point_into_the_shared_buffers() {
+        let (vertices, mut indices) = grid(4);
+        let nodes = build(&vertices, &mut indices, 100, 10);
+
+        assert!(nodes.iter().filter(|node| !node.is_leaf()).all(|node| node.first >= 10 && node.first + 1 < 10 + nodes.len() as u32));
+        assert!(nodes.iter().filter(|node| node.is_leaf()).all(|node| node.first >= 100 && node.first + node.triangle_count * 3 <= 100 + 32 * 3));
+        assert!(build(&vertices, &mut [], 0, 0).is_empty());
+    }
+
+    #[test]
+    fn traversal_finds_the_closest_triangle() {
+        let (vertices, mut indices) = grid(32);
+        let nodes = build(&vertices, &mut indices, 0, 0);
+
+        let mut seed = 17u32;
+        let mut random = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f32 / u32::MAX as f32
+        };
+
+        for _ in 0..500 {
+            let origin = vec3(random() * 1.4 - 0.2, 1.0, random() * 1.4 - 0.2);
+            let direction = vec3(random() - 0.5, -1.0, random() - 0.5).normalize();
+
+            let brute_force = indices.chunks(3).filter_map(|triangle| triangle_hit(&vertices, triangle, origin, direction)).reduce(f32::min);
+            assert_eq!(closest_hit(&nodes, &vertices, &indices, origin, direction), brute_force);
+        }
+    }
+}
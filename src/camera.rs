@@ -1,17 +1,21 @@
 use cgmath::{Vector2, Vector3, InnerSpace};
 
+use crate::sampler::SamplerType;
+
 const WORLD_UP: Vector3<f32> = cgmath::vec3(0.0, 1.0, 0.0);
 
-#[repr(C)]
-#[derive(Clone, Debug, Copy)]
-pub struct CameraRaw {
-    position: [f32; 4],
-    horizontal: [f32; 4],
-    vertical: [f32; 4],
-    lower_left_corner: [f32; 4],
-    size: [f32; 2],
-    frame_index: [u32; 1],
-    exposure: [f32; 1],
+gpu_struct! {
+    #[derive(Clone, Debug, Copy)]
+    pub struct CameraRaw as "Camera" {
+        position: [f32; 4] => "vec3 position",
+        horizontal: [f32; 4] => "vec3 horizontal",
+        vertical: [f32; 4] => "vec3 vertical",
+        lower_left_corner: [f32; 4] => "vec3 lowerLeftCorner",
+        size: [f32; 2] => "vec2 size",
+        frame_index: [u32; 1] => "uint frameIndex",
+        exposure: [f32; 1] => "float exposure",
+        sampler_type: [u32; 4] => "uint samplerType",
+    }
 }
 
 #[derive(Clone, Debug, Copy)]
@@ -59,7 +63,7 @@ impl Camera {
         self.fov = self.fov.clamp(10.0, 120.0);
     }
 
-    pub fn to_raw(&self, frames_since_start: u32) -> CameraRaw {
+    pub fn to_raw(&self, frames_since_start: u32, sampler_type: SamplerType) -> CameraRaw {
         let aspect_ratio = self.size.x / self.size.y;
         let viewport_height = 2.0 * (self.fov / 2.0).tan();
         let viewport_width = aspect_ratio * viewport_height;
@@ -77,7 +81,8 @@ impl Camera {
             lower_left_corner: lower_left_corner.extend(0.0).into(),
             size: self.size.into(),
             frame_index: [frames_since_start; 1],
-            exposure: [self.exposure; 1]
+            exposure: [self.exposure; 1],
+            sampler_type: [sampler_type.to_raw(); 4],
         }
     }
 
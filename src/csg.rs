@@ -0,0 +1,249 @@
+use cgmath::{vec3, Vector3, Zero};
+
+use crate::primitive::*;
+use crate::scene::NO_MEDIUM;
+use crate::vk::error::*;
+
+// Evaluation stack size in shaders/csg.glsl
+pub(crate) const CSG_MAX_DEPTH: usize = 4;
+// Spans a node keeps at most, the closest ones are the ones that matter
+pub(crate) const CSG_MAX_INTERVALS: usize = 4;
+
+// Types of the inner nodes, above every primitive type so both fit in PrimitiveRaw::kind
+pub(crate) const CSG_UNION: u32 = 100;
+pub(crate) const CSG_INTERSECTION: u32 = 101;
+pub(crate) const CSG_DIFFERENCE: u32 = 102;
+
+gpu_struct! {
+    #[derive(Clone, Debug, Copy, Default)]
+    pub struct CsgObjectRaw as "CsgObject" {
+        first_node: u32 => "uint firstNode",
+        node_count: u32 => "uint nodeCount",
+        material: u32 => "uint material",
+        medium: u32 => "uint medium",
+    }
+}
+
+impl CsgObjectRaw {
+    // Index of the first node after this object's
+    pub fn end(&self) -> u32 {
+        self.first_node + self.node_count
+    }
+}
+
+// Boolean combinations of solid shapes. Planes count as the half-space behind them,
+// disks and quads have no inside and can't be used.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CsgNode {
+    Shape(Shape),
+    Union(Box<CsgNode>, Box<CsgNode>),
+    Intersection(Box<CsgNode>, Box<CsgNode>),
+    Difference(Box<CsgNode>, Box<CsgNode>),
+}
+
+impl CsgNode {
+    pub fn union(a: CsgNode, b: CsgNode) -> CsgNode {
+        CsgNode::Union(Box::new(a), Box::new(b))
+    }
+
+    pub fn intersection(a: CsgNode, b: CsgNode) -> CsgNode {
+        CsgNode::Intersection(Box::new(a), Box::new(b))
+    }
+
+    pub fn difference(a: CsgNode, b: CsgNode) -> CsgNode {
+        CsgNode::Difference(Box::new(a), Box::new(b))
+    }
+
+    // Appends the subtree in postfix order and returns the evaluation stack depth it needs
+    fn compile(&self, nodes: &mut Vec<PrimitiveRaw>) -> VkResult<usize> {
+        let (operation, a, b) = match self {
+            CsgNode::Shape(shape) => {
+                match shape {
+                    Shape::Disk { .. } => return Err(VkError::InvalidCsgOperand("disks")),
+                    Shape::Quad { .. } => return Err(VkError::InvalidCsgOperand("quads")),
+                    _ => {}
+                }
+
+                nodes.push(Primitive::new(*shape, 0).to_raw());
+                return Ok(1);
+            }
+            CsgNode::Union(a, b) => (CSG_UNION, a, b),
+            CsgNode::Intersection(a, b) => (CSG_INTERSECTION, a, b),
+            CsgNode::Difference(a, b) => (CSG_DIFFERENCE, a, b),
+        };
+
+        // The left operand's result stays on the stack while the right one is evaluated
+        let depth = a.compile(nodes)?.max(b.compile(nodes)? + 1);
+        nodes.push(PrimitiveRaw::operation(operation));
+
+        Ok(depth)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsgObject {
+    pub root: CsgNode,
+    material: u32,
+    medium: u32,
+}
+
+impl CsgObject {
+    pub fn new(root: CsgNode, material: u32) -> CsgObject {
+        CsgObject {
+            root,
+            material,
+            medium: NO_MEDIUM,
+        }
+    }
+
+    pub fn with_medium(mut self, medium: u32) -> CsgObject {
+        self.medium = medium;
+        self
+    }
+
+    pub fn to_raw(&self, first_node: u32) -> VkResult<(CsgObjectRaw, Vec<PrimitiveRaw>)> {
+        let mut nodes = Vec::new();
+        if self.root.compile(&mut nodes)? > CSG_MAX_DEPTH {
+            return Err(VkError::SceneCapacity { what: "CSG stack", capacity: CSG_MAX_DEPTH });
+        }
+
+        let raw = CsgObjectRaw {
+            first_node,
+            node_count: nodes.len() as u32,
+            material: self.material,
+            medium: self.medium,
+        };
+
+        Ok((raw, nodes))
+    }
+}
+
+// Span of a ray inside a solid, the normals point out of it
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Interval {
+    pub t_in: f32,
+    pub t_out: f32,
+    pub n_in: Vector3<f32>,
+    pub n_out: Vector3<f32>,
+}
+
+fn csg_inside(operation: u32, a: bool, b: bool) -> bool {
+    match operation {
+        CSG_UNION => a || b,
+        CSG_INTERSECTION => a && b,
+        _ => a && !b,
+    }
+}
+
+// Combines two sorted lists of disjoint spans the same way CombineIntervals in shaders/csg.glsl does,
+// sweeping over their boundaries in order and keeping at most CSG_MAX_INTERVALS spans
+pub fn combine_intervals(operation: u32, a: &[Interval], b: &[Interval]) -> Vec<Interval> {
+    // Even indices are the entries of the intervals, odd ones their exits
+    let boundary = |list: &[Interval], index: usize| {
+        let interval = list[index / 2];
+        if index.is_multiple_of(2) { (interval.t_in, interval.n_in) } else { (interval.t_out, interval.n_out) }
+    };
+
+    let mut result: Vec<Interval> = Vec::with_capacity(CSG_MAX_INTERVALS);
+    let (mut inside_a, mut inside_b, mut inside) = (false, false, false);
+    let (mut i, mut j) = (0, 0);
+    let mut t_in = (0.0, vec3(0.0, 0.0, 0.0));
+
+    while i < 2 * a.len() || j < 2 * b.len() {
+        let (t_a, n_a) = if i < 2 * a.len() { boundary(a, i) } else { (f32::MAX, Vector3::zero()) };
+        let (t_b, n_b) = if j < 2 * b.len() { boundary(b, j) } else { (f32::MAX, Vector3::zero()) };
+
+        let (t, n) = if t_a <= t_b {
+            inside_a = !inside_a;
+            i += 1;
+            (t_a, n_a)
+        } else {
+            // The surface of a subtracted solid faces into it
+            inside_b = !inside_b;
+            j += 1;
+            (t_b, if operation == CSG_DIFFERENCE { -n_b } else { n_b })
+        };
+
+        let now_inside = csg_inside(operation, inside_a, inside_b);
+        if result.len() < CSG_MAX_INTERVALS {
+            if now_inside && !inside {
+                t_in = (t, n);
+            } else if !now_inside && inside {
+                result.push(Interval { t_in: t_in.0, t_out: t, n_in: t_in.1, n_out: n });
+            }
+        }
+        inside = now_inside;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(t_in: f32, t_out: f32) -> Interval {
+        Interval { t_in, t_out, n_in: vec3(0.0, 0.0, -1.0), n_out: vec3(0.0, 0.0, 1.0) }
+    }
+
+    fn spans(list: &[Interval]) -> Vec<(f32, f32)> {
+        list.iter().map(|interval| (interval.t_in, interval.t_out)).collect()
+    }
+
+    #[test]
+    fn operations_on_overlapping_spans() {
+        let (a, b) = ([span(1.0, 3.0)], [span(2.0, 4.0)]);
+
+        assert_eq!(spans(&combine_intervals(CSG_UNION, &a, &b)), [(1.0, 4.0)]);
+        assert_eq!(spans(&combine_intervals(CSG_INTERSECTION, &a, &b)), [(2.0, 3.0)]);
+        assert_eq!(spans(&combine_intervals(CSG_DIFFERENCE, &a, &b)), [(1.0, 2.0)]);
+        assert_eq!(spans(&combine_intervals(CSG_DIFFERENCE, &b, &a)), [(3.0, 4.0)]);
+    }
+
+    #[test]
+    fn operations_on_disjoint_and_empty_lists() {
+        let (a, b) = ([span(1.0, 2.0)], [span(3.0, 4.0)]);
+
+        assert_eq!(spans(&combine_intervals(CSG_UNION, &a, &b)), [(1.0, 2.0), (3.0, 4.0)]);
+        assert!(combine_intervals(CSG_INTERSECTION, &a, &b).is_empty());
+        assert_eq!(spans(&combine_intervals(CSG_DIFFERENCE, &a, &b)), [(1.0, 2.0)]);
+        assert_eq!(spans(&combine_intervals(CSG_UNION, &a, &[])), [(1.0, 2.0)]);
+        assert!(combine_intervals(CSG_INTERSECTION, &[], &b).is_empty());
+    }
+
+    #[test]
+    fn subtracted_surfaces_face_into_the_subtracted_solid() {
+        // A hole through the middle of `a` splits it in two
+        let a = [span(1.0, 5.0)];
+        let b = [span(2.0, 3.0)];
+        let result = combine_intervals(CSG_DIFFERENCE, &a, &b);
+
+        assert_eq!(spans(&result), [(1.0, 2.0), (3.0, 5.0)]);
+        assert_eq!(result[0].n_out, vec3(0.0, 0.0, 1.0));
+        assert_eq!(result[0].n_in, vec3(0.0, 0.0, -1.0));
+        assert_eq!(result[1].n_in, vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn spans_past_the_limit_are_dropped() {
+        let a: Vec<Interval> = (0..CSG_MAX_INTERVALS).map(|i| span(i as f32 * 4.0, i as f32 * 4.0 + 1.0)).collect();
+        let b: Vec<Interval> = (0..CSG_MAX_INTERVALS).map(|i| span(i as f32 * 4.0 + 2.0, i as f32 * 4.0 + 3.0)).collect();
+
+        let result = combine_intervals(CSG_UNION, &a, &b);
+        assert_eq!(result.len(), CSG_MAX_INTERVALS);
+        assert_eq!(spans(&result), [(0.0, 1.0), (2.0, 3.0), (4.0, 5.0), (6.0, 7.0)]);
+    }
+
+    #[test]
+    fn nodes_are_compiled_in_postfix_order() {
+        let sphere = |x: f32| CsgNode::Shape(Shape::Sphere { center: vec3(x, 0.0, 0.0), radius: 1.0 });
+        let object = CsgObject::new(CsgNode::difference(CsgNode::union(sphere(0.0), sphere(1.0)), sphere(2.0)), 3);
+
+        let (raw, nodes) = object.to_raw(5).unwrap();
+        let kinds: Vec<u32> = nodes.iter().map(|node| node.kind()).collect();
+        let sphere_kind = Primitive::new(Shape::Sphere { center: vec3(0.0, 0.0, 0.0), radius: 1.0 }, 0).to_raw().kind();
+
+        assert_eq!(kinds, [sphere_kind, sphere_kind, CSG_UNION, sphere_kind, CSG_DIFFERENCE]);
+        assert_eq!((raw.first_node, raw.end(), raw.material), (5, 10, 3));
+    }
+}
@@ -0,0 +1,34 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+
+use notify::Watcher;
+
+// Collects the files created or modified below a directory, the events are drained on demand
+pub struct FileWatcher {
+    _watcher: notify::RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+impl FileWatcher {
+    pub fn new(directory: &Path) -> notify::Result<FileWatcher> {
+        let (sender, events) = std::sync::mpsc::channel();
+
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(directory, notify::RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    // Editors tend to write a file several times when saving, every file is reported once
+    pub fn changed_files(&self) -> HashSet<PathBuf> {
+        self.events.try_iter()
+            .filter_map(|event| event.ok())
+            .filter(|event| event.kind.is_create() || event.kind.is_modify())
+            .flat_map(|event| event.paths)
+            .collect()
+    }
+}
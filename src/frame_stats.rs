@@ -0,0 +1,130 @@
+use std::fmt;
+use std::io::Write;
+
+// What one frame cost on the GPU, read back once the fence of the frame is signaled.
+// The timings are zero on devices without timestamp support, see VkPhysicalDevice::supports_timestamps
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    // Frames accumulated since the render target was last reset
+    pub frame: u32,
+    pub width: u32,
+    pub height: u32,
+    pub preview: bool,
+
+    pub clear_ms: f64,
+    pub trace_ms: f64,
+    pub blit_ms: f64,
+    pub gpu_ms: f64,
+
+    pub samples: u64,
+    // Counted by main.comp, the preview traces one ray per sample
+    pub rays: u64,
+}
+
+impl FrameStats {
+    pub const CSV_HEADER: &'static str = "frame,width,height,preview,clear_ms,trace_ms,blit_ms,gpu_ms,samples,rays,samples_per_second,rays_per_second";
+
+    // Over the time spent tracing, not the whole frame
+    pub fn samples_per_second(&self) -> f64 {
+        Self::per_second(self.samples, self.trace_ms)
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        Self::per_second(self.rays, self.trace_ms)
+    }
+
+    fn per_second(count: u64, ms: f64) -> f64 {
+        if ms > 0.0 {
+            count as f64 / (ms / 1000.0)
+        } else {
+            0.0
+        }
+    }
+
+    // Mean of every field, the frame and size are the ones of the last frame
+    pub fn average(stats: &[FrameStats]) -> Option<FrameStats> {
+        let last = stats.last()?;
+        let count = stats.len() as f64;
+        let mean = |field: fn(&FrameStats) -> f64| stats.iter().map(field).sum::<f64>() / count;
+
+        Some(FrameStats {
+            clear_ms: mean(|stats| stats.clear_ms),
+            trace_ms: mean(|stats| stats.trace_ms),
+            blit_ms: mean(|stats| stats.blit_ms),
+            gpu_ms: mean(|stats| stats.gpu_ms),
+            samples: mean(|stats| stats.samples as f64).round() as u64,
+            rays: mean(|stats| stats.rays as f64).round() as u64,
+            ..*last
+        })
+    }
+
+    pub fn csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{:.4},{:.4},{:.4},{:.4},{},{},{:.0},{:.0}",
+            self.frame, self.width, self.height, self.preview,
+            self.clear_ms, self.trace_ms, self.blit_ms, self.gpu_ms,
+            self.samples, self.rays, self.samples_per_second(), self.rays_per_second()
+        )
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}: trace {:.2} ms, blit {:.2} ms, GPU {:.2} ms, {:.1} Msamples/s, {:.1} Mrays/s",
+            self.width, self.height, self.trace_ms, self.blit_ms, self.gpu_ms,
+            self.samples_per_second() / 1e6, self.rays_per_second() / 1e6
+        )
+    }
+}
+
+// One row per frame, see FrameStats::CSV_HEADER
+pub struct CsvStatsWriter {
+    writer: std::io::BufWriter<std::fs::File>,
+}
+
+impl CsvStatsWriter {
+    pub fn create(path: &std::path::Path) -> std::io::Result<Self> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(writer, "{}", FrameStats::CSV_HEADER)?;
+
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, stats: &[FrameStats]) -> std::io::Result<()> {
+        for frame_stats in stats {
+            writeln!(self.writer, "{}", frame_stats.csv_row())?;
+        }
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_are_over_the_trace_time() {
+        let stats = FrameStats { trace_ms: 500.0, gpu_ms: 1000.0, samples: 1_000, rays: 4_000, ..Default::default() };
+
+        assert_eq!(stats.samples_per_second(), 2_000.0);
+        assert_eq!(stats.rays_per_second(), 8_000.0);
+        assert_eq!(FrameStats::default().rays_per_second(), 0.0);
+    }
+
+    #[test]
+    fn average_keeps_the_last_frame() {
+        let stats = [
+            FrameStats { frame: 1, trace_ms: 1.0, samples: 10, ..Default::default() },
+            FrameStats { frame: 2, trace_ms: 3.0, samples: 30, ..Default::default() },
+        ];
+        let average = FrameStats::average(&stats).unwrap();
+
+        assert_eq!(average.frame, 2);
+        assert_eq!(average.trace_ms, 2.0);
+        assert_eq!(average.samples, 20);
+        assert!(FrameStats::average(&[]).is_none());
+        assert_eq!(average.csv_row().split(',').count(), FrameStats::CSV_HEADER.split(',').count());
+    }
+}
@@ -0,0 +1,225 @@
+// Structs shared with the shaders are declared once with `gpu_struct!`, which defines the
+// #[repr(C)] Rust struct and records the GLSL declaration of every field next to it.
+// shaders/scene.glsl is generated from these, see `scene_glsl`. The tests check that the
+// Rust padding follows the std430 rules and that the generated file is up to date.
+
+use crate::{
+    bvh::{BvhNode, BVH_MAX_DEPTH},
+    camera::CameraRaw,
+    csg::*,
+    material::*,
+    medium::MediumRaw,
+    primitive::PrimitiveRaw,
+    sampler::*,
+    scene::*,
+    sdf::*,
+};
+
+pub trait GpuStruct {
+    const GLSL_NAME: &'static str;
+
+    // GLSL declarations of the fields along with their offsets in the Rust struct
+    fn fields() -> Vec<(&'static str, usize)>;
+}
+
+macro_rules! gpu_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident as $glsl_name:literal {
+            $($field_vis:vis $field:ident : $ty:ty => $glsl:literal),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        $vis struct $name {
+            $($field_vis $field: $ty),*
+        }
+
+        impl $crate::layout::GpuStruct for $name {
+            const GLSL_NAME: &'static str = $glsl_name;
+
+            // std's offset_of!, memoffset builds an uninitialized value on the stack which Scene doesn't fit on
+            fn fields() -> Vec<(&'static str, usize)> {
+                vec![$(($glsl, std::mem::offset_of!($name, $field))),*]
+            }
+        }
+    };
+}
+
+// Constants the layout depends on, plus the sentinels and limits the shaders have to agree on
+fn defines() -> Vec<(&'static str, String)> {
+    vec![
+        ("MAX_MESHES", MAX_MESHES.to_string()),
+        ("MAX_VERTICES", MAX_VERTICES.to_string()),
+        ("MAX_INDICES", MAX_INDICES.to_string()),
+        ("MAX_BVH_NODES", MAX_BVH_NODES.to_string()),
+        ("MAX_MATERIALS", MAX_MATERIALS.to_string()),
+        ("MAX_SPHERES", MAX_SPHERES.to_string()),
+        ("MAX_MEDIA", MAX_MEDIA.to_string()),
+        ("MAX_PRIMITIVES", MAX_PRIMITIVES.to_string()),
+        ("MAX_CSG_NODES", MAX_CSG_NODES.to_string()),
+        ("MAX_CSG_OBJECTS", MAX_CSG_OBJECTS.to_string()),
+        ("MAX_SDF_INSTRUCTIONS", MAX_SDF_INSTRUCTIONS.to_string()),
+        ("MAX_SDF_OBJECTS", MAX_SDF_OBJECTS.to_string()),
+        ("MAX_OPACITY_MASKS", MAX_OPACITY_MASKS.to_string()),
+        ("MAX_OPACITY_TEXELS", MAX_OPACITY_TEXELS.to_string()),
+        ("CSG_MAX_DEPTH", CSG_MAX_DEPTH.to_string()),
+        ("CSG_MAX_INTERVALS", CSG_MAX_INTERVALS.to_string()),
+        ("CSG_UNION", CSG_UNION.to_string()),
+        ("CSG_INTERSECTION", CSG_INTERSECTION.to_string()),
+        ("CSG_DIFFERENCE", CSG_DIFFERENCE.to_string()),
+        ("SDF_MAX_DEPTH", SDF_MAX_DEPTH.to_string()),
+        ("SDF_MAX_NESTING", SDF_MAX_NESTING.to_string()),
+        ("SDF_SPHERE", SDF_SPHERE.to_string()),
+        ("SDF_BOX", SDF_BOX.to_string()),
+        ("SDF_TORUS", SDF_TORUS.to_string()),
+        ("SDF_UNION", SDF_UNION.to_string()),
+        ("SDF_REPEAT_BEGIN", SDF_REPEAT_BEGIN.to_string()),
+        ("SDF_REPEAT_END", SDF_REPEAT_END.to_string()),
+        ("SDF_DISPLACE", SDF_DISPLACE.to_string()),
+        ("BVH_MAX_DEPTH", BVH_MAX_DEPTH.to_string()),
+        ("BLUE_NOISE_SIZE", BLUE_NOISE_SIZE.to_string()),
+        ("SAMPLES_PER_PIXEL", SAMPLES_PER_PIXEL.to_string()),
+        ("SAMPLER_RANDOM", SAMPLER_RANDOM.to_string()),
+        ("SAMPLER_SOBOL", SAMPLER_SOBOL.to_string()),
+        ("SAMPLER_BLUE_NOISE", SAMPLER_BLUE_NOISE.to_string()),
+        ("HASH_XOR", format!("{}u", HASH_XOR)),
+        ("HASH_MULTIPLIER", format!("{}u", HASH_MULTIPLIER)),
+        ("LAINE_KARRAS_MULTIPLIER_0", format!("{:#x}u", LAINE_KARRAS_MULTIPLIERS[0])),
+        ("LAINE_KARRAS_MULTIPLIER_1", format!("{:#x}u", LAINE_KARRAS_MULTIPLIERS[1])),
+        ("LAINE_KARRAS_MULTIPLIER_2", format!("{:#x}u", LAINE_KARRAS_MULTIPLIERS[2])),
+        ("LAINE_KARRAS_MULTIPLIER_3", format!("{:#x}u", LAINE_KARRAS_MULTIPLIERS[3])),
+        ("SOBOL_DIMENSION_SEED", format!("{:#x}u", SOBOL_DIMENSION_SEED)),
+        ("FORK_SEED", format!("{:#x}u", FORK_SEED)),
+        ("BLUE_NOISE_STEP", format!("{:#x}u", BLUE_NOISE_STEP)),
+        ("NO_MEDIUM", format!("{:#X}u", NO_MEDIUM)),
+        ("NO_OPACITY_MASK", format!("{:#X}u", NO_OPACITY_MASK)),
+    ]
+}
+
+fn glsl_fields<T: GpuStruct>() -> String {
+    T::fields().iter().map(|(field, _)| format!("    {};\n", field)).collect()
+}
+
+fn glsl_struct<T: GpuStruct>() -> String {
+    format!("struct {} {{\n{}}};\n\n", T::GLSL_NAME, glsl_fields::<T>())
+}
+
+pub fn scene_glsl() -> String {
+    let mut glsl = String::from("// Generated from the gpu_struct! declarations in src, don't edit by hand.\n");
+    glsl += "// Run the tests with OXITRACE_UPDATE_LAYOUT=1 to regenerate it.\n\n";
+
+    for (name, value) in defines() {
+        glsl += &format!("#define {} {}\n", name, value);
+    }
+    glsl += "\n";
+
+    glsl += &glsl_struct::<Vertex>();
+    glsl += &glsl_struct::<Mesh>();
+    glsl += &glsl_struct::<BvhNode>();
+    glsl += &glsl_struct::<CameraRaw>();
+    glsl += &glsl_struct::<MaterialRaw>();
+    glsl += &glsl_struct::<Sphere>();
+    glsl += &glsl_struct::<MediumRaw>();
+    glsl += &glsl_struct::<PrimitiveRaw>();
+    glsl += &glsl_struct::<CsgObjectRaw>();
+    glsl += &glsl_struct::<SdfInstructionRaw>();
+    glsl += &glsl_struct::<SdfObjectRaw>();
+
+    glsl += &format!("layout(set = 0, std430, binding = 1) buffer {} {{\n{}}};\n\n", Scene::GLSL_NAME, glsl_fields::<Scene>());
+    glsl += "layout(set = 0, binding = 2) uniform CameraBuffer {\n    Camera camera;\n};\n";
+
+    glsl
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn round_up(value: usize, alignment: usize) -> usize {
+        value.div_ceil(alignment) * alignment
+    }
+
+    // Array sizes are either numbers, defines or one of those divided or multiplied by a number
+    fn array_length(expression: &str) -> usize {
+        let defines: HashMap<&str, String> = defines().into_iter().collect();
+        let value = |token: &str| token.trim().parse::<usize>().unwrap_or_else(|_| defines[token.trim()].parse().unwrap());
+
+        if let Some((a, b)) = expression.split_once('/') {
+            value(a) / value(b)
+        } else if let Some((a, b)) = expression.split_once('*') {
+            value(a) * value(b)
+        } else {
+            value(expression)
+        }
+    }
+
+    // Base alignment and size of a GLSL type under std430
+    fn std430(glsl_type: &str, structs: &HashMap<&str, (usize, usize)>) -> (usize, usize) {
+        match glsl_type {
+            "uint" | "int" | "float" | "bool" => (4, 4),
+            "vec2" | "uvec2" | "ivec2" => (8, 8),
+            "vec3" | "uvec3" | "ivec3" => (16, 12),
+            "vec4" | "uvec4" | "ivec4" => (16, 16),
+            "mat4" => (16, 64),
+            name => *structs.get(name).unwrap_or_else(|| panic!("Unknown GLSL type {}", name)),
+        }
+    }
+
+    // Checks every field offset against std430 and returns the alignment and size of the struct
+    fn check_layout<T: GpuStruct>(structs: &HashMap<&str, (usize, usize)>) -> (usize, usize) {
+        let mut offset = 0;
+        let mut alignment = 4;
+
+        for (field, rust_offset) in T::fields() {
+            let (glsl_type, declarator) = field.split_once(' ').unwrap();
+            let (align, size) = std430(glsl_type, structs);
+
+            let size = match declarator.split_once('[') {
+                Some((_, length)) => round_up(size, align) * array_length(length.trim_end_matches(']')),
+                None => size,
+            };
+
+            offset = round_up(offset, align);
+            assert_eq!(offset, rust_offset, "{}: '{}' is at {} in Rust but at {} in GLSL", T::GLSL_NAME, field, rust_offset, offset);
+
+            offset += size;
+            alignment = alignment.max(align);
+        }
+
+        (alignment, round_up(offset, alignment))
+    }
+
+    #[test]
+    fn rust_layout_matches_std430() {
+        let mut structs = HashMap::new();
+
+        macro_rules! check {
+            ($($ty:ty),*) => {$(
+                let (alignment, size) = check_layout::<$ty>(&structs);
+                assert_eq!(size, std::mem::size_of::<$ty>(), "{} is {} bytes in GLSL but {} in Rust", <$ty>::GLSL_NAME, size, std::mem::size_of::<$ty>());
+                structs.insert(<$ty>::GLSL_NAME, (alignment, size));
+            )*};
+        }
+
+        check!(Vertex, Mesh, BvhNode, CameraRaw, MaterialRaw, Sphere, MediumRaw, PrimitiveRaw, CsgObjectRaw, SdfInstructionRaw, SdfObjectRaw);
+
+        // Padding at the end of the buffer doesn't matter
+        let (_, size) = check_layout::<Scene>(&structs);
+        assert!(size <= std::mem::size_of::<Scene>());
+    }
+
+    #[test]
+    fn generated_glsl_is_up_to_date() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/scene.glsl");
+        let generated = scene_glsl();
+
+        if std::env::var("OXITRACE_UPDATE_LAYOUT").is_ok() {
+            std::fs::write(path, &generated).unwrap();
+        }
+
+        let current = std::fs::read_to_string(path).unwrap_or_default();
+        assert!(current == generated, "shaders/scene.glsl is out of date, run the tests with OXITRACE_UPDATE_LAYOUT=1");
+    }
+}
@@ -0,0 +1,42 @@
+// OxiTrace as a library: build a Scene, point a Camera at it and render it with a Renderer,
+// either into a window or headless into an image. The viewer in main.rs is built on top of this.
+//
+//     let mut renderer = Renderer::headless(&DeviceSelector::Best, &ValidationSettings::default(), 640, 480)?;
+//     renderer.bind_scene(&scene)?;
+//     let image = renderer.render_to_image(&camera, 16)?;
+
+#[macro_use]
+pub mod layout;
+mod utilities;
+mod shader_compiler;
+
+pub mod vk;
+pub mod camera;
+pub mod material;
+pub mod renderer;
+pub mod scene;
+pub mod obj_loader;
+pub mod sampler;
+pub mod medium;
+pub mod primitive;
+pub mod bvh;
+pub mod csg;
+pub mod sdf;
+pub mod shaders;
+pub mod shader_watcher;
+pub mod file_watcher;
+pub mod frame_stats;
+pub mod scenes;
+pub mod bench;
+
+pub use camera::Camera;
+pub use frame_stats::FrameStats;
+pub use renderer::{Renderer, UpscaleFilter};
+pub use sampler::SamplerType;
+pub use scene::Scene;
+pub use scenes::BuiltinScene;
+pub use vk::{
+    error::{VkError, VkResult},
+    physical_device::DeviceSelector,
+    debug_messenger::ValidationSettings,
+};
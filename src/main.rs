@@ -1,27 +1,45 @@
 use cgmath::{Zero, InnerSpace};
 
-use renderer::Renderer;
-use scene::*;
 use winit::dpi::{PhysicalPosition};
 use winit::event::{Event, VirtualKeyCode, ElementState, KeyboardInput, WindowEvent, MouseScrollDelta};
 use winit::event_loop::{EventLoop, ControlFlow};
 
-mod vk;
-mod utilities;
-mod camera;
-mod material;
-mod renderer;
-mod scene;
-mod obj_loader;
+use oxitrace::{
+    camera::*,
+    scene::*,
+    scenes::{self, BuiltinScene},
+    bench::{self, BenchSettings},
+    obj_loader,
+    renderer::Renderer,
+    shader_watcher::ShaderWatcher,
+    file_watcher::FileWatcher,
+    frame_stats::{FrameStats, CsvStatsWriter},
+    vk::context::VkContext,
+    DeviceSelector,
+    ValidationSettings,
+    VkResult,
+};
 
-use camera::*;
-use material::*;
+const MODEL_PATH: &str = "res/model.obj";
+const STATS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
-use winit::window::WindowButtons;
+// Rebuilt whenever the model changes on disk
+fn load_scene() -> VkResult<Box<Scene>> {
+    let (vertices, indices) = obj_loader::load_from_file(MODEL_PATH)?;
+
+    scenes::spheres(vertices, indices)
+}
 
 pub struct OxiTrace {
     camera: Camera,
     renderer: Renderer,
+    shader_watcher: Option<ShaderWatcher>,
+    scene_watcher: Option<FileWatcher>,
+
+    stats_csv: Option<CsvStatsWriter>,
+    // Averaged into the window title every STATS_INTERVAL
+    recent_stats: Vec<FrameStats>,
+    last_stats_report: std::time::Instant,
 
     scroll_delta: f32,
     mouse_delta: cgmath::Vector2<f32>,
@@ -29,85 +47,47 @@ pub struct OxiTrace {
 }
 
 impl OxiTrace {
-    pub fn new(window: &winit::window::Window) -> OxiTrace {
-        let mut renderer = Renderer::new(window);
-
-        let camera = Camera::new(
-            cgmath::vec3(2.0, 0.5, 2.0),
-            15.0,
-            45.0,
-            cgmath::vec2(
-                renderer.swapchain.extent.width as f32,
-                renderer.swapchain.extent.height as f32
-            ),
-            80.0,
-            1.1
-        );
+    pub fn new(
+        window: &winit::window::Window,
+        device_selector: &DeviceSelector,
+        validation: &ValidationSettings,
+        stats_csv: Option<CsvStatsWriter>
+    ) -> VkResult<OxiTrace> {
+        let mut renderer = Renderer::new(window, device_selector, validation)?;
+
+        let camera = BuiltinScene::Spheres.camera(renderer.output_extent().width, renderer.output_extent().height);
+
+        renderer.bind_scene(&*load_scene()?)?;
 
-        let spheres = vec![
-            Sphere::new(cgmath::vec3(0.0, -100.5, 0.0), 100.0, 0),
-            Sphere::new(cgmath::vec3(50.0, 40.0, 50.0), 20.0, 5),
-            Sphere::new(cgmath::vec3(0.0, 0.0, 0.0), 0.5, 1),
-            Sphere::new(cgmath::vec3(-1.0, 0.0, 0.0), 0.5, 2),
-            Sphere::new(cgmath::vec3(1.0, 0.0, 0.0), 0.5, 3),
-        ];
-
-        let materials = vec![
-            Material::Lambertian(Lambertian {
-                color: cgmath::vec3(0.7, 0.7, 0.7) 
-            }),
-            Material::Lambertian(Lambertian {
-                color: cgmath::vec3(0.9, 0.08, 0.1)
-            }),
-            Material::Metal(Metal { 
-                color: cgmath::vec3(0.8, 0.8, 0.8),
-                fuzz: 0.3
-            }),
-            Material::Metal(Metal {
-                color: cgmath::vec3(0.8, 0.6, 0.2),
-                fuzz: 0.95
-            }),
-            Material::Metal(Metal {
-                color: cgmath::vec3(1.0, 1.0, 1.0),
-                fuzz: 0.0
-            }),
-            Material::Emmisive(Emmisive{
-                color: cgmath::vec3(1.0, 0.5, 0.2),
-                intensity: 80.0,
-            }),
-        ];
-
-        let offset = cgmath::vec3(1.5, -0.2, -3.0);
-
-        let (vertices, indices) = obj_loader::load_from_file("res/model.obj").expect("Failed to load model!");
-
-        //let vertices = vec![
-        //    Vertex::new(cgmath::vec3(-1.0, -0.5, 0.0) + offset, cgmath::vec3(0.0, 0.0, 1.0)),
-        //    Vertex::new(cgmath::vec3(-1.0,  1.0, 0.0) + offset, cgmath::vec3(0.0, 0.0, 1.0)),
-        //    Vertex::new(cgmath::vec3( 1.0,  1.0, 0.0) + offset, cgmath::vec3(0.0, 0.0, 1.0)),
-        //    Vertex::new(cgmath::vec3( 1.0, -0.5, 0.0) + offset, cgmath::vec3(0.0, 0.0, 1.0)),
-        //];
-        //let indices = vec![
-        //    0,1,2,
-        //    0,2,3,
-        //];
-        
-        let meshes = vec![
-            Mesh::new(vertices.len() as u32, indices.len() as u32, 0, 4)
-        ];
-        
-        let scene = Box::new(Scene::new(materials, spheres, vertices, indices, meshes));
-
-        renderer.bind_scene(*scene);
-
-        Self {
+        let shader_watcher = match ShaderWatcher::new() {
+            Ok(shader_watcher) => Some(shader_watcher),
+            Err(error) => {
+                log::warn!("Shader hot reload disabled: {}", error);
+                None
+            }
+        };
+        let scene_watcher = match FileWatcher::new(std::path::Path::new(MODEL_PATH).parent().unwrap()) {
+            Ok(scene_watcher) => Some(scene_watcher),
+            Err(error) => {
+                log::warn!("Scene hot reload disabled: {}", error);
+                None
+            }
+        };
+
+        Ok(Self {
             camera,
             renderer,
+            shader_watcher,
+            scene_watcher,
+
+            stats_csv,
+            recent_stats: Vec::new(),
+            last_stats_report: std::time::Instant::now(),
 
             scroll_delta: 0.0,
             mouse_delta: cgmath::vec2(0.0, 0.0),
             movement_delta: cgmath::vec3(0.0, 0.0, 0.0),
-        }
+        })
     }
 
     fn update(&mut self, delta_time: f32) {
@@ -121,13 +101,73 @@ impl OxiTrace {
 
         self.mouse_delta = cgmath::Vector2::zero();
         self.scroll_delta = 0.0;
+
+        if let Some(shader_watcher) = &self.shader_watcher {
+            for (shader, spirv) in shader_watcher.poll() {
+                let reloaded = spirv.and_then(
+                    |spirv| self.renderer.reload_shader(shader, &spirv).map_err(|error| error.to_string())
+                );
+                match reloaded {
+                    Ok(_) => log::info!("Reloaded the {} shader", shader.name()),
+                    Err(error) => log::warn!("Keeping the previous {} shader:\n{}", shader.name(), error),
+                }
+            }
+        }
+
+        let scene_changed = self.scene_watcher.as_ref().is_some_and(|scene_watcher| {
+            scene_watcher.changed_files().iter().any(|path| path.ends_with(MODEL_PATH))
+        });
+        if scene_changed {
+            let reloaded = load_scene().and_then(|scene| self.renderer.bind_scene(&scene));
+            match reloaded {
+                Ok(_) => {
+                    self.renderer.reset_render_target();
+                    log::info!("Reloaded the scene");
+                }
+                Err(error) => log::warn!("Keeping the previous scene: {}", error),
+            }
+        }
     }
-    fn render(&mut self) {  
-        self.renderer.render(&self.camera);
+    fn render(&mut self) -> VkResult<()> {  
+        self.renderer.render(&self.camera)
+    }
+    // Shown in the window title since there's no UI to draw an overlay with
+    fn report_stats(&mut self, window: &winit::window::Window) {
+        let stats = self.renderer.take_frame_stats();
+
+        if let Some(stats_csv) = &mut self.stats_csv {
+            if let Err(error) = stats_csv.write(&stats) {
+                log::warn!("Stopped writing frame statistics: {}", error);
+                self.stats_csv = None;
+            }
+        }
+
+        self.recent_stats.extend(stats);
+        if self.last_stats_report.elapsed() < STATS_INTERVAL {
+            return;
+        }
+
+        if let Some(average) = FrameStats::average(&self.recent_stats) {
+            log::info!("{}", average);
+            window.set_title(&format!(
+                "OxiTrace - {:.2} ms, {:.1} Msamples/s, {:.1} Mrays/s",
+                average.gpu_ms,
+                average.samples_per_second() / 1e6,
+                average.rays_per_second() / 1e6
+            ));
+        }
+
+        self.recent_stats.clear();
+        self.last_stats_report = std::time::Instant::now();
+    }
+    // A minimized window has a size of zero, the renderer skips frames until it's restored
+    fn resize(&mut self, width: u32, height: u32) {
+        self.camera.size = cgmath::vec2(width as f32, height as f32);
+        self.renderer.resize(width, height);
     }
    
-    fn wait_device_idle(&self) {
-        self.renderer.wait_device_idle();
+    fn wait_device_idle(&self) -> VkResult<()> {
+        self.renderer.wait_device_idle()
     }
 
     fn process_keyboard_input(&mut self, key: VirtualKeyCode, state: ElementState, control_flow: &mut ControlFlow) {
@@ -141,6 +181,30 @@ impl OxiTrace {
                     self.renderer.reset_render_target();
                 }
             }
+            VirtualKeyCode::N => {
+                if state == ElementState::Released {
+                    self.renderer.sampler_type = self.renderer.sampler_type.next();
+                    self.renderer.reset_render_target();
+
+                    log::info!("Sampler: {}", self.renderer.sampler_type.name());
+                }
+            }
+            VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+                if state == ElementState::Released {
+                    let step = if key == VirtualKeyCode::Minus { -0.25 } else { 0.25 };
+                    match self.renderer.set_render_scale(self.renderer.render_scale() + step) {
+                        Ok(_) => log::info!("Render scale: {}", self.renderer.render_scale()),
+                        Err(error) => log::warn!("Keeping the render scale at {}: {}", self.renderer.render_scale(), error),
+                    }
+                }
+            }
+            VirtualKeyCode::F => {
+                if state == ElementState::Released {
+                    self.renderer.upscale_filter = self.renderer.upscale_filter.next();
+
+                    log::info!("Upscale filter: {}", self.renderer.upscale_filter.name());
+                }
+            }
             VirtualKeyCode::W => {
                 self.movement_delta.z = if state == ElementState::Pressed {
                     1.0
@@ -206,8 +270,17 @@ impl OxiTrace {
                         WindowEvent::CloseRequested => {
                             *control_flow = ControlFlow::Exit
                         }
+                        WindowEvent::Resized(size) => {
+                            self.resize(size.width, size.height);
+                        }
+                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                            self.resize(new_inner_size.width, new_inner_size.height);
+                        }
                         WindowEvent::KeyboardInput { input, .. } => {
                             match input {
+                                KeyboardInput { virtual_keycode: Some(VirtualKeyCode::F11), state: ElementState::Released, .. } => {
+                                    toggle_fullscreen(&window);
+                                }
                                 KeyboardInput { virtual_keycode, state, .. } => {
                                     if let Some(key) = virtual_keycode {
                                         self.process_keyboard_input(key, state, control_flow);
@@ -240,10 +313,16 @@ impl OxiTrace {
                     window.request_redraw();
                 },
                 Event::RedrawRequested(_window_id) => {
-                    self.render();
+                    if let Err(error) = self.render() {
+                        eprintln!("{}", error);
+                        *control_flow = ControlFlow::Exit;
+                    }
+                    self.report_stats(&window);
                 },
                 Event::LoopDestroyed => {
-                    self.wait_device_idle();
+                    if let Err(error) = self.wait_device_idle() {
+                        eprintln!("{}", error);
+                    }
                 },
                 _ => (),
             }
@@ -251,23 +330,147 @@ impl OxiTrace {
     }
 }
 
+fn toggle_fullscreen(window: &winit::window::Window) {
+    if window.fullscreen().is_some() {
+        window.set_fullscreen(None);
+    } else {
+        window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(window.current_monitor())));
+    }
+}
+
+// Errors come from either the renderer or writing the report
+fn run_bench(device_selector: &DeviceSelector, validation: &ValidationSettings, settings: &BenchSettings, output: &str) -> Result<(), String> {
+    let report = bench::run(device_selector, validation, settings).map_err(|error| error.to_string())?;
+
+    for result in report.results.iter() {
+        println!(
+            "{:<12} {:>6} triangles {:>10.1} ms {:>8.1} Mrays/s  {}",
+            result.scene, result.triangles, result.wall_ms, result.rays_per_second / 1e6, result.image_hash
+        );
+    }
+
+    let json = serde_json::to_string_pretty(&report).map_err(|error| error.to_string())?;
+    std::fs::write(output, json).map_err(|error| format!("Failed to write {}: {}", output, error))?;
+
+    println!("Wrote {} on {}", output, report.device);
+    Ok(())
+}
+
+// Bad arguments end the program with a message instead of a panic
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
 fn main() {
+    // Validation messages are logged under the "vulkan" target, RUST_LOG overrides this
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let mut device_selector = DeviceSelector::Best;
+    let mut validation = ValidationSettings::default();
+    let mut list_devices = false;
+    let mut stats_csv = None;
+
+    let mut args = std::env::args().skip(1).peekable();
+    // `oxitrace bench` renders the built-in scenes headless instead of opening the viewer
+    let is_bench = args.next_if_eq("bench").is_some();
+    let mut bench_settings = BenchSettings::default();
+    let mut bench_output = String::from("bench.json");
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list-devices" => list_devices = true,
+            "--device" => {
+                let value = args.next().unwrap_or_else(|| usage_error("--device needs an index or a name, see --list-devices"));
+                device_selector = DeviceSelector::parse(&value);
+            }
+            "--validation" => validation.enabled = true,
+            "--no-validation" => validation.enabled = false,
+            "--validation-level" => {
+                validation.level = args.next().and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage_error("--validation-level needs error, warn, info or debug"));
+            }
+            "--panic-on-validation-error" => {
+                validation.enabled = true;
+                validation.panic_on_error = true;
+            }
+            "--stats-csv" => {
+                let path = args.next().unwrap_or_else(|| usage_error("--stats-csv needs a file to write the frame statistics to"));
+                match CsvStatsWriter::create(std::path::Path::new(&path)) {
+                    Ok(writer) => stats_csv = Some(writer),
+                    Err(error) => {
+                        eprintln!("Failed to create {}: {}", path, error);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--output" if is_bench => bench_output = args.next().unwrap_or_else(|| usage_error("--output needs a file to write the report to")),
+            "--size" if is_bench => {
+                let size = args.next().and_then(|value| {
+                    let (width, height) = value.split_once('x')?;
+                    Some((width.parse().ok()?, height.parse().ok()?))
+                });
+                (bench_settings.width, bench_settings.height) = size.unwrap_or_else(|| usage_error("--size needs <width>x<height>"));
+            }
+            "--frames" if is_bench => {
+                bench_settings.frames = args.next().and_then(|value| value.parse().ok()).unwrap_or_else(|| usage_error("--frames needs a number"));
+            }
+            "--scene" if is_bench => {
+                let names: Vec<&str> = BuiltinScene::ALL.iter().map(|scene| scene.name()).collect();
+                let scene = args.next().and_then(|value| BuiltinScene::parse(&value))
+                    .unwrap_or_else(|| usage_error(&format!("--scene needs one of {}", names.join(", "))));
+
+                // The first --scene replaces the default of all scenes
+                if bench_settings.scenes == BuiltinScene::ALL {
+                    bench_settings.scenes.clear();
+                }
+                bench_settings.scenes.push(scene);
+            }
+            _ if is_bench => usage_error(&format!("Unknown argument {}, expected --device <index|name>, --output <path>, --size <width>x<height>, --frames <count>, --scene <name> or one of the validation flags", arg)),
+            _ => usage_error(&format!("Unknown argument {}, expected bench, --list-devices, --device <index|name>, --validation, --no-validation, --validation-level <level>, --panic-on-validation-error or --stats-csv <path>", arg)),
+        }
+    }
+
+    if is_bench {
+        if let Err(error) = run_bench(&device_selector, &validation, &bench_settings, &bench_output) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new();
 
+    // Starts windowed, F11 switches to borderless fullscreen and back
     let window = winit::window::WindowBuilder::new()
         .with_title("OxiTrace")
         .with_inner_size(winit::dpi::LogicalSize::new(1200, 800))
-        .with_resizable(false)
-        .with_enabled_buttons(WindowButtons::MINIMIZE | WindowButtons::CLOSE)
+        .with_resizable(true)
+        .with_visible(!list_devices)
         .build(&event_loop)
         .expect("Failed to create window.");
 
-    window.set_cursor_visible(false);
+    // Presenting is part of being suitable, so this still needs a (hidden) window
+    if list_devices {
+        match VkContext::list_devices(Some(&window)) {
+            Ok(device_list) => println!("{}", device_list),
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    let monitor = window.primary_monitor().expect("Failed to get the primary monitor!");
-    window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(Some(monitor))));
+    window.set_cursor_visible(false);
 
-    let app = OxiTrace::new(&window);
+    let app = match OxiTrace::new(&window, &device_selector, &validation, stats_csv) {
+        Ok(app) => app,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
 
     app.run(window, event_loop);
 }
\ No newline at end of file
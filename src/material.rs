@@ -1,11 +1,124 @@
+use cgmath::ElementWise;
+
+// Marks materials whose opacity is just the constant
+pub const NO_OPACITY_MASK: u32 = u32::MAX;
+
+gpu_struct! {
+    #[derive(Clone, Debug, Copy)]
+    pub struct MaterialRaw as "Material" {
+        material_type: [u32; 4] => "uint type",
+        color: [f32; 3] => "vec3 color",
+        fuzz: [f32; 1] => "float fuzz",
+        emission: [f32; 1] => "float emmision",
+        ior: [f32; 1] => "float ior",
+        dispersion: [u32; 2] => "uint dispersion",
+        absorption: [f32; 4] => "vec3 absorption",
+        dispersion_b: [f32; 4] => "vec4 dispersionB",
+        dispersion_c: [f32; 4] => "vec4 dispersionC",
+        scattering: [f32; 3] => "vec3 scattering",
+        anisotropy: [f32; 1] => "float anisotropy",
+        opacity: [f32; 1] => "float opacity",
+        opacity_mask: [u32; 1] => "uint opacityMask",
+        double_sided: [u32; 2] => "uint doubleSided",
+    }
+}
+
+impl Default for MaterialRaw {
+    fn default() -> Self {
+        MaterialRaw {
+            material_type: [0; 4],
+            color: [0.0; 3],
+            fuzz: [0.0; 1],
+            emission: [0.0; 1],
+            ior: [0.0; 1],
+            dispersion: [0; 2],
+            absorption: [0.0; 4],
+            dispersion_b: [0.0; 4],
+            dispersion_c: [0.0; 4],
+            scattering: [0.0; 3],
+            anisotropy: [0.0; 1],
+            opacity: [1.0; 1],
+            opacity_mask: [NO_OPACITY_MASK; 1],
+            double_sided: [0; 2],
+        }
+    }
+}
+
+impl MaterialRaw {
+    pub fn set_opacity(&mut self, opacity: Opacity) {
+        (self.opacity, self.opacity_mask) = match opacity {
+            Opacity::Constant(opacity) => ([opacity; 1], [NO_OPACITY_MASK; 1]),
+            Opacity::Mask(mask) => ([1.0; 1], [mask; 1]),
+        };
+    }
+
+    // Single sided triangles are invisible from behind, this has no effect on analytic shapes
+    pub fn set_double_sided(&mut self, double_sided: bool) {
+        self.double_sided = [double_sided as u32; 2];
+    }
+
+    // Takes the opacity of `current`, and its double sidedness unless this material needs both sides anyway.
+    // Material::to_raw doesn't know about either, they are set on the Scene.
+    pub fn keep_flags_of(mut self, current: &MaterialRaw) -> MaterialRaw {
+        self.opacity = current.opacity;
+        self.opacity_mask = current.opacity_mask;
+        self.double_sided = [self.double_sided[0] | current.double_sided[0]; 2];
+        self
+    }
+}
+
+// Cutout opacity of triangle hits, anything in between 0 and 1 is stochastically transparent
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum Opacity {
+    Constant(f32),
+    // Index returned by Scene::add_opacity_mask
+    Mask(u32),
+}
+
 #[repr(C)]
 #[derive(Clone, Debug, Copy, Default)]
-pub struct MaterialRaw {
-    material_type: [u32; 4],
-    color: [f32; 3],
-    fuzz: [f32; 1],
-    emission: [f32; 1],
-    ior: [f32; 3],
+pub struct OpacityMaskRaw {
+    first_texel: u32,
+    width: u32,
+    height: u32,
+    padding: u32,
+}
+
+impl OpacityMaskRaw {
+    // Index of the first texel after this mask's
+    pub fn end(&self) -> u32 {
+        self.first_texel + self.width * self.height
+    }
+}
+
+// Single channel 8 bit texture, looked up with the mesh's texture coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpacityMask {
+    pub width: u32,
+    pub height: u32,
+    pub texels: Vec<u8>,
+}
+
+impl OpacityMask {
+    // Takes one channel of an image, usually 3 for the alpha of RGBA images
+    pub fn from_file(path: &str, channel: usize) -> image::ImageResult<OpacityMask> {
+        let image = image::open(path)?.to_rgba8();
+
+        Ok(OpacityMask {
+            width: image.width(),
+            height: image.height(),
+            texels: image.pixels().map(|pixel| pixel.0[channel]).collect(),
+        })
+    }
+
+    pub fn to_raw(&self, first_texel: u32) -> OpacityMaskRaw {
+        OpacityMaskRaw {
+            first_texel,
+            width: self.width,
+            height: self.height,
+            padding: 0,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Copy, PartialEq)]
@@ -20,7 +133,7 @@ impl Lambertian {
             color: self.color.into(),
             fuzz: [0.0; 1],
             emission: [0.0; 1],
-            ior: [0.0; 3],
+            ..Default::default()
         }
     }
 }
@@ -38,25 +151,67 @@ impl Metal {
             color: self.color.into(),
             fuzz: [self.fuzz; 1],
             emission: [0.0; 1],
-            ior: [0.0; 3],
+            ..Default::default()
         }
     }
 }
 
+// Wavelength dependent index of refraction, wavelengths are in micrometers
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum Dispersion {
+    // Constant 'ior' of the dielectric
+    None,
+    // n = a + b / λ²
+    Cauchy { a: f32, b: f32 },
+    // n² = 1 + Σ b[i] * λ² / (λ² - c[i])
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Dispersion {
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039612, 0.23179234, 1.0104695],
+        c: [0.006000699, 0.020017914, 103.56065],
+    };
+    pub const DENSE_FLINT: Dispersion = Dispersion::Sellmeier {
+        b: [1.737597, 0.31374734, 1.898781],
+        c: [0.013188707, 0.062306814, 155.2363],
+    };
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030625, 0.011236, 0.0],
+    };
+}
+
 #[derive(Clone, Debug, Copy, PartialEq)]
 pub struct Dielectric {
     pub color: cgmath::Vector3<f32>,
-    pub ior: f32
+    pub ior: f32,
+    pub dispersion: Dispersion,
+    // Beer-Lambert attenuation per unit of distance travelled inside, zero for clear glass
+    pub absorption: cgmath::Vector3<f32>,
 }
 
 impl Dielectric {
     pub fn to_raw(&self) -> MaterialRaw {
+        let (dispersion, dispersion_b, dispersion_c) = match self.dispersion {
+            Dispersion::None => (0, [0.0; 4], [0.0; 4]),
+            Dispersion::Cauchy { a, b } => (1, [a, b, 0.0, 0.0], [0.0; 4]),
+            Dispersion::Sellmeier { b, c } => (2, [b[0], b[1], b[2], 0.0], [c[0], c[1], c[2], 0.0]),
+        };
+
         MaterialRaw {
             material_type: [2;4],
             color: self.color.into(),
             fuzz: [0.0; 1],
             emission: [0.0; 1],
-            ior: [self.ior; 3],
+            ior: [self.ior; 1],
+            dispersion: [dispersion; 2],
+            absorption: self.absorption.extend(0.0).into(),
+            dispersion_b,
+            dispersion_c,
+            // Rays have to be able to leave closed meshes again
+            double_sided: [1; 2],
+            ..Default::default()
         }
     }
 }
@@ -74,7 +229,57 @@ impl Emmisive {
             color: self.color.into(),
             fuzz: [0.0; 1],
             emission: [self.intensity; 1],
-            ior: [0.0; 3],
+            ..Default::default()
+        }
+    }
+}
+
+// Invisible surface, only useful for marking the boundary of an object's interior medium
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Interface;
+
+impl Interface {
+    pub fn to_raw(&self) -> MaterialRaw {
+        MaterialRaw {
+            material_type: [4;4],
+            double_sided: [1; 2],
+            ..Default::default()
+        }
+    }
+}
+
+// Random walk subsurface scattering behind a smooth dielectric boundary
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Subsurface {
+    // Multiple scattering albedo, the colour the material ends up having
+    pub color: cgmath::Vector3<f32>,
+    // Average distance travelled inside between scattering events, per channel
+    pub mean_free_path: cgmath::Vector3<f32>,
+    pub ior: f32,
+    pub anisotropy: f32,
+}
+
+impl Subsurface {
+    pub fn to_raw(&self) -> MaterialRaw {
+        // Single scattering albedo that gives the requested multiple scattering albedo,
+        // "Practical and Controllable Subsurface Scattering for Production Path Tracing" (Chiang et al. 2016)
+        let single_scattering_albedo = self.color.map(|a| {
+            let a = a.clamp(0.0, 0.999);
+            1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+        });
+
+        let extinction = self.mean_free_path.map(|d| 1.0 / d.max(1e-4));
+        let scattering = single_scattering_albedo.mul_element_wise(extinction);
+
+        MaterialRaw {
+            material_type: [5;4],
+            color: self.color.into(),
+            ior: [self.ior; 1],
+            absorption: (extinction - scattering).extend(0.0).into(),
+            scattering: scattering.into(),
+            anisotropy: [self.anisotropy; 1],
+            double_sided: [1; 2],
+            ..Default::default()
         }
     }
 }
@@ -85,4 +290,45 @@ pub enum Material {
     Dielectric(Dielectric),
     Metal(Metal),
     Emmisive(Emmisive),
-}
\ No newline at end of file
+    Interface(Interface),
+    Subsurface(Subsurface),
+}
+
+impl Material {
+    pub fn to_raw(&self) -> MaterialRaw {
+        match self {
+            Material::Lambertian(material) => material.to_raw(),
+            Material::Metal(material) => material.to_raw(),
+            Material::Dielectric(material) => material.to_raw(),
+            Material::Emmisive(material) => material.to_raw(),
+            Material::Interface(material) => material.to_raw(),
+            Material::Subsurface(material) => material.to_raw(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaced_materials_keep_their_flags() {
+        let mut current = MaterialRaw::default();
+        current.set_opacity(Opacity::Constant(0.25));
+        current.set_double_sided(true);
+
+        let metal = Metal { color: cgmath::vec3(1.0, 1.0, 1.0), fuzz: 0.1 }.to_raw().keep_flags_of(&current);
+        assert_eq!(metal.opacity, [0.25]);
+        assert_eq!(metal.double_sided, [1; 2]);
+        assert_eq!(metal.fuzz, [0.1]);
+
+        // Glass stays double sided even where the previous material wasn't
+        let glass = Dielectric {
+            color: cgmath::vec3(1.0, 1.0, 1.0),
+            ior: 1.5,
+            dispersion: Dispersion::None,
+            absorption: cgmath::vec3(0.0, 0.0, 0.0),
+        };
+        assert_eq!(glass.to_raw().keep_flags_of(&MaterialRaw::default()).double_sided, [1; 2]);
+    }
+}
@@ -0,0 +1,27 @@
+gpu_struct! {
+    #[derive(Clone, Debug, Copy, Default)]
+    pub struct MediumRaw as "Medium" {
+        absorption: [f32; 3] => "vec3 absorption",
+        anisotropy: [f32; 1] => "float anisotropy",
+        scattering: [f32; 4] => "vec3 scattering",
+    }
+}
+
+// Homogeneous participating medium, coefficients are per unit of distance
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Medium {
+    pub absorption: cgmath::Vector3<f32>,
+    pub scattering: cgmath::Vector3<f32>,
+    // Henyey-Greenstein asymmetry, negative values scatter backwards and positive forwards
+    pub anisotropy: f32,
+}
+
+impl Medium {
+    pub fn to_raw(&self) -> MediumRaw {
+        MediumRaw {
+            absorption: self.absorption.into(),
+            anisotropy: [self.anisotropy; 1],
+            scattering: self.scattering.extend(0.0).into(),
+        }
+    }
+}
@@ -1,32 +1,39 @@
-use crate::{scene::Vertex};
+use crate::{scene::{Vertex, MAX_VERTICES, MAX_INDICES}, vk::error::*};
 use cgmath::vec3;
 use tobj::{self};
 
-pub fn load_from_file(path: &str) -> Result<(Vec<Vertex>, Vec<u32>), ()> {
-    let cornell_box = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS);
+// Errors are reported instead of panicking, the file may be reloaded while it's being written
+pub fn load_from_file(path: &str) -> VkResult<(Vec<Vertex>, Vec<u32>)> {
+    let error = |reason: String| VkError::Model { path: path.into(), reason };
 
-    let (models, materials) = cornell_box.expect("Failed to load OBJ file");
+    let (models, _materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS).map_err(|e| error(e.to_string()))?;
 
-    let materials = materials.unwrap_or_default();
-
-    let mesh = &models.first().expect("Model contains no meshes!").mesh;
+    let mesh = &models.first().ok_or_else(|| error("model contains no meshes".to_string()))?.mesh;
 
     if mesh.normals.is_empty() || mesh.texcoords.is_empty() {
-        panic!("Normals and texture coordinates are required!");
+        return Err(error("normals and texture coordinates are required".to_string()));
+    }
+
+    if mesh.positions.len() / 3 > MAX_VERTICES || mesh.indices.len() > MAX_INDICES {
+        return Err(error(format!("{} vertices and {} indices don't fit into the scene ({} and {} at most)",
+            mesh.positions.len() / 3, mesh.indices.len(), MAX_VERTICES, MAX_INDICES)));
     }
 
-    let positions: Vec<[f32; 4]> = mesh.positions.chunks(3).map(|i| [i[0], i[1], i[2], 0.0]).collect();
-    let normals: Vec<[f32; 4]> = mesh.normals.chunks(3).map(|i| [i[0], i[1], i[2], 0.0]).collect();
+    // Texture coordinates go into the w components, see Vertex
+    let positions: Vec<[f32; 4]> = mesh.positions.chunks(3).zip(mesh.texcoords.chunks(2)).map(|(i, uv)| [i[0], i[1], i[2], uv[0]]).collect();
+    let normals: Vec<[f32; 4]> = mesh.normals.chunks(3).zip(mesh.texcoords.chunks(2)).map(|(i, uv)| [i[0], i[1], i[2], uv[1]]).collect();
 
     let mut vertices = Vec::with_capacity(mesh.positions.len());
 
-    assert!(positions.len() == normals.len());
+    if positions.len() != normals.len() {
+        return Err(error("every vertex needs a normal and texture coordinates".to_string()));
+    }
 
     for i in 0..positions.len() {
         vertices.push(Vertex::from_raw(positions[i], normals[i]));
     }
 
-    println!("Vertex count: {}, Index count: {}", vertices.len(), mesh.indices.len());
+    log::info!("Vertex count: {}, Index count: {}", vertices.len(), mesh.indices.len());
 
     Ok((vertices, mesh.indices.clone()))
 }
@@ -0,0 +1,394 @@
+use cgmath::{InnerSpace, Vector3, vec3};
+
+use crate::scene::NO_MEDIUM;
+
+gpu_struct! {
+    #[derive(Clone, Debug, Copy, Default)]
+    pub struct PrimitiveRaw as "Primitive" {
+        kind: u32 => "uint type",
+        material: u32 => "uint material",
+        medium: u32 => "uint medium",
+        padding: u32 => "uint padding",
+        data: [[f32; 4]; 4] => "vec4 data[4]",
+    }
+}
+
+impl PrimitiveRaw {
+    // The shape, or the operation of inner CSG nodes
+    pub fn kind(&self) -> u32 {
+        self.kind
+    }
+
+    // Inner CSG node, the operands come before it in the node list
+    pub fn operation(kind: u32) -> PrimitiveRaw {
+        PrimitiveRaw {
+            kind,
+            ..Default::default()
+        }
+    }
+}
+
+// Analytic shapes, see shaders/primitive.glsl for the GPU side of the intersection code
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum Shape {
+    // Infinite plane, the normal doesn't have to be normalized
+    Plane { point: Vector3<f32>, normal: Vector3<f32> },
+    // Axis aligned box
+    Box { min: Vector3<f32>, max: Vector3<f32> },
+    // Box with its own orthonormal frame, 'axes' are the local x, y and z directions
+    OrientedBox { center: Vector3<f32>, half_extents: Vector3<f32>, axes: [Vector3<f32>; 3] },
+    Disk { center: Vector3<f32>, normal: Vector3<f32>, radius: f32 },
+    // Capped cylinder from 'base' up to 'base + axis', 'axis' also gives the height
+    Cylinder { base: Vector3<f32>, axis: Vector3<f32>, radius: f32 },
+    // Cone with its base disk at 'base' and its apex at 'base + axis'
+    Cone { base: Vector3<f32>, axis: Vector3<f32>, radius: f32 },
+    // Parallelogram spanned by the 'u' and 'v' edges from 'corner'
+    Quad { corner: Vector3<f32>, u: Vector3<f32>, v: Vector3<f32> },
+    // Same as 'scene::Sphere', mostly useful as a CSG operand
+    Sphere { center: Vector3<f32>, radius: f32 },
+}
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Hit {
+    pub t: f32,
+    // Points away from the shape, regardless of which side the ray came from
+    pub normal: Vector3<f32>,
+}
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Primitive {
+    pub shape: Shape,
+    material: u32,
+    medium: u32,
+}
+
+impl Primitive {
+    pub fn new(shape: Shape, material: u32) -> Primitive {
+        Primitive {
+            shape,
+            material,
+            medium: NO_MEDIUM,
+        }
+    }
+
+    pub fn with_medium(mut self, medium: u32) -> Primitive {
+        self.medium = medium;
+        self
+    }
+
+    pub fn to_raw(&self) -> PrimitiveRaw {
+        let (kind, data) = match self.shape {
+            Shape::Plane { point, normal } => (0, [
+                point.extend(0.0).into(),
+                normal.normalize().extend(0.0).into(),
+                [0.0; 4],
+                [0.0; 4],
+            ]),
+            Shape::Box { min, max } => (1, [
+                ((min + max) * 0.5).extend(0.0).into(),
+                vec3(1.0, 0.0, 0.0).extend((max.x - min.x) * 0.5).into(),
+                vec3(0.0, 1.0, 0.0).extend((max.y - min.y) * 0.5).into(),
+                vec3(0.0, 0.0, 1.0).extend((max.z - min.z) * 0.5).into(),
+            ]),
+            Shape::OrientedBox { center, half_extents, axes } => (1, [
+                center.extend(0.0).into(),
+                axes[0].normalize().extend(half_extents.x).into(),
+                axes[1].normalize().extend(half_extents.y).into(),
+                axes[2].normalize().extend(half_extents.z).into(),
+            ]),
+            Shape::Disk { center, normal, radius } => (2, [
+                center.extend(radius).into(),
+                normal.normalize().extend(0.0).into(),
+                [0.0; 4],
+                [0.0; 4],
+            ]),
+            Shape::Cylinder { base, axis, radius } => (3, [
+                base.extend(radius).into(),
+                axis.normalize().extend(axis.magnitude()).into(),
+                [0.0; 4],
+                [0.0; 4],
+            ]),
+            Shape::Cone { base, axis, radius } => (4, [
+                base.extend(radius).into(),
+                axis.normalize().extend(axis.magnitude()).into(),
+                [0.0; 4],
+                [0.0; 4],
+            ]),
+            Shape::Quad { corner, u, v } => (5, [
+                corner.extend(0.0).into(),
+                u.extend(0.0).into(),
+                v.extend(0.0).into(),
+                [0.0; 4],
+            ]),
+            Shape::Sphere { center, radius } => (6, [
+                center.extend(radius).into(),
+                [0.0; 4],
+                [0.0; 4],
+                [0.0; 4],
+            ]),
+        };
+
+        PrimitiveRaw {
+            kind,
+            material: self.material,
+            medium: self.medium,
+            padding: 0,
+            data,
+        }
+    }
+}
+
+// Keeps the closest hit within (t_min, t_max), the same way the shaders do
+fn consider(closest: &mut Option<Hit>, t: f32, normal: Vector3<f32>, t_min: f32, t_max: f32) {
+    let best = closest.map_or(t_max, |hit| hit.t);
+    if t > t_min && t < best {
+        *closest = Some(Hit { t, normal });
+    }
+}
+
+fn intersect_disk(center: Vector3<f32>, normal: Vector3<f32>, radius: f32, origin: Vector3<f32>, direction: Vector3<f32>) -> Option<f32> {
+    let denom = normal.dot(direction);
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+
+    let t = (center - origin).dot(normal) / denom;
+    ((origin + direction * t - center).magnitude2() <= radius * radius).then_some(t)
+}
+
+impl Shape {
+    // CPU reference of the intersection routines in shaders/primitive.glsl
+    pub fn intersect(&self, origin: Vector3<f32>, direction: Vector3<f32>, t_min: f32, t_max: f32) -> Option<Hit> {
+        let raw = Primitive::new(*self, 0).to_raw();
+        let data: [Vector3<f32>; 4] = raw.data.map(|d| vec3(d[0], d[1], d[2]));
+        let w = raw.data.map(|d| d[3]);
+
+        let mut closest = None;
+
+        match self {
+            Shape::Plane { .. } => {
+                let normal = data[1];
+                let denom = normal.dot(direction);
+                if denom.abs() > 1e-8 {
+                    consider(&mut closest, (data[0] - origin).dot(normal) / denom, normal, t_min, t_max);
+                }
+            }
+            Shape::Box { .. } | Shape::OrientedBox { .. } => {
+                // Slab test in the frame of the box
+                let axes = [data[1], data[2], data[3]];
+                let o = origin - data[0];
+                let local_origin = vec3(o.dot(axes[0]), o.dot(axes[1]), o.dot(axes[2]));
+                let local_direction = vec3(direction.dot(axes[0]), direction.dot(axes[1]), direction.dot(axes[2]));
+
+                let mut near = (f32::MIN, 0);
+                let mut far = (f32::MAX, 0);
+                for i in 0..3 {
+                    let t0 = (-w[i + 1] - local_origin[i]) / local_direction[i];
+                    let t1 = (w[i + 1] - local_origin[i]) / local_direction[i];
+                    if t0.min(t1) > near.0 {
+                        near = (t0.min(t1), i);
+                    }
+                    if t0.max(t1) < far.0 {
+                        far = (t0.max(t1), i);
+                    }
+                }
+
+                if near.0 <= far.0 {
+                    consider(&mut closest, near.0, axes[near.1] * -local_direction[near.1].signum(), t_min, t_max);
+                    consider(&mut closest, far.0, axes[far.1] * local_direction[far.1].signum(), t_min, t_max);
+                }
+            }
+            Shape::Disk { .. } => {
+                if let Some(t) = intersect_disk(data[0], data[1], w[0], origin, direction) {
+                    consider(&mut closest, t, data[1], t_min, t_max);
+                }
+            }
+            Shape::Cylinder { .. } | Shape::Cone { .. } => {
+                let (base, radius, axis, height) = (data[0], w[0], data[1], w[1]);
+                let is_cone = matches!(self, Shape::Cone { .. });
+
+                let oc = origin - base;
+                let (oy, dy) = (oc.dot(axis), direction.dot(axis));
+                let oc_perp = oc - axis * oy;
+                let d_perp = direction - axis * dy;
+
+                // |p_perp|² = r(y)², with r(y) shrinking linearly to zero at the apex for cones
+                let (k, m) = if is_cone { (radius / height, height - oy) } else { (0.0, 0.0) };
+                let a = d_perp.magnitude2() - k * k * dy * dy;
+                let half_b = oc_perp.dot(d_perp) + k * k * m * dy;
+                let c = oc_perp.magnitude2() - if is_cone { k * k * m * m } else { radius * radius };
+
+                let discriminant = half_b * half_b - a * c;
+                if discriminant >= 0.0 && a.abs() > 1e-8 {
+                    let sqrtd = discriminant.sqrt();
+                    for t in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
+                        let y = oy + t * dy;
+                        if (0.0..=height).contains(&y) {
+                            let radial = (oc_perp + d_perp * t).normalize();
+                            let normal = if is_cone { (radial * height + axis * radius).normalize() } else { radial };
+                            consider(&mut closest, t, normal, t_min, t_max);
+                        }
+                    }
+                }
+
+                if let Some(t) = intersect_disk(base, -axis, radius, origin, direction) {
+                    consider(&mut closest, t, -axis, t_min, t_max);
+                }
+                if !is_cone {
+                    if let Some(t) = intersect_disk(base + axis * height, axis, radius, origin, direction) {
+                        consider(&mut closest, t, axis, t_min, t_max);
+                    }
+                }
+            }
+            Shape::Quad { .. } => {
+                let (corner, u, v) = (data[0], data[1], data[2]);
+                let n = u.cross(v);
+                let denom = n.dot(direction);
+                if denom.abs() > 1e-8 {
+                    let t = (corner - origin).dot(n) / denom;
+                    let p = origin + direction * t - corner;
+
+                    let w = n / n.magnitude2();
+                    let alpha = w.dot(p.cross(v));
+                    let beta = w.dot(u.cross(p));
+                    if (0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta) {
+                        consider(&mut closest, t, n.normalize(), t_min, t_max);
+                    }
+                }
+            }
+            Shape::Sphere { .. } => {
+                let (center, radius) = (data[0], w[0]);
+                let oc = origin - center;
+                let a = direction.magnitude2();
+                let half_b = oc.dot(direction);
+                let c = oc.magnitude2() - radius * radius;
+
+                let discriminant = half_b * half_b - a * c;
+                if discriminant >= 0.0 {
+                    for t in [(-half_b - discriminant.sqrt()) / a, (-half_b + discriminant.sqrt()) / a] {
+                        consider(&mut closest, t, (origin + direction * t - center) / radius, t_min, t_max);
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T_MAX: f32 = 1000.0;
+
+    fn assert_hit(shape: Shape, origin: Vector3<f32>, direction: Vector3<f32>, t: f32, normal: Vector3<f32>) {
+        let hit = shape.intersect(origin, direction, 0.001, T_MAX).expect("Expected a hit");
+        assert!((hit.t - t).abs() < 1e-4, "t = {}, expected {}", hit.t, t);
+        assert!((hit.normal - normal).magnitude() < 1e-4, "normal = {:?}, expected {:?}", hit.normal, normal);
+    }
+
+    fn assert_miss(shape: Shape, origin: Vector3<f32>, direction: Vector3<f32>) {
+        assert_eq!(shape.intersect(origin, direction, 0.001, T_MAX), None);
+    }
+
+    #[test]
+    fn plane() {
+        let plane = Shape::Plane { point: vec3(0.0, -0.5, 0.0), normal: vec3(0.0, 2.0, 0.0) };
+
+        assert_hit(plane, vec3(3.0, 1.5, -7.0), vec3(0.0, -1.0, 0.0), 2.0, vec3(0.0, 1.0, 0.0));
+        assert_hit(plane, vec3(0.0, -1.5, 0.0), vec3(0.0, 1.0, 0.0), 1.0, vec3(0.0, 1.0, 0.0));
+        assert_miss(plane, vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0));
+        assert_miss(plane, vec3(0.0, 1.0, 0.0), vec3(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn axis_aligned_box() {
+        let aabb = Shape::Box { min: vec3(-1.0, -2.0, -3.0), max: vec3(1.0, 2.0, 3.0) };
+
+        assert_hit(aabb, vec3(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), 4.0, vec3(-1.0, 0.0, 0.0));
+        assert_hit(aabb, vec3(0.5, 10.0, 0.5), vec3(0.0, -1.0, 0.0), 8.0, vec3(0.0, 1.0, 0.0));
+        // From the inside the far side is hit, the normal still points outwards
+        assert_hit(aabb, vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0), 3.0, vec3(0.0, 0.0, 1.0));
+        assert_miss(aabb, vec3(-5.0, 2.5, 0.0), vec3(1.0, 0.0, 0.0));
+        assert_miss(aabb, vec3(5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn oriented_box() {
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let obb = Shape::OrientedBox {
+            center: vec3(0.0, 1.0, 0.0),
+            half_extents: vec3(1.0, 1.0, 1.0),
+            axes: [vec3(s, 0.0, -s), vec3(0.0, 1.0, 0.0), vec3(s, 0.0, s)],
+        };
+
+        // Rotated by 45 degrees around y, the corners reach sqrt(2) along the world x and z axes
+        assert_hit(obb, vec3(5.0 * s, 1.0, -5.0 * s), vec3(-s, 0.0, s), 4.0, vec3(s, 0.0, -s));
+        assert_hit(obb, vec3(0.0, 5.0, 0.0), vec3(0.0, -1.0, 0.0), 3.0, vec3(0.0, 1.0, 0.0));
+        assert_hit(obb, vec3(5.0, 1.0, 0.2), vec3(-1.0, 0.0, 0.0), 5.0 - (2.0f32.sqrt() - 0.2), vec3(s, 0.0, s));
+        assert_miss(obb, vec3(5.0, 1.0, 1.5), vec3(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn disk() {
+        let disk = Shape::Disk { center: vec3(0.0, 0.0, 0.0), normal: vec3(0.0, 0.0, 1.0), radius: 1.0 };
+
+        assert_hit(disk, vec3(0.5, 0.5, 2.0), vec3(0.0, 0.0, -1.0), 2.0, vec3(0.0, 0.0, 1.0));
+        assert_hit(disk, vec3(0.0, 0.0, -2.0), vec3(0.0, 0.0, 1.0), 2.0, vec3(0.0, 0.0, 1.0));
+        assert_miss(disk, vec3(0.8, 0.8, 2.0), vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn cylinder() {
+        let cylinder = Shape::Cylinder { base: vec3(0.0, 0.0, 0.0), axis: vec3(0.0, 2.0, 0.0), radius: 0.5 };
+
+        assert_hit(cylinder, vec3(-3.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), 2.5, vec3(-1.0, 0.0, 0.0));
+        assert_hit(cylinder, vec3(0.2, 5.0, 0.0), vec3(0.0, -1.0, 0.0), 3.0, vec3(0.0, 1.0, 0.0));
+        assert_hit(cylinder, vec3(0.2, -5.0, 0.0), vec3(0.0, 1.0, 0.0), 5.0, vec3(0.0, -1.0, 0.0));
+        assert_hit(cylinder, vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0), 0.5, vec3(0.0, 0.0, 1.0));
+        assert_miss(cylinder, vec3(-3.0, 2.5, 0.0), vec3(1.0, 0.0, 0.0));
+        assert_miss(cylinder, vec3(0.6, 5.0, 0.0), vec3(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn cone() {
+        let cone = Shape::Cone { base: vec3(0.0, 0.0, 0.0), axis: vec3(0.0, 1.0, 0.0), radius: 1.0 };
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+
+        // Half way up the radius is 0.5
+        assert_hit(cone, vec3(-3.0, 0.5, 0.0), vec3(1.0, 0.0, 0.0), 2.5, vec3(-s, s, 0.0));
+        assert_hit(cone, vec3(0.0, -2.0, 0.0), vec3(0.0, 1.0, 0.0), 2.0, vec3(0.0, -1.0, 0.0));
+        assert_hit(cone, vec3(0.25, 5.0, 0.0), vec3(0.0, -1.0, 0.0), 4.25, vec3(s, s, 0.0));
+        assert_miss(cone, vec3(-3.0, 0.9, 0.5), vec3(1.0, 0.0, 0.0));
+        assert_miss(cone, vec3(-3.0, 1.5, 0.0), vec3(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn quad() {
+        let quad = Shape::Quad { corner: vec3(0.0, 0.0, 0.0), u: vec3(2.0, 0.0, 0.0), v: vec3(1.0, 1.0, 0.0) };
+
+        assert_hit(quad, vec3(1.5, 0.5, 3.0), vec3(0.0, 0.0, -1.0), 3.0, vec3(0.0, 0.0, 1.0));
+        assert_hit(quad, vec3(2.9, 0.99, 3.0), vec3(0.0, 0.0, -1.0), 3.0, vec3(0.0, 0.0, 1.0));
+        // Inside the bounding rectangle but outside the parallelogram
+        assert_miss(quad, vec3(0.1, 0.9, 3.0), vec3(0.0, 0.0, -1.0));
+        assert_miss(quad, vec3(1.0, 0.5, 3.0), vec3(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn sphere() {
+        let sphere = Shape::Sphere { center: vec3(0.0, 0.0, -2.0), radius: 0.5 };
+
+        assert_hit(sphere, vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0), 1.5, vec3(0.0, 0.0, 1.0));
+        assert_hit(sphere, vec3(0.0, 0.0, -2.0), vec3(0.0, 1.0, 0.0), 0.5, vec3(0.0, 1.0, 0.0));
+        assert_miss(sphere, vec3(0.0, 0.6, 0.0), vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn respects_ray_interval() {
+        let disk = Shape::Disk { center: vec3(0.0, 0.0, 0.0), normal: vec3(0.0, 1.0, 0.0), radius: 1.0 };
+
+        assert_eq!(disk.intersect(vec3(0.0, 1.0, 0.0), vec3(0.0, -1.0, 0.0), 0.001, 0.5), None);
+        assert_eq!(disk.intersect(vec3(0.0, 1.0, 0.0), vec3(0.0, -1.0, 0.0), 1.5, T_MAX), None);
+    }
+}
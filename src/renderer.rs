@@ -7,19 +7,67 @@ use crate::{vk::{
     descriptor_pool::*,
     swapchain::*,
     texture::*,
+    query_pool::*,
+    physical_device::DeviceSelector,
+    debug_messenger::ValidationSettings,
+    error::*,
 }, 
 scene::*, 
 utilities, 
-camera::*
+shaders::{self, ComputeShader},
+camera::*,
+material::{Material, MaterialRaw},
+sampler::*,
+frame_stats::FrameStats,
 };
 
 use gpu_allocator::MemoryLocation;
 
+use std::collections::VecDeque;
+
 const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
+// Start of the frame, after the clear, after the dispatch and after the blit
+const TIMESTAMPS_PER_FRAME: u32 = 4;
+// Statistics of older frames are dropped when nobody takes them, see take_frame_stats
+const MAX_KEPT_FRAME_STATS: usize = 1024;
+
+const MIN_RENDER_SCALE: f32 = 0.25;
+const MAX_RENDER_SCALE: f32 = 2.0;
+
+// How the render target is scaled to the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    Nearest,
+    Bilinear,
+}
+
+impl UpscaleFilter {
+    pub fn to_vk(self) -> ash::vk::Filter {
+        match self {
+            UpscaleFilter::Nearest => ash::vk::Filter::NEAREST,
+            UpscaleFilter::Bilinear => ash::vk::Filter::LINEAR,
+        }
+    }
+
+    pub fn next(self) -> UpscaleFilter {
+        match self {
+            UpscaleFilter::Nearest => UpscaleFilter::Bilinear,
+            UpscaleFilter::Bilinear => UpscaleFilter::Nearest,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UpscaleFilter::Nearest => "Nearest",
+            UpscaleFilter::Bilinear => "Bilinear",
+        }
+    }
+}
+
 pub struct Renderer {
-    pub context: VkContext,
-    pub swapchain: VkSwapchain,
+    // None when rendering headless
+    swapchain: Option<VkSwapchain>,
 
     compute_pipeline: VkComputePipeline,
     preview_pipeline: VkComputePipeline,
@@ -34,149 +82,226 @@ pub struct Renderer {
 
     descriptor_sets: Vec<VkDescriptorSet>,
     scene_buffer: VkBuffer,
+    // What the scene buffer holds, so update_material can keep the opacity and sidedness set on the Scene
+    materials: [MaterialRaw; MAX_MATERIALS],
+    // Only read through the descriptor sets, kept so it lives as long as they do
+    _sampler_buffer: VkBuffer,
 
     camera_buffers: Vec<VkBuffer>,
     render_target: VkTexture,
 
+    // One per frame in flight, empty when the device can't write timestamps
+    query_pools: Vec<VkTimestampQueryPool>,
+    // The ray counter of main.comp for every frame in flight
+    stats_buffers: Vec<VkBuffer>,
+    // Filled in once the fence of the frame is signaled
+    pending_stats: Vec<Option<FrameStats>>,
+    frame_stats: VecDeque<FrameStats>,
+
     should_reset_rt: bool,
+    // The size of the window, or of the image when rendering headless
+    window_extent: ash::vk::Extent2D,
+    swapchain_outdated: bool,
+    render_scale: f32,
+    pub upscale_filter: UpscaleFilter,
     pub preview_mode: bool,
+    pub sampler_type: SamplerType,
+
+    // Declared last so it is dropped after the resources above
+    context: VkContext,
 }
 
 impl Renderer {
-    pub fn new(window: &winit::window::Window) -> Renderer {
-        let mut context = VkContext::new(window);
+    pub fn new(window: &winit::window::Window, device_selector: &DeviceSelector, validation: &ValidationSettings) -> VkResult<Renderer> {
+        let context = VkContext::new(Some(window), device_selector, validation)?;
+
+        let window_extent = ash::vk::Extent2D { 
+            width: window.inner_size().width, 
+            height: window.inner_size().height 
+        };
 
         let swapchain = VkSwapchain::new(
-            &context.instance,
+            &context.device.instance,
             &context.device,
             &context.physical_device,
-            &context.surface,
-            ash::vk::Extent2D { 
-                width: window.inner_size().width, 
-                height: window.inner_size().height 
-            }
-        );
+            context.surface.as_ref().unwrap(),
+            window_extent
+        )?;
 
-        let mut render_target = VkTexture::new(
-            &context.device,
-            &mut context.allocator.as_mut().unwrap(), 
-            ash::vk::Format::B8G8R8A8_UNORM,
-            ash::vk::Extent2D { 
-                width: swapchain.extent.width,
-                height: swapchain.extent.height
-            },
-            ash::vk::ImageTiling::LINEAR,
-            ash::vk::ImageUsageFlags::STORAGE | ash::vk::ImageUsageFlags::TRANSFER_SRC | ash::vk::ImageUsageFlags::TRANSFER_DST,
-            ash::vk::ImageAspectFlags::COLOR
-        );
+        Self::prepare_swapchain_images(&context, &swapchain)?;
 
-        let cmd = utilities::begin_single_queue_submit(&context.device, &context.graphics_command_pool);
-        for image in swapchain.images.iter() {
-            cmd.transition_image_layout(
-                &context.device,
-                image.clone(),
-                ash::vk::ImageAspectFlags::COLOR,
-                ash::vk::ImageLayout::UNDEFINED,
-                ash::vk::ImageLayout::PRESENT_SRC_KHR
-            );
-        }
-        
-        render_target.transition_layout(
-            &context.device,
-            ash::vk::ImageLayout::GENERAL,
-            &cmd
-        );
-        utilities::end_single_queue_submit(&context.device, &context.graphics_command_pool, &context.graphics_queue, cmd);
+        Self::create(context, window_extent, Some(swapchain))
+    }
+    // Renders `width` x `height` images without a window, see render_to_image
+    pub fn headless(device_selector: &DeviceSelector, validation: &ValidationSettings, width: u32, height: u32) -> VkResult<Renderer> {
+        let context = VkContext::new(None, device_selector, validation)?;
+
+        let mut renderer = Self::create(context, ash::vk::Extent2D { width, height }, None)?;
+        renderer.preview_mode = false;
+
+        Ok(renderer)
+    }
+    fn create(mut context: VkContext, window_extent: ash::vk::Extent2D, swapchain: Option<VkSwapchain>) -> VkResult<Renderer> {
+        let output_extent = swapchain.as_ref().map_or(window_extent, |swapchain| swapchain.extent);
+        let render_target = Self::create_render_target(&mut context, Self::scaled_extent(output_extent, 1.0))?;
 
         let scene_buffer = VkBuffer::new(
             &context.device,
-            &mut context.allocator.as_mut().unwrap(),
             std::mem::size_of::<Scene>() as u64,
             ash::vk::BufferUsageFlags::STORAGE_BUFFER | ash::vk::BufferUsageFlags::TRANSFER_DST,
             MemoryLocation::GpuOnly
-        );
+        )?;
+        scene_buffer.set_name("Scene");
 
-        let camera_buffers: Vec<VkBuffer> = (0..MAX_FRAMES_IN_FLIGHT).into_iter().map(|_|{VkBuffer::new(
+        let blue_noise = generate_blue_noise(BLUE_NOISE_SIZE);
+
+        let sampler_buffer = VkBuffer::new(
+            &context.device,
+            (blue_noise.len() * std::mem::size_of::<u32>()) as u64,
+            ash::vk::BufferUsageFlags::STORAGE_BUFFER | ash::vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly
+        )?;
+        sampler_buffer.set_name("Blue noise");
+
+        let mut staging_sampler_buffer = VkBuffer::new(
             &context.device,
-            &mut context.allocator.as_mut().unwrap(),
-            std::mem::size_of::<CameraRaw>() as u64,
-            ash::vk::BufferUsageFlags::UNIFORM_BUFFER,
+            sampler_buffer.size,
+            ash::vk::BufferUsageFlags::TRANSFER_SRC,
             MemoryLocation::CpuToGpu
-        )}).collect();
+        )?;
+
+        staging_sampler_buffer.fill(&blue_noise);
+
+        let cmd = utilities::begin_single_queue_submit(&context.device, &context.transfer_command_pool)?;
+        staging_sampler_buffer.copy_to_buffer(&cmd, &sampler_buffer, &context.device);
+        utilities::end_single_queue_submit(&context.device, &context.transfer_command_pool, &context.transfer_queue, cmd)?;
+
+        let camera_buffers: Vec<VkBuffer> = (0..MAX_FRAMES_IN_FLIGHT).map(|i|{
+            let camera_buffer = VkBuffer::new(
+                &context.device,
+                std::mem::size_of::<CameraRaw>() as u64,
+                ash::vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu
+            )?;
+            camera_buffer.set_name(&format!("Camera {}", i));
+            Ok(camera_buffer)
+        }).collect::<VkResult<_>>()?;
+
+        let stats_buffers: Vec<VkBuffer> = (0..MAX_FRAMES_IN_FLIGHT).map(|i|{
+            let mut stats_buffer = VkBuffer::new(
+                &context.device,
+                std::mem::size_of::<[u32; 2]>() as u64,
+                ash::vk::BufferUsageFlags::STORAGE_BUFFER,
+                MemoryLocation::GpuToCpu
+            )?;
+            stats_buffer.set_name(&format!("Stats {}", i));
+            stats_buffer.fill(&[0u32; 2]);
+            Ok(stats_buffer)
+        }).collect::<VkResult<_>>()?;
+
+        let query_pools = if context.physical_device.supports_timestamps {
+            (0..MAX_FRAMES_IN_FLIGHT).map(|_| {
+                VkTimestampQueryPool::new(
+                    &context.device,
+                    TIMESTAMPS_PER_FRAME,
+                    context.physical_device.timestamp_period,
+                    context.physical_device.timestamp_valid_bits
+                )
+            }).collect::<VkResult<_>>()?
+        } else {
+            log::info!("{} can't write timestamps, frame timings are reported as zero", context.physical_device.name);
+            Vec::new()
+        };
 
         let descriptor_sets: Vec<VkDescriptorSet> = (0..MAX_FRAMES_IN_FLIGHT).into_iter().map(|i|{
-            context.descriptor_pool.allocate(&context.device, &vec![
+            context.descriptor_pool.allocate(&vec![
+                Self::render_target_slot(&render_target),
+                VkDescriptorSetSlot{
+                    binding: ash::vk::DescriptorSetLayoutBinding {
+                        binding: 1,
+                        descriptor_type: ash::vk::DescriptorType::STORAGE_BUFFER,
+                        descriptor_count: 1,
+                        stage_flags: ash::vk::ShaderStageFlags::COMPUTE,
+                        p_immutable_samplers: std::ptr::null(),
+                    },
+                    buffer_info: Some(ash::vk::DescriptorBufferInfo {
+                        buffer: scene_buffer.handle,
+                        offset: 0,
+                        range: scene_buffer.size,
+                    }),
+                    image_info: None,
+                },
                 VkDescriptorSetSlot{
                     binding: ash::vk::DescriptorSetLayoutBinding {
-                        binding: 0,
-                        descriptor_type: ash::vk::DescriptorType::STORAGE_IMAGE,
+                        binding: 2,
+                        descriptor_type: ash::vk::DescriptorType::UNIFORM_BUFFER,
                         descriptor_count: 1,
                         stage_flags: ash::vk::ShaderStageFlags::COMPUTE,
                         p_immutable_samplers: std::ptr::null(),
                     },
-                    buffer_info: None,
-                    image_info: Some(ash::vk::DescriptorImageInfo{
-                        sampler: ash::vk::Sampler::null(),
-                        image_view: render_target.view,
-                        image_layout: render_target.layout,
+                    buffer_info: Some(ash::vk::DescriptorBufferInfo {
+                        buffer: camera_buffers[i].handle,
+                        offset: 0,
+                        range: camera_buffers[i].size,
                     }),
+                    image_info: None,
                 },
                 VkDescriptorSetSlot{
                     binding: ash::vk::DescriptorSetLayoutBinding {
-                        binding: 1,
+                        binding: 3,
                         descriptor_type: ash::vk::DescriptorType::STORAGE_BUFFER,
                         descriptor_count: 1,
                         stage_flags: ash::vk::ShaderStageFlags::COMPUTE,
                         p_immutable_samplers: std::ptr::null(),
                     },
                     buffer_info: Some(ash::vk::DescriptorBufferInfo {
-                        buffer: scene_buffer.handle,
+                        buffer: sampler_buffer.handle,
                         offset: 0,
-                        range: scene_buffer.size,
+                        range: sampler_buffer.size,
                     }),
                     image_info: None,
                 },
                 VkDescriptorSetSlot{
                     binding: ash::vk::DescriptorSetLayoutBinding {
-                        binding: 2,
-                        descriptor_type: ash::vk::DescriptorType::UNIFORM_BUFFER,
+                        binding: 4,
+                        descriptor_type: ash::vk::DescriptorType::STORAGE_BUFFER,
                         descriptor_count: 1,
                         stage_flags: ash::vk::ShaderStageFlags::COMPUTE,
                         p_immutable_samplers: std::ptr::null(),
                     },
                     buffer_info: Some(ash::vk::DescriptorBufferInfo {
-                        buffer: camera_buffers[i].handle,
+                        buffer: stats_buffers[i].handle,
                         offset: 0,
-                        range: camera_buffers[i].size,
+                        range: stats_buffers[i].size,
                     }),
                     image_info: None,
                 },
             ])
-        }).collect();
+        }).collect::<VkResult<_>>()?;
 
         let compute_pipeline = VkComputePipeline::new(
             &context.device,
-            "shaders/main.spv",
+            shaders::MAIN,
             &vec![descriptor_sets[0].layout],
             &vec![]
-        );
+        )?;
+        compute_pipeline.set_name(ComputeShader::Main.name());
         let preview_pipeline = VkComputePipeline::new(
             &context.device,
-            "shaders/preview.spv",
+            shaders::PREVIEW,
             &vec![descriptor_sets[0].layout],
             &vec![]
-        );
-
-        let command_buffers = context.graphics_command_pool.allocate(&context.device, MAX_FRAMES_IN_FLIGHT as u32);
+        )?;
+        preview_pipeline.set_name(ComputeShader::Preview.name());
 
-        let image_available_semaphores = (0..MAX_FRAMES_IN_FLIGHT).map(|_| VkSemaphore::new(&context.device)).collect();
-        let render_finished_semaphores = (0..MAX_FRAMES_IN_FLIGHT).map(|_| VkSemaphore::new(&context.device)).collect();
+        let command_buffers = context.graphics_command_pool.allocate(&context.device, MAX_FRAMES_IN_FLIGHT as u32)?;
 
-        let in_flight_fences = (0..MAX_FRAMES_IN_FLIGHT).map(|_| VkFence::new(&context.device, ash::vk::FenceCreateFlags::SIGNALED)).collect();
+        let image_available_semaphores = (0..MAX_FRAMES_IN_FLIGHT).map(|_| VkSemaphore::new(&context.device)).collect::<VkResult<_>>()?;
+        let render_finished_semaphores = (0..MAX_FRAMES_IN_FLIGHT).map(|_| VkSemaphore::new(&context.device)).collect::<VkResult<_>>()?;
 
-        Self {
-            context,
+        let in_flight_fences = (0..MAX_FRAMES_IN_FLIGHT).map(|_| VkFence::new(&context.device, ash::vk::FenceCreateFlags::SIGNALED)).collect::<VkResult<_>>()?;
 
+        Ok(Self {
             swapchain,
 
             compute_pipeline,
@@ -192,71 +317,292 @@ impl Renderer {
             frames_since_start: 0,
 
             scene_buffer,
+            materials: [MaterialRaw::default(); MAX_MATERIALS],
+            _sampler_buffer: sampler_buffer,
             camera_buffers,
             descriptor_sets,
 
             render_target,
+
+            query_pools,
+            stats_buffers,
+            pending_stats: vec![None; MAX_FRAMES_IN_FLIGHT],
+            frame_stats: VecDeque::new(),
+
             should_reset_rt: false,
+            window_extent,
+            swapchain_outdated: false,
+            render_scale: 1.0,
+            upscale_filter: UpscaleFilter::Bilinear,
             preview_mode: true,
+            sampler_type: SamplerType::Sobol,
+
+            context,
+        })
+    }
+    // Swapchain images start out in the layout presenting leaves them in
+    fn prepare_swapchain_images(context: &VkContext, swapchain: &VkSwapchain) -> VkResult<()> {
+        let cmd = utilities::begin_single_queue_submit(&context.device, &context.graphics_command_pool)?;
+        for image in swapchain.images.iter() {
+            cmd.transition_image_layout(
+                &context.device,
+                *image,
+                ash::vk::ImageAspectFlags::COLOR,
+                ash::vk::ImageLayout::UNDEFINED,
+                ash::vk::ImageLayout::PRESENT_SRC_KHR
+            );
+        }
+        utilities::end_single_queue_submit(&context.device, &context.graphics_command_pool, &context.graphics_queue, cmd)
+    }
+    fn scaled_extent(extent: ash::vk::Extent2D, scale: f32) -> ash::vk::Extent2D {
+        ash::vk::Extent2D {
+            width: ((extent.width as f32 * scale).round() as u32).max(1),
+            height: ((extent.height as f32 * scale).round() as u32).max(1),
         }
     }
-    pub fn bind_scene(&mut self, scene: Scene) {
+    fn create_render_target(context: &mut VkContext, extent: ash::vk::Extent2D) -> VkResult<VkTexture> {
+        let mut render_target = VkTexture::new(
+            &context.device, 
+            ash::vk::Format::B8G8R8A8_UNORM,
+            extent,
+            ash::vk::ImageTiling::OPTIMAL,
+            ash::vk::ImageUsageFlags::STORAGE | ash::vk::ImageUsageFlags::TRANSFER_SRC | ash::vk::ImageUsageFlags::TRANSFER_DST,
+            ash::vk::ImageAspectFlags::COLOR
+        )?;
+        render_target.set_name("Render target");
+
+        let cmd = utilities::begin_single_queue_submit(&context.device, &context.graphics_command_pool)?;
+        render_target.transition_layout(
+            &context.device,
+            ash::vk::ImageLayout::GENERAL,
+            &cmd
+        );
+        utilities::end_single_queue_submit(&context.device, &context.graphics_command_pool, &context.graphics_queue, cmd)?;
+
+        Ok(render_target)
+    }
+    fn render_target_slot(render_target: &VkTexture) -> VkDescriptorSetSlot {
+        VkDescriptorSetSlot{
+            binding: ash::vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: ash::vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: 1,
+                stage_flags: ash::vk::ShaderStageFlags::COMPUTE,
+                p_immutable_samplers: std::ptr::null(),
+            },
+            buffer_info: None,
+            image_info: Some(ash::vk::DescriptorImageInfo{
+                sampler: ash::vk::Sampler::null(),
+                image_view: render_target.view,
+                image_layout: render_target.layout,
+            }),
+        }
+    }
+    pub fn bind_scene(&mut self, scene: &Scene) -> VkResult<()> {
         let mut staging_scene_buffer = VkBuffer::new(
             &self.context.device,
-            &mut self.context.allocator.as_mut().unwrap(),
             std::mem::size_of::<Scene>() as u64,
             ash::vk::BufferUsageFlags::TRANSFER_SRC,
             MemoryLocation::CpuToGpu
-        );
+        )?;
 
-        staging_scene_buffer.fill_raw(&scene, 1);
+        staging_scene_buffer.fill(std::slice::from_ref(scene));
 
-        let cmd = utilities::begin_single_queue_submit(&self.context.device, &self.context.transfer_command_pool);
-        staging_scene_buffer.copy_to_buffer(&cmd, &self.scene_buffer, &self.context.device);
-        utilities::end_single_queue_submit(&self.context.device, &self.context.transfer_command_pool, &self.context.transfer_queue, cmd);
+        // Frames in flight still read the previous scene
+        self.wait_device_idle()?;
+        self.copy_from_staging(&mut staging_scene_buffer, 0)?;
+        self.materials = *scene.materials();
+        Ok(())
+    }
+    fn copy_from_staging(&mut self, staging_buffer: &mut VkBuffer, offset: usize) -> VkResult<()> {
+        let cmd = utilities::begin_single_queue_submit(&self.context.device, &self.context.transfer_command_pool)?;
+        staging_buffer.copy_to_buffer_at(&cmd, &self.scene_buffer, offset as u64, &self.context.device);
+        utilities::end_single_queue_submit(&self.context.device, &self.context.transfer_command_pool, &self.context.transfer_queue, cmd)
+    }
+    // Uploads `data` alone to `offset` bytes into the scene buffer, see the Scene::*_offset functions
+    fn update_scene<T>(&mut self, offset: usize, data: &[T]) -> VkResult<()> {
+        let size = std::mem::size_of_val(data);
+        assert!(offset + size <= std::mem::size_of::<Scene>());
+
+        let mut staging_buffer = VkBuffer::new(
+            &self.context.device,
+            size as u64,
+            ash::vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu
+        )?;
+
+        staging_buffer.fill(data);
+
+        // Frames in flight still read the scene
+        self.wait_device_idle()?;
+        self.copy_from_staging(&mut staging_buffer, offset)?;
 
-        staging_scene_buffer.destroy(&self.context.device, self.context.allocator.as_mut().unwrap());
+        self.reset_render_target();
+        Ok(())
+    }
+    // Replaces the material but keeps the opacity and double sidedness it has in the bound scene, see MaterialRaw::keep_flags_of
+    pub fn update_material(&mut self, index: usize, material: &Material) -> VkResult<()> {
+        let raw = material.to_raw().keep_flags_of(&self.materials[index]);
+        self.update_scene(Scene::material_offset(index), &[raw])?;
 
+        self.materials[index] = raw;
+        Ok(())
+    }
+    pub fn update_sphere(&mut self, index: usize, sphere: Sphere) -> VkResult<()> {
+        self.update_scene(Scene::sphere_offset(index), &[sphere])
+    }
+    // E.g. to move a mesh around with Mesh::with_transform. The vertices and the BVH stay untouched, so the index range has to as well
+    pub fn update_mesh(&mut self, index: usize, mesh: Mesh) -> VkResult<()> {
+        self.update_scene(Scene::mesh_offset(index), &[mesh])
+    }
+    // The swapchain is recreated before the next frame, nothing is rendered while minimized
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.window_extent = ash::vk::Extent2D { width, height };
+        self.swapchain_outdated = true;
     }
-    pub fn render(&mut self, camera: &Camera) {  
-        if camera.size.x * camera.size.y == 0.0 {
-            return;
+    // Headless only the render target changes size
+    fn recreate_swapchain(&mut self) -> VkResult<()> {
+        self.wait_device_idle()?;
+
+        if let Some(previous) = self.swapchain.as_ref() {
+            // The old swapchain is only destroyed once the new one exists, so a failure leaves a usable renderer
+            let swapchain = VkSwapchain::new(
+                &self.context.device.instance,
+                &self.context.device,
+                &self.context.physical_device,
+                self.context.surface.as_ref().unwrap(),
+                self.window_extent
+            )?;
+            previous.destroy(&self.context.device);
+            Self::prepare_swapchain_images(&self.context, &swapchain)?;
+            self.swapchain = Some(swapchain);
         }
 
-        self.in_flight_fences[self.frame_index].wait(&self.context.device);
+        self.swapchain_outdated = false;
+        self.recreate_render_target()
+    }
+    // The size images are presented or read back at, before the render scale applies
+    pub fn output_extent(&self) -> ash::vk::Extent2D {
+        self.swapchain.as_ref().map_or(self.window_extent, |swapchain| swapchain.extent)
+    }
+    pub fn device_name(&self) -> &str {
+        &self.context.physical_device.name
+    }
+    // Without them the timings in FrameStats stay zero
+    pub fn supports_timestamps(&self) -> bool {
+        !self.query_pools.is_empty()
+    }
+    fn recreate_render_target(&mut self) -> VkResult<()> {
+        self.wait_device_idle()?;
+
+        let extent = Self::scaled_extent(self.output_extent(), self.render_scale);
+        self.render_target = Self::create_render_target(&mut self.context, extent)?;
+
+        for descriptor_set in self.descriptor_sets.iter() {
+            descriptor_set.update(&vec![Self::render_target_slot(&self.render_target)]);
+        }
+
+        self.reset_render_target();
+        Ok(())
+    }
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+    // Size of the render target relative to the window, below 1 for fast previews and above for supersampling
+    pub fn set_render_scale(&mut self, scale: f32) -> VkResult<()> {
+        let scale = scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+
+        if scale != self.render_scale {
+            let previous = std::mem::replace(&mut self.render_scale, scale);
+            if let Err(error) = self.recreate_render_target() {
+                self.render_scale = previous;
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+    pub fn render(&mut self, camera: &Camera) -> VkResult<()> {  
+        if camera.size.x * camera.size.y == 0.0 || self.window_extent.width * self.window_extent.height == 0 {
+            return Ok(());
+        }
+
+        if self.swapchain_outdated {
+            self.recreate_swapchain()?;
+        }
 
-        let result = self.swapchain.acquire_next_image(&self.image_available_semaphores[self.frame_index]);
+        self.in_flight_fences[self.frame_index].wait()?;
+        self.collect_frame_stats(self.frame_index)?;
 
-        let (image_index, _is_sub_optimal) = match result {
-            Ok(swapchain_info) => swapchain_info,
-            Err(result) => match result {
-                ash::vk::Result::ERROR_OUT_OF_DATE_KHR => {
-                    panic!("Swapchain out of date!");
+        // Headless frames only accumulate into the render target
+        let image_index = match self.swapchain.as_mut() {
+            Some(swapchain) => match swapchain.acquire_next_image(&self.image_available_semaphores[self.frame_index]) {
+                Ok((image_index, is_sub_optimal)) => {
+                    // Still usable, this frame is presented before recreating
+                    self.swapchain_outdated |= is_sub_optimal;
+                    Some(image_index)
                 }
-                _ => panic!("Failed to acquire Swap Chain Image!"),
+                Err(ash::vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    self.swapchain_outdated = true;
+                    return Ok(());
+                }
+                Err(result) => return Err(VkError::Vulkan { action: "acquire Swap Chain Image", result }),
             },
+            None => None,
         };
         
-        self.in_flight_fences[self.frame_index].reset(&self.context.device);
+        self.in_flight_fences[self.frame_index].reset()?;
 
         self.camera_buffers[self.frame_index].fill(&[camera.to_raw(if self.should_reset_rt {
             0
         } else {
             self.frames_since_start
-        })]);
+        }, self.sampler_type)]);
+        self.stats_buffers[self.frame_index].fill(&[0u32; 2]);
+
+        self.command_buffers[self.frame_index].begin_recording(&self.context.device, ash::vk::CommandBufferUsageFlags::empty())?;
+
+        if let Some(query_pool) = self.query_pools.get(self.frame_index) {
+            query_pool.reset(&self.command_buffers[self.frame_index]);
+            query_pool.write(&self.command_buffers[self.frame_index], ash::vk::PipelineStageFlags::TOP_OF_PIPE, 0);
+        }
 
-        self.command_buffers[self.frame_index].begin_recording(&self.context.device, ash::vk::CommandBufferUsageFlags::empty());
+        // The previous frame may still be accumulating into the render target
+        self.command_buffers[self.frame_index].memory_barrier(
+            &self.context.device,
+            ash::vk::PipelineStageFlags::COMPUTE_SHADER | ash::vk::PipelineStageFlags::TRANSFER,
+            ash::vk::AccessFlags::SHADER_WRITE | ash::vk::AccessFlags::TRANSFER_WRITE,
+            ash::vk::PipelineStageFlags::COMPUTE_SHADER | ash::vk::PipelineStageFlags::TRANSFER,
+            ash::vk::AccessFlags::SHADER_READ | ash::vk::AccessFlags::SHADER_WRITE | ash::vk::AccessFlags::TRANSFER_WRITE
+        );
 
         if self.should_reset_rt {
+            self.command_buffers[self.frame_index].begin_label(&self.context.device, "Clear render target");
             self.render_target.clear(
                 &self.context.device,
                 &self.command_buffers[self.frame_index],
                 cgmath::vec4(0.2, 0.2, 0.2, 1.0)
             );
+            self.command_buffers[self.frame_index].end_label(&self.context.device);
+            self.command_buffers[self.frame_index].memory_barrier(
+                &self.context.device,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::AccessFlags::TRANSFER_WRITE,
+                ash::vk::PipelineStageFlags::COMPUTE_SHADER,
+                ash::vk::AccessFlags::SHADER_READ | ash::vk::AccessFlags::SHADER_WRITE
+            );
             self.frames_since_start = 0;
             self.should_reset_rt = false;
         }
-        
+
+        self.write_timestamp(1);
+
+        self.command_buffers[self.frame_index].begin_label(&self.context.device, if self.preview_mode {
+            "Preview"
+        } else {
+            "Path trace"
+        });
+
         self.command_buffers[self.frame_index].bind_compute_pipeline(&self.context.device, if self.preview_mode { 
             &self.preview_pipeline
         } else {
@@ -272,91 +618,253 @@ impl Renderer {
 
         self.command_buffers[self.frame_index].dispatch(
             &self.context.device,
-            (self.swapchain.extent.width as f32 / 4.0).ceil() as u32,
-            (self.swapchain.extent.height as f32 / 8.0).ceil() as u32,
+            (self.render_target.extent.width as f32 / 4.0).ceil() as u32,
+            (self.render_target.extent.height as f32 / 8.0).ceil() as u32,
             1
         );
 
-        self.command_buffers[self.frame_index].transition_image_layout(
-            &self.context.device,
-            self.swapchain.images[image_index as usize],
-            ash::vk::ImageAspectFlags::COLOR,
-            ash::vk::ImageLayout::PRESENT_SRC_KHR,
-            ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL
-        );
+        self.command_buffers[self.frame_index].end_label(&self.context.device);
+        self.write_timestamp(2);
 
-        self.render_target.copy_to_image(
+        // The ray count is read on the host once the fence is signaled
+        self.command_buffers[self.frame_index].memory_barrier(
             &self.context.device,
-            &self.command_buffers[self.frame_index],
-            self.swapchain.images[image_index as usize],
-            ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            ash::vk::ImageAspectFlags::COLOR
+            ash::vk::PipelineStageFlags::COMPUTE_SHADER,
+            ash::vk::AccessFlags::SHADER_WRITE,
+            ash::vk::PipelineStageFlags::HOST,
+            ash::vk::AccessFlags::HOST_READ
         );
 
-        self.command_buffers[self.frame_index].transition_image_layout(
-            &self.context.device,
-            self.swapchain.images[image_index as usize],
-            ash::vk::ImageAspectFlags::COLOR,
-            ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            ash::vk::ImageLayout::PRESENT_SRC_KHR,
-        );
+        if let (Some(swapchain), Some(image_index)) = (self.swapchain.as_ref(), image_index) {
+            self.command_buffers[self.frame_index].begin_label(&self.context.device, "Blit to swapchain");
 
-        self.command_buffers[self.frame_index].end_recording(&self.context.device);
+            self.command_buffers[self.frame_index].memory_barrier(
+                &self.context.device,
+                ash::vk::PipelineStageFlags::COMPUTE_SHADER,
+                ash::vk::AccessFlags::SHADER_WRITE,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::AccessFlags::TRANSFER_READ
+            );
+
+            self.command_buffers[self.frame_index].transition_image_layout(
+                &self.context.device,
+                swapchain.images[image_index as usize],
+                ash::vk::ImageAspectFlags::COLOR,
+                ash::vk::ImageLayout::PRESENT_SRC_KHR,
+                ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL
+            );
+
+            let target = BlitTarget {
+                image: swapchain.images[image_index as usize],
+                extent: swapchain.extent,
+                layout: ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                aspect: ash::vk::ImageAspectFlags::COLOR,
+            };
+            self.render_target.blit_to_image(
+                &self.context.device,
+                &self.command_buffers[self.frame_index],
+                &target,
+                self.upscale_filter.to_vk()
+            );
+
+            self.command_buffers[self.frame_index].transition_image_layout(
+                &self.context.device,
+                swapchain.images[image_index as usize],
+                ash::vk::ImageAspectFlags::COLOR,
+                ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                ash::vk::ImageLayout::PRESENT_SRC_KHR,
+            );
+
+            self.command_buffers[self.frame_index].end_label(&self.context.device);
+        }
+
+        self.write_timestamp(3);
+
+        self.command_buffers[self.frame_index].end_recording(&self.context.device)?;
+
+        let is_presenting = image_index.is_some();
 
         self.context.graphics_queue.submit(
             &self.context.device,
             &self.command_buffers[self.frame_index],
             ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            &self.image_available_semaphores[self.frame_index],
-            &self.render_finished_semaphores[self.frame_index],
+            Some(&self.image_available_semaphores[self.frame_index]).filter(|_| is_presenting),
+            Some(&self.render_finished_semaphores[self.frame_index]).filter(|_| is_presenting),
             &self.in_flight_fences[self.frame_index]
-        );
+        )?;
+
+        let extent = self.render_target.extent;
+        let samples_per_pixel = if self.preview_mode { 1 } else { SAMPLES_PER_PIXEL };
+        self.pending_stats[self.frame_index] = Some(FrameStats {
+            frame: self.frames_since_start + 1,
+            width: extent.width,
+            height: extent.height,
+            preview: self.preview_mode,
+            samples: extent.width as u64 * extent.height as u64 * samples_per_pixel as u64,
+            ..Default::default()
+        });
+
+        if let (Some(swapchain), Some(image_index)) = (self.swapchain.as_ref(), image_index) {
+            let result = swapchain.present(
+                image_index,
+                &self.context.present_queue,
+                &self.render_finished_semaphores[self.frame_index]
+            );
 
-        self.swapchain.present(
-            image_index,
-            &self.context.present_queue,
-            &self.render_finished_semaphores[self.frame_index]
-        ).expect("Failed to present swapchain image!");
+            match result {
+                Ok(is_sub_optimal) => self.swapchain_outdated |= is_sub_optimal,
+                Err(ash::vk::Result::ERROR_OUT_OF_DATE_KHR) => self.swapchain_outdated = true,
+                Err(result) => return Err(VkError::Vulkan { action: "present swapchain image", result }),
+            }
+        }
+
+        self.context.device.check_validation();
 
         self.frames_since_start += 1;
         self.frame_index = (self.frame_index + 1) % MAX_FRAMES_IN_FLIGHT;
+        Ok(())
     }
-    pub fn wait_device_idle(&self) {
+    // Restarts accumulation and renders `frames` frames, each adds SAMPLES_PER_PIXEL samples (see main.comp) to every pixel
+    pub fn render_to_image(&mut self, camera: &Camera, frames: u32) -> VkResult<image::RgbaImage> {
+        self.reset_render_target();
+
+        for _ in 0..frames {
+            self.render(camera)?;
+        }
+
+        self.wait_device_idle()?;
+        for frame_index in 0..MAX_FRAMES_IN_FLIGHT {
+            self.collect_frame_stats(frame_index)?;
+        }
+
+        self.read_render_target()
+    }
+    fn write_timestamp(&self, query: u32) {
+        if let Some(query_pool) = self.query_pools.get(self.frame_index) {
+            // Once everything recorded so far has finished
+            query_pool.write(&self.command_buffers[self.frame_index], ash::vk::PipelineStageFlags::BOTTOM_OF_PIPE, query);
+        }
+    }
+    // The frame in `frame_index` has to be done executing
+    fn collect_frame_stats(&mut self, frame_index: usize) -> VkResult<()> {
+        let mut stats = match self.pending_stats[frame_index].take() {
+            Some(stats) => stats,
+            None => return Ok(()),
+        };
+
+        if let Some(query_pool) = self.query_pools.get(frame_index) {
+            let timestamps = query_pool.read_ms()?;
+            stats.clear_ms = timestamps[1];
+            stats.trace_ms = timestamps[2] - timestamps[1];
+            stats.blit_ms = timestamps[3] - timestamps[2];
+            stats.gpu_ms = timestamps[3];
+        }
+
+        // Only main.comp counts its rays, the preview casts one per sample
+        stats.rays = if stats.preview {
+            stats.samples
+        } else {
+            // The low and the high word of main.comp's rayCount
+            let bytes = self.stats_buffers[frame_index].read_bytes();
+            let low = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64;
+            let high = u32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as u64;
+            high << 32 | low
+        };
+
+        if self.frame_stats.len() == MAX_KEPT_FRAME_STATS {
+            self.frame_stats.pop_front();
+        }
+        self.frame_stats.push_back(stats);
+        Ok(())
+    }
+    // Statistics of the most recent frame the GPU has finished
+    pub fn last_frame_stats(&self) -> Option<FrameStats> {
+        self.frame_stats.back().copied()
+    }
+    // Statistics of every finished frame since the last call, oldest first
+    pub fn take_frame_stats(&mut self) -> Vec<FrameStats> {
+        self.frame_stats.drain(..).collect()
+    }
+    fn read_render_target(&mut self) -> VkResult<image::RgbaImage> {
+        let extent = self.render_target.extent;
+
+        let readback_buffer = VkBuffer::new(
+            &self.context.device,
+            extent.width as u64 * extent.height as u64 * 4,
+            ash::vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu
+        )?;
+        readback_buffer.set_name("Readback");
+
+        let cmd = utilities::begin_single_queue_submit(&self.context.device, &self.context.graphics_command_pool)?;
+        cmd.begin_label(&self.context.device, "Read back render target");
+        cmd.memory_barrier(
+            &self.context.device,
+            ash::vk::PipelineStageFlags::COMPUTE_SHADER | ash::vk::PipelineStageFlags::TRANSFER,
+            ash::vk::AccessFlags::SHADER_WRITE | ash::vk::AccessFlags::TRANSFER_WRITE,
+            ash::vk::PipelineStageFlags::TRANSFER,
+            ash::vk::AccessFlags::TRANSFER_READ
+        );
+        self.render_target.copy_to_buffer(&self.context.device, &cmd, &readback_buffer);
+        cmd.memory_barrier(
+            &self.context.device,
+            ash::vk::PipelineStageFlags::TRANSFER,
+            ash::vk::AccessFlags::TRANSFER_WRITE,
+            ash::vk::PipelineStageFlags::HOST,
+            ash::vk::AccessFlags::HOST_READ
+        );
+        cmd.end_label(&self.context.device);
+        utilities::end_single_queue_submit(&self.context.device, &self.context.graphics_command_pool, &self.context.graphics_queue, cmd)?;
+
+        // The render target is BGRA
+        let mut pixels = readback_buffer.read_bytes().to_vec();
+        pixels.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+
+        image::RgbaImage::from_raw(extent.width, extent.height, pixels).ok_or(VkError::InvalidImage("render target readback has the wrong size"))
+    }
+    pub fn wait_device_idle(&self) -> VkResult<()> {
         unsafe {
-            self.context.device.device_wait_idle().expect("Failed to wait device idle!");
+            self.context.device.device_wait_idle().or_fail_to("wait device idle")?;
         }
+
+        self.context.device.check_validation();
+        Ok(())
     }
     pub fn reset_render_target(&mut self) {
         self.should_reset_rt = true;
     }
-}
+    // Swaps in a pipeline built from new SPIR-V and restarts accumulation, the current one stays on failure
+    pub fn reload_shader(&mut self, shader: ComputeShader, spirv: &[u8]) -> VkResult<()> {
+        self.wait_device_idle()?;
 
-impl Drop for Renderer {
-    fn drop(&mut self) {
-        self.render_target.destroy(&self.context.device, self.context.allocator.as_mut().unwrap());
+        let pipeline = VkComputePipeline::new(
+            &self.context.device,
+            spirv,
+            &vec![self.descriptor_sets[0].layout],
+            &vec![]
+        )?;
+        pipeline.set_name(shader.name());
 
-        for descriptor_set in self.descriptor_sets.iter() {
-            self.context.descriptor_pool.deallocate(&self.context.device, descriptor_set);
-        }
-        
-        for camera_buffer in self.camera_buffers.iter_mut() {
-            camera_buffer.destroy(&self.context.device, self.context.allocator.as_mut().unwrap());
+        match shader {
+            ComputeShader::Main => self.compute_pipeline = pipeline,
+            ComputeShader::Preview => self.preview_pipeline = pipeline,
         }
 
-        self.scene_buffer.destroy(&self.context.device, self.context.allocator.as_mut().unwrap());
+        self.reset_render_target();
+        Ok(())
+    }
+}
 
-        for fence in &self.in_flight_fences {
-            fence.destroy(&self.context.device);
-        }
-        for semaphore in &self.render_finished_semaphores {
-            semaphore.destroy(&self.context.device);
-        }
-        for semaphore in &self.image_available_semaphores {
-            semaphore.destroy(&self.context.device);
+impl Drop for Renderer {
+    // The other resources free themselves once the GPU is done with them, the swapchain isn't owned through the device
+    fn drop(&mut self) {
+        // Nothing left to recover when this fails while tearing down, validation errors are checked when the device goes
+        unsafe {
+            let _ = self.context.device.device_wait_idle();
         }
 
-        self.preview_pipeline.destroy(&self.context.device);
-        self.compute_pipeline.destroy(&self.context.device);
-        self.swapchain.destroy(&self.context.device);
+        if let Some(swapchain) = &self.swapchain {
+            swapchain.destroy(&self.context.device);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,343 @@
+pub const BLUE_NOISE_SIZE: usize = 64;
+// Samples main.comp takes for every pixel each frame, the preview takes one
+pub const SAMPLES_PER_PIXEL: u32 = 80;
+
+// Sampler types as the shaders see them, see SamplerType::to_raw
+pub(crate) const SAMPLER_RANDOM: u32 = 0;
+pub(crate) const SAMPLER_SOBOL: u32 = 1;
+pub(crate) const SAMPLER_BLUE_NOISE: u32 = 2;
+
+// Constants of the hashes in shaders/random.glsl and shaders/sampler.glsl. The shaders get them from scene.glsl
+// and the CPU ports in the tests use them as well, so the tests check the constants the GPU runs with.
+pub(crate) const HASH_XOR: u32 = 2747636419;
+pub(crate) const HASH_MULTIPLIER: u32 = 2654435769;
+pub(crate) const LAINE_KARRAS_MULTIPLIERS: [u32; 4] = [0x6c50b47c, 0xb82f1e52, 0xc7afe638, 0x8d22f6e6];
+// Decorrelates the Sobol scrambles of the dimensions
+pub(crate) const SOBOL_DIMENSION_SEED: u32 = 0x9e3779b9;
+// Decorrelates forked samplers from the one they were forked from
+pub(crate) const FORK_SEED: u32 = 0x5bd1e995;
+// Fraction of the golden ratio in 0.32 fixed point, each sample index shifts the blue noise by this much
+pub(crate) const BLUE_NOISE_STEP: u32 = 0x9e3779b9;
+
+const BLUE_NOISE_SIGMA: f32 = 1.5;
+const BLUE_NOISE_INITIAL_DENSITY: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerType {
+    Random,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerType {
+    pub fn to_raw(self) -> u32 {
+        match self {
+            SamplerType::Random => SAMPLER_RANDOM,
+            SamplerType::Sobol => SAMPLER_SOBOL,
+            SamplerType::BlueNoise => SAMPLER_BLUE_NOISE,
+        }
+    }
+
+    pub fn next(self) -> SamplerType {
+        match self {
+            SamplerType::Random => SamplerType::Sobol,
+            SamplerType::Sobol => SamplerType::BlueNoise,
+            SamplerType::BlueNoise => SamplerType::Random,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerType::Random => "Random",
+            SamplerType::Sobol => "Owen-scrambled Sobol",
+            SamplerType::BlueNoise => "Blue noise",
+        }
+    }
+}
+
+// Void-and-cluster (Ulichney 1993) dither array of `size * size` ranks on a torus.
+// Every rank in 0..size*size appears exactly once, so thresholding the table at any
+// level yields a well spread point set.
+pub fn generate_blue_noise(size: usize) -> Vec<u32> {
+    let count = size * size;
+
+    // Gaussian energy kernel, separable and wrapped around the torus
+    let kernel: Vec<f32> = (0..size).map(|d| {
+        let d = d.min(size - d) as f32;
+        (-d * d / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp()
+    }).collect();
+
+    let mut pattern = vec![false; count];
+    let mut energy = vec![0.0f32; count];
+
+    let toggle = |pattern: &mut Vec<bool>, energy: &mut Vec<f32>, index: usize| {
+        let sign = if pattern[index] { -1.0 } else { 1.0 };
+        pattern[index] = !pattern[index];
+
+        let (px, py) = (index % size, index / size);
+        for y in 0..size {
+            let ky = kernel[(y + size - py) % size];
+            for x in 0..size {
+                energy[y * size + x] += sign * ky * kernel[(x + size - px) % size];
+            }
+        }
+    };
+
+    let tightest_cluster = |pattern: &Vec<bool>, energy: &Vec<f32>| -> usize {
+        (0..count).filter(|&i| pattern[i]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |pattern: &Vec<bool>, energy: &Vec<f32>| -> usize {
+        (0..count).filter(|&i| !pattern[i]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    // Deterministic white noise seed pattern
+    let initial_ones = ((count as f32 * BLUE_NOISE_INITIAL_DENSITY) as usize).max(1);
+    let mut state = 0x9E3779B9u32;
+    let mut placed = 0;
+    while placed < initial_ones {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+
+        let index = state as usize % count;
+        if !pattern[index] {
+            toggle(&mut pattern, &mut energy, index);
+            placed += 1;
+        }
+    }
+
+    // Relax the seed pattern until moving the tightest cluster would not change anything
+    loop {
+        let cluster = tightest_cluster(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, cluster);
+
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0u32; count];
+
+    // Rank the seed points by removing clusters one by one
+    let (mut phase_pattern, mut phase_energy) = (pattern.clone(), energy.clone());
+    for rank in (0..initial_ones).rev() {
+        let cluster = tightest_cluster(&phase_pattern, &phase_energy);
+        toggle(&mut phase_pattern, &mut phase_energy, cluster);
+        ranks[cluster] = rank as u32;
+    }
+
+    // Rank everything else by filling the largest voids. Past the half-way point this is
+    // equivalent to removing the tightest clusters of the minority (empty) pixels.
+    for rank in initial_ones..count {
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+        ranks[void] = rank as u32;
+    }
+
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CPU ports of the sampling code in shaders/sampler.glsl and shaders/random.glsl
+
+    fn hash(seed: &mut u32) {
+        *seed ^= HASH_XOR;
+        *seed = seed.wrapping_mul(HASH_MULTIPLIER);
+        *seed ^= *seed >> 16;
+        *seed = seed.wrapping_mul(HASH_MULTIPLIER);
+        *seed ^= *seed >> 16;
+        *seed = seed.wrapping_mul(HASH_MULTIPLIER);
+    }
+
+    // The offset BlueNoise adds for a sample index, keeping the top 24 bits that fit into a float exactly
+    fn blue_noise_step(index: u32) -> f32 {
+        (index.wrapping_mul(BLUE_NOISE_STEP) >> 8) as f32 / 16777216.0
+    }
+
+    fn random_float(seed: &mut u32) -> f32 {
+        hash(seed);
+        *seed as f32 / 4294967295.0
+    }
+
+    fn sobol_2d(index: u32) -> (u32, u32) {
+        let mut v = 1u32 << 31;
+        let mut y = 0;
+        let mut i = index;
+        while i != 0 {
+            if i & 1 != 0 {
+                y ^= v;
+            }
+            v ^= v >> 1;
+            i >>= 1;
+        }
+
+        (index.reverse_bits(), y)
+    }
+
+    fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+        x = x.wrapping_add(seed);
+        for multiplier in LAINE_KARRAS_MULTIPLIERS {
+            x ^= x.wrapping_mul(multiplier);
+        }
+        x
+    }
+
+    fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+        laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+    }
+
+    fn shuffled_scrambled_sobol_2d(index: u32, mut seed: u32) -> (u32, u32) {
+        let (x, y) = sobol_2d(nested_uniform_scramble(index, seed));
+
+        hash(&mut seed);
+        let x = nested_uniform_scramble(x, seed);
+        hash(&mut seed);
+        let y = nested_uniform_scramble(y, seed);
+
+        (x, y)
+    }
+
+    // Every elementary interval of volume 1/2^m must hold exactly one of the first 2^m points
+    fn assert_02_net(points: &[(u32, u32)], m: u32) {
+        for x_bits in 0..=m {
+            let y_bits = m - x_bits;
+            let mut cells = vec![0; 1 << m];
+
+            for &(x, y) in points {
+                let cx = if x_bits == 0 { 0 } else { x >> (32 - x_bits) };
+                let cy = if y_bits == 0 { 0 } else { y >> (32 - y_bits) };
+                cells[((cy << x_bits) | cx) as usize] += 1;
+            }
+
+            assert!(cells.iter().all(|&c| c == 1), "Not stratified in {}x{} intervals", 1 << x_bits, 1 << y_bits);
+        }
+    }
+
+    // The shaders have to use the names from scene.glsl, or the ports above could drift from them
+    #[test]
+    fn shaders_take_the_constants_from_scene_glsl() {
+        let shaders = [include_str!("../shaders/random.glsl"), include_str!("../shaders/sampler.glsl")].concat().to_lowercase();
+        let constants = [HASH_XOR, HASH_MULTIPLIER, SOBOL_DIMENSION_SEED, FORK_SEED, BLUE_NOISE_STEP].into_iter().chain(LAINE_KARRAS_MULTIPLIERS);
+
+        for constant in constants {
+            assert!(!shaders.contains(&constant.to_string()), "{} is spelled out in the shaders", constant);
+            assert!(!shaders.contains(&format!("{:#x}", constant)), "{:#x} is spelled out in the shaders", constant);
+        }
+        assert!(!shaders.contains("#define sampler_"), "The sampler types are defined in the shaders");
+    }
+
+    #[test]
+    fn sobol_is_02_net() {
+        let points: Vec<(u32, u32)> = (0..256).map(sobol_2d).collect();
+        assert_02_net(&points, 8);
+    }
+
+    #[test]
+    fn scrambled_sobol_stays_02_net() {
+        for seed in [0u32, 1, 0xdeadbeef, 123456789] {
+            let points: Vec<(u32, u32)> = (0..256).map(|i| shuffled_scrambled_sobol_2d(i, seed)).collect();
+            assert_02_net(&points, 8);
+
+            // Later blocks of 2^m points are nets as well, which is what progressive rendering relies on
+            let points: Vec<(u32, u32)> = (256..512).map(|i| shuffled_scrambled_sobol_2d(i, seed)).collect();
+            assert_02_net(&points, 8);
+        }
+    }
+
+    #[test]
+    fn random_is_uniform() {
+        const BINS: usize = 16;
+        const SAMPLES: usize = 1 << 16;
+
+        let mut seed = 1234;
+        let mut bins = [0usize; BINS];
+        for _ in 0..SAMPLES {
+            let value = random_float(&mut seed);
+            assert!((0.0..=1.0).contains(&value));
+            bins[((value * BINS as f32) as usize).min(BINS - 1)] += 1;
+        }
+
+        let expected = (SAMPLES / BINS) as f32;
+        let chi_squared: f32 = bins.iter().map(|&b| (b as f32 - expected).powi(2) / expected).sum();
+
+        // 15 degrees of freedom, p = 0.001
+        assert!(chi_squared < 37.7, "chi^2 = {}", chi_squared);
+    }
+
+    #[test]
+    fn blue_noise_is_a_permutation() {
+        let ranks = generate_blue_noise(BLUE_NOISE_SIZE);
+
+        let mut seen = vec![false; ranks.len()];
+        for &rank in ranks.iter() {
+            assert!(!seen[rank as usize]);
+            seen[rank as usize] = true;
+        }
+    }
+
+    fn mean_nearest_neighbour_distance(ranks: &[u32], size: usize, count: u32) -> f32 {
+        let points: Vec<(i32, i32)> = (0..ranks.len()).filter(|&i| ranks[i] < count).map(
+            |i| ((i % size) as i32, (i / size) as i32)
+        ).collect();
+
+        points.iter().map(|&(ax, ay)| {
+            points.iter().filter(|&&b| b != (ax, ay)).map(|&(bx, by)| {
+                let dx = (ax - bx).abs().min(size as i32 - (ax - bx).abs());
+                let dy = (ay - by).abs().min(size as i32 - (ay - by).abs());
+                ((dx * dx + dy * dy) as f32).sqrt()
+            }).fold(f32::MAX, f32::min)
+        }).sum::<f32>() / points.len() as f32
+    }
+
+    #[test]
+    fn blue_noise_is_well_spread() {
+        let size = BLUE_NOISE_SIZE;
+        let ranks = generate_blue_noise(size);
+
+        // White noise reference: the same ranks in a random order
+        let mut white_noise = ranks.clone();
+        let mut seed = 42;
+        for i in (1..white_noise.len()).rev() {
+            hash(&mut seed);
+            white_noise.swap(i, seed as usize % (i + 1));
+        }
+
+        // Sparse thresholds of the table should keep their points further apart than white noise does.
+        // Denser ones are dominated by the pixel grid and say little about the distribution.
+        for density in [0.02f32, 0.05, 0.125] {
+            let count = (ranks.len() as f32 * density) as u32;
+
+            let blue = mean_nearest_neighbour_distance(&ranks, size, count);
+            let white = mean_nearest_neighbour_distance(&white_noise, size, count);
+            assert!(blue > 1.3 * white, "density {}: blue noise {} vs white noise {}", density, blue, white);
+        }
+    }
+
+    // A float index runs out of fraction bits after a few hundred thousand samples, the fixed point one never does
+    #[test]
+    fn blue_noise_steps_stay_spread_out() {
+        let golden_ratio = (5.0f64.sqrt() - 1.0) / 2.0;
+        for index in [1u32, 80, 12345] {
+            // The step itself is rounded to 32 bits, its error grows with the index
+            let exact = (index as f64 * golden_ratio).fract();
+            let tolerance = (index as f64 + 1.0) / 4294967296.0 + 1.0 / 16777216.0;
+            assert!((blue_noise_step(index) as f64 - exact).abs() < tolerance, "index {}: {} vs {}", index, blue_noise_step(index), exact);
+        }
+
+        // Consecutive indices deep into a progressive render still split 0..1 into similar gaps
+        let count = 1000;
+        let first = 100_000 * SAMPLES_PER_PIXEL;
+        let mut steps: Vec<f32> = (first..first + count).map(blue_noise_step).collect();
+        steps.sort_by(f32::total_cmp);
+        steps.push(steps[0] + 1.0);
+
+        let largest_gap = steps.windows(2).map(|pair| pair[1] - pair[0]).fold(0.0, f32::max);
+        assert!(largest_gap < 3.0 / count as f32, "largest gap {}", largest_gap);
+    }
+}
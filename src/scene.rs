@@ -1,16 +1,39 @@
+use cgmath::SquareMatrix;
+
+use crate::bvh::{self, BvhNode};
 use crate::material::*;
+use crate::medium::*;
+use crate::primitive::*;
+use crate::csg::*;
+use crate::sdf::*;
+use crate::vk::error::*;
+
+pub(crate) const MAX_MESHES: usize = 64;
+pub(crate) const MAX_VERTICES: usize = 1024*4;
+pub(crate) const MAX_INDICES: usize = 1024*16;
+// A mesh never has more nodes than triangles, see bvh::build, Scene::new checks it anyway
+pub(crate) const MAX_BVH_NODES: usize = MAX_INDICES / 3;
+pub(crate) const MAX_MATERIALS: usize = 64;
+pub(crate) const MAX_SPHERES: usize = 64;
+pub(crate) const MAX_MEDIA: usize = 16;
+pub(crate) const MAX_PRIMITIVES: usize = 64;
+pub(crate) const MAX_CSG_NODES: usize = 128;
+pub(crate) const MAX_CSG_OBJECTS: usize = 16;
+pub(crate) const MAX_SDF_INSTRUCTIONS: usize = 128;
+pub(crate) const MAX_SDF_OBJECTS: usize = 16;
+pub(crate) const MAX_OPACITY_MASKS: usize = 16;
+pub(crate) const MAX_OPACITY_TEXELS: usize = 512*512;
 
-const MAX_MESHES: usize = 64;
-const MAX_VERTICES: usize = 1024*4;
-const MAX_INDICES: usize = 1024*16;
-const MAX_MATERIALS: usize = 64;
-const MAX_SPHERES: usize = 64;
-
-#[repr(C)]
-#[derive(Clone, Debug, Copy, Default)]
-pub struct Vertex {
-    position: [f32; 4],
-    normal: [f32; 4],
+// Marks objects without an interior medium, rays inside them travel through the fog
+pub const NO_MEDIUM: u32 = u32::MAX;
+
+// The texture coordinates are packed into the w components of the position and normal
+gpu_struct! {
+    #[derive(Clone, Debug, Copy, Default)]
+    pub struct Vertex as "Vertex" {
+        position: [f32; 4] => "vec4 position",
+        normal: [f32; 4] => "vec4 normal",
+    }
 }
 
 impl Vertex {
@@ -26,15 +49,23 @@ impl Vertex {
             normal,
         } 
     }
+
+    pub fn position(&self) -> cgmath::Vector3<f32> {
+        cgmath::vec3(self.position[0], self.position[1], self.position[2])
+    }
 }
 
-#[repr(C)]
-#[derive(Clone, Debug, Copy, Default)]
-pub struct Mesh {
-    vertex_count: u32,
-    index_count: u32,
-    first_index: u32,
-    material_index: u32,
+gpu_struct! {
+    #[derive(Clone, Debug, Copy, Default)]
+    pub struct Mesh as "Mesh" {
+        vertex_count: u32 => "uint vertexCount",
+        index_count: u32 => "uint indexCount",
+        first_index: u32 => "uint firstIndex",
+        material_index: u32 => "uint materialIndex",
+        medium: [u32; 4] => "uint medium",
+        // Rays are moved into the space of the vertices instead of transforming every vertex
+        world_to_object: [[f32; 4]; 4] => "mat4 worldToObject",
+    }
 }
 
 impl Mesh {
@@ -43,17 +74,33 @@ impl Mesh {
             vertex_count,
             index_count,
             first_index,
-            material_index
+            material_index,
+            medium: [NO_MEDIUM; 4],
+            world_to_object: cgmath::Matrix4::identity().into(),
         } 
     }
+
+    // The mesh has to be closed for the medium to be entered and left properly
+    pub fn with_medium(mut self, medium: u32) -> Mesh {
+        self.medium = [medium; 4];
+        self
+    }
+
+    // Places the vertices in the world, they are used as they are by default. None when the transform can't be inverted
+    pub fn with_transform(mut self, object_to_world: cgmath::Matrix4<f32>) -> Option<Mesh> {
+        self.world_to_object = object_to_world.invert()?.into();
+        Some(self)
+    }
 }
 
-#[repr(C)]
-#[derive(Clone, Debug, Copy, Default)]
-pub struct Sphere {
-    position: [f32; 3],
-    radius: f32,
-    material: [u32; 4]
+gpu_struct! {
+    #[derive(Clone, Debug, Copy, Default)]
+    pub struct Sphere as "Sphere" {
+        position: [f32; 3] => "vec3 position",
+        radius: f32 => "float radius",
+        material: [u32; 1] => "uint material",
+        medium: [u32; 3] => "uint medium",
+    }
 }
 
 impl Sphere {
@@ -61,69 +108,338 @@ impl Sphere {
         Sphere { 
             position: position.into(),
             radius,
-            material: [material; 4] 
+            material: [material; 1],
+            medium: [NO_MEDIUM; 3],
         }
     }
+
+    pub fn with_medium(mut self, medium: u32) -> Sphere {
+        self.medium = [medium; 3];
+        self
+    }
 }
 
-#[repr(C)]
-#[derive(Clone, Debug, Copy)]
-pub struct Scene {
-    materials: [MaterialRaw; MAX_MATERIALS],
-    spheres: [Sphere; MAX_SPHERES],
-    sphere_count: [u32; 4],
+gpu_struct! {
+    #[derive(Clone, Debug, Copy)]
+    pub struct Scene as "WorldBuffer" {
+        materials: [MaterialRaw; MAX_MATERIALS] => "Material materials[MAX_MATERIALS]",
+        spheres: [Sphere; MAX_SPHERES] => "Sphere spheres[MAX_SPHERES]",
+        sphere_count: [u32; 4] => "uint sphereCount",
+
+        vertices: [Vertex; MAX_VERTICES] => "Vertex vertices[MAX_VERTICES]",
+        indices: [u32; MAX_INDICES] => "uint indices[MAX_INDICES]",
+
+        meshes: [Mesh; MAX_MESHES] => "Mesh meshes[MAX_MESHES]",
+        mesh_count: [u32; 4] => "uint meshCount",
+
+        // Built from the vertices by Scene::new, Renderer::update_mesh leaves them alone
+        bvh_nodes: [BvhNode; MAX_BVH_NODES] => "BvhNode bvhNodes[MAX_BVH_NODES]",
+        bvh_roots: [u32; MAX_MESHES] => "uint bvhRoots[MAX_MESHES]",
+
+        media: [MediumRaw; MAX_MEDIA] => "Medium media[MAX_MEDIA]",
+        fog: MediumRaw => "Medium fog",
+
+        primitives: [PrimitiveRaw; MAX_PRIMITIVES] => "Primitive primitives[MAX_PRIMITIVES]",
+        primitive_count: [u32; 4] => "uint primitiveCount",
 
-    vertices: [Vertex; MAX_VERTICES],
-    indices: [u32; MAX_INDICES],
+        csg_nodes: [PrimitiveRaw; MAX_CSG_NODES] => "Primitive csgNodes[MAX_CSG_NODES]",
+        csg_objects: [CsgObjectRaw; MAX_CSG_OBJECTS] => "CsgObject csgObjects[MAX_CSG_OBJECTS]",
+        csg_object_count: [u32; 4] => "uint csgObjectCount",
 
-    meshes: [Mesh; MAX_MESHES],
-    mesh_count: [u32; 4],
+        sdf_instructions: [SdfInstructionRaw; MAX_SDF_INSTRUCTIONS] => "SdfInstruction sdfInstructions[MAX_SDF_INSTRUCTIONS]",
+        sdf_objects: [SdfObjectRaw; MAX_SDF_OBJECTS] => "SdfObject sdfObjects[MAX_SDF_OBJECTS]",
+        sdf_object_count: [u32; 4] => "uint sdfObjectCount",
+
+        opacity_masks: [OpacityMaskRaw; MAX_OPACITY_MASKS] => "uvec4 opacityMasks[MAX_OPACITY_MASKS]",
+        // Four 8 bit texels per element
+        opacity_texels: [u32; MAX_OPACITY_TEXELS / 4] => "uint opacityTexels[MAX_OPACITY_TEXELS / 4]",
+    }
 }
 
 impl Scene {
-    pub fn new(materials: Vec<Material>, spheres: Vec<Sphere>, vertices: Vec<Vertex>, indices: Vec<u32>, meshes: Vec<Mesh>) -> Scene{
-        let mut raw_materials = [MaterialRaw::default(); MAX_MATERIALS];
+    // Boxed, a Scene is too large to be built and moved around on the stack
+    pub fn new(materials: Vec<Material>, media: Vec<Medium>, spheres: Vec<Sphere>, primitives: Vec<Primitive>, vertices: Vec<Vertex>, indices: Vec<u32>, meshes: Vec<Mesh>) -> VkResult<Box<Scene>> {
+        let counts = [
+            ("materials", materials.len(), MAX_MATERIALS),
+            ("media", media.len(), MAX_MEDIA),
+            ("spheres", spheres.len(), MAX_SPHERES),
+            ("primitives", primitives.len(), MAX_PRIMITIVES),
+            ("vertices", vertices.len(), MAX_VERTICES),
+            ("indices", indices.len(), MAX_INDICES),
+            ("meshes", meshes.len(), MAX_MESHES),
+        ];
+        if let Some(&(what, _, capacity)) = counts.iter().find(|(_, count, capacity)| count > capacity) {
+            return Err(VkError::SceneCapacity { what, capacity });
+        }
+        // Indices past the end of the arrays would be read by the BVH builder
+        if indices.iter().any(|&index| index as usize >= MAX_VERTICES) {
+            return Err(VkError::SceneCapacity { what: "vertices", capacity: MAX_VERTICES });
+        }
+        if meshes.iter().any(|mesh| mesh.first_index as usize + mesh.index_count as usize > MAX_INDICES) {
+            return Err(VkError::SceneCapacity { what: "indices", capacity: MAX_INDICES });
+        }
+
+        // Every field is made of u32s and f32s, for which all zero bits are valid
+        let mut scene = unsafe { Box::<Scene>::new_zeroed().assume_init() };
+        let raw = &mut *scene;
+
+        raw.materials = [MaterialRaw::default(); MAX_MATERIALS];
+        for (raw_material, material) in raw.materials.iter_mut().zip(materials) {
+            *raw_material = material.to_raw();
+        }
+        for (raw_medium, medium) in raw.media.iter_mut().zip(media) {
+            *raw_medium = medium.to_raw();
+        }
+
+        raw.spheres[..spheres.len()].copy_from_slice(&spheres);
+        raw.sphere_count = [spheres.len() as u32; 4];
+
+        for (raw_primitive, primitive) in raw.primitives.iter_mut().zip(primitives.iter()) {
+            *raw_primitive = primitive.to_raw();
+        }
+        raw.primitive_count = [primitives.len() as u32; 4];
+
+        raw.vertices[..vertices.len()].copy_from_slice(&vertices);
+        raw.indices[..indices.len()].copy_from_slice(&indices);
+
+        raw.meshes[..meshes.len()].copy_from_slice(&meshes);
+        raw.mesh_count = [meshes.len() as u32; 4];
+
+        // Meshes sharing their indices share the hierarchy too, building it again would reorder the triangles under the first one
+        let mut node_count = 0;
+        for i in 0..meshes.len() {
+            let (first_index, index_count) = (meshes[i].first_index, meshes[i].index_count);
+            if let Some(shared) = (0..i).find(|&j| meshes[j].first_index == first_index && meshes[j].index_count == index_count) {
+                raw.bvh_roots[i] = raw.bvh_roots[shared];
+                continue;
+            }
+
+            let indices = &mut raw.indices[first_index as usize..(first_index + index_count) as usize];
+            let nodes = bvh::build(&raw.vertices, indices, first_index, node_count as u32);
+            if node_count + nodes.len() > MAX_BVH_NODES {
+                return Err(VkError::SceneCapacity { what: "BVH nodes", capacity: MAX_BVH_NODES });
+            }
+
+            raw.bvh_nodes[node_count..node_count + nodes.len()].copy_from_slice(&nodes);
+            raw.bvh_roots[i] = node_count as u32;
+            node_count += nodes.len();
+        }
+
+        Ok(scene)
+    }
+
+    // Over all meshes, instances count once per mesh that uses them
+    pub fn triangle_count(&self) -> u32 {
+        self.meshes[..self.mesh_count[0] as usize].iter().map(|mesh| mesh.index_count / 3).sum()
+    }
+
+    // Homogeneous medium filling all the space that isn't inside an object with its own medium
+    pub fn set_fog(&mut self, fog: Medium) {
+        self.fog = fog.to_raw();
+    }
+
+    pub fn add_csg(&mut self, object: CsgObject) -> VkResult<()> {
+        let count = self.csg_object_count[0] as usize;
+        let first_node = if count > 0 { self.csg_objects[count - 1].end() } else { 0 };
+
+        if count == MAX_CSG_OBJECTS {
+            return Err(VkError::SceneCapacity { what: "CSG objects", capacity: MAX_CSG_OBJECTS });
+        }
+
+        let (raw, nodes) = object.to_raw(first_node)?;
+        if raw.end() as usize > MAX_CSG_NODES {
+            return Err(VkError::SceneCapacity { what: "CSG nodes", capacity: MAX_CSG_NODES });
+        }
+
+        self.csg_nodes[first_node as usize..raw.end() as usize].copy_from_slice(&nodes);
+        self.csg_objects[count] = raw;
 
-        let mut raw_spheres = [Sphere::default(); MAX_SPHERES];
-        let sphere_count = spheres.len();
+        self.csg_object_count = [count as u32 + 1; 4];
+        Ok(())
+    }
 
-        let mut raw_vertices = [Vertex::default(); MAX_VERTICES];
-        let mut raw_indices = [0; MAX_INDICES];
+    pub fn add_sdf(&mut self, object: SdfObject) -> VkResult<()> {
+        let count = self.sdf_object_count[0] as usize;
+        let first_instruction = if count > 0 { self.sdf_objects[count - 1].end() } else { 0 };
 
-        let mut raw_meshes = [Mesh::default(); MAX_MESHES];
-        let mesh_count = meshes.len();
+        if count == MAX_SDF_OBJECTS {
+            return Err(VkError::SceneCapacity { what: "SDF objects", capacity: MAX_SDF_OBJECTS });
+        }
 
-        for (i, material) in materials.into_iter().enumerate() {
-            raw_materials[i] = match material {
-                Material::Lambertian(material) => material.to_raw(),
-                Material::Metal(material) => material.to_raw(),
-                Material::Dielectric(material) => material.to_raw(),
-                Material::Emmisive(material) => material.to_raw(),
-            };
+        let (raw, instructions) = object.to_raw(first_instruction)?;
+        if raw.end() as usize > MAX_SDF_INSTRUCTIONS {
+            return Err(VkError::SceneCapacity { what: "SDF instructions", capacity: MAX_SDF_INSTRUCTIONS });
         }
 
-        for (i, sphere) in spheres.into_iter().enumerate() {
-            raw_spheres[i] = sphere;
+        self.sdf_instructions[first_instruction as usize..raw.end() as usize].copy_from_slice(&instructions);
+        self.sdf_objects[count] = raw;
+
+        self.sdf_object_count = [count as u32 + 1; 4];
+        Ok(())
+    }
+
+    // Returns the index to use in Opacity::Mask
+    pub fn add_opacity_mask(&mut self, mask: &OpacityMask) -> VkResult<u32> {
+        if mask.width == 0 || mask.height == 0 || mask.texels.len() != (mask.width * mask.height) as usize {
+            return Err(VkError::InvalidImage("opacity masks need width * height texels"));
         }
-        for (i, vertex) in vertices.into_iter().enumerate() {
-            raw_vertices[i] = vertex;
+
+        let count = self.opacity_masks.iter().take_while(|mask| mask.end() > 0).count();
+        let first_texel = if count > 0 { self.opacity_masks[count - 1].end() } else { 0 };
+
+        if count == MAX_OPACITY_MASKS {
+            return Err(VkError::SceneCapacity { what: "opacity masks", capacity: MAX_OPACITY_MASKS });
         }
-        for (i, index) in indices.into_iter().enumerate() {
-            raw_indices[i] = index;
+        if first_texel as usize + mask.texels.len() > MAX_OPACITY_TEXELS {
+            return Err(VkError::SceneCapacity { what: "opacity mask texels", capacity: MAX_OPACITY_TEXELS });
         }
 
-        for (i, mesh) in meshes.into_iter().enumerate() {
-            raw_meshes[i] = mesh;
+        for (i, &texel) in mask.texels.iter().enumerate() {
+            let index = first_texel as usize + i;
+            self.opacity_texels[index / 4] |= (texel as u32) << (8 * (index % 4));
         }
-        
-        Scene {
-            materials: raw_materials,
-            spheres: raw_spheres,
-            sphere_count: [sphere_count as u32; 4],
-            vertices: raw_vertices,
-            indices: raw_indices,
-            meshes: raw_meshes,
-            mesh_count: [mesh_count as u32; 4]
+        self.opacity_masks[count] = mask.to_raw(first_texel);
+
+        Ok(count as u32)
+    }
+
+    pub fn materials(&self) -> &[MaterialRaw; MAX_MATERIALS] {
+        &self.materials
+    }
+
+    pub fn set_opacity(&mut self, material: u32, opacity: Opacity) {
+        self.materials[material as usize].set_opacity(opacity);
+    }
+
+    pub fn set_double_sided(&mut self, material: u32, double_sided: bool) {
+        self.materials[material as usize].set_double_sided(double_sided);
+    }
+
+    // Byte offsets of single elements in the scene buffer, for uploading just those
+    pub fn material_offset(index: usize) -> usize {
+        assert!(index < MAX_MATERIALS);
+        std::mem::offset_of!(Scene, materials) + index * std::mem::size_of::<MaterialRaw>()
+    }
+    pub fn sphere_offset(index: usize) -> usize {
+        assert!(index < MAX_SPHERES);
+        std::mem::offset_of!(Scene, spheres) + index * std::mem::size_of::<Sphere>()
+    }
+    pub fn mesh_offset(index: usize) -> usize {
+        assert!(index < MAX_MESHES);
+        std::mem::offset_of!(Scene, meshes) + index * std::mem::size_of::<Mesh>()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_scene() -> Box<Scene> {
+        Scene::new(vec![], vec![], vec![], vec![], vec![], vec![], vec![]).unwrap()
+    }
+
+    #[test]
+    fn scene_arrays_have_to_fit() {
+        let spheres = vec![Sphere::new(cgmath::vec3(0.0, 0.0, 0.0), 1.0, 0); MAX_SPHERES + 1];
+        let vertices = vec![Vertex::default(); 3];
+        let meshes = vec![Mesh::new(3, 3, 0, 0); MAX_MESHES + 1];
+
+        assert!(Scene::new(vec![], vec![], spheres[..MAX_SPHERES].to_vec(), vec![], vec![], vec![], vec![]).is_ok());
+        assert!(matches!(Scene::new(vec![], vec![], spheres, vec![], vec![], vec![], vec![]), Err(VkError::SceneCapacity { what: "spheres", .. })));
+        assert!(matches!(Scene::new(vec![], vec![], vec![], vec![], vertices.clone(), vec![0, 1, 2], meshes), Err(VkError::SceneCapacity { what: "meshes", .. })));
+        assert!(matches!(
+            Scene::new(vec![], vec![], vec![], vec![], vertices.clone(), vec![0, 1, MAX_VERTICES as u32], vec![]),
+            Err(VkError::SceneCapacity { what: "vertices", .. })
+        ));
+        assert!(matches!(
+            Scene::new(vec![], vec![], vec![], vec![], vertices, vec![0, 1, 2], vec![Mesh::new(3, 3, MAX_INDICES as u32 - 1, 0)]),
+            Err(VkError::SceneCapacity { what: "indices", .. })
+        ));
+    }
+
+    #[test]
+    fn opacity_masks_have_to_fit() {
+        let mut scene = empty_scene();
+        let mask = |size: u32| OpacityMask { width: size, height: size, texels: vec![255; (size * size) as usize] };
+
+        assert_eq!(scene.add_opacity_mask(&mask(4)).unwrap(), 0);
+        assert_eq!(scene.add_opacity_mask(&mask(4)).unwrap(), 1);
+        assert!(matches!(scene.add_opacity_mask(&mask(512)), Err(VkError::SceneCapacity { .. })));
+        assert!(matches!(scene.add_opacity_mask(&OpacityMask { width: 2, height: 2, texels: vec![0; 3] }), Err(VkError::InvalidImage(_))));
+
+        for _ in 2..MAX_OPACITY_MASKS {
+            scene.add_opacity_mask(&mask(1)).unwrap();
+        }
+        assert!(matches!(scene.add_opacity_mask(&mask(1)), Err(VkError::SceneCapacity { .. })));
+    }
+
+    #[test]
+    fn csg_objects_have_to_fit() {
+        let mut scene = empty_scene();
+        let sphere = || CsgNode::Shape(Shape::Sphere { center: cgmath::vec3(0.0, 0.0, 0.0), radius: 1.0 });
+        // Balanced unions of eight spheres, 15 nodes and as deep as CSG_MAX_DEPTH allows
+        let pair = || CsgNode::union(sphere(), sphere());
+        let object = || CsgObject::new(CsgNode::union(CsgNode::union(pair(), pair()), CsgNode::union(pair(), pair())), 0);
+
+        for _ in 0..MAX_CSG_NODES / 15 {
+            scene.add_csg(object()).unwrap();
+        }
+        assert!(matches!(scene.add_csg(object()), Err(VkError::SceneCapacity { what: "CSG nodes", .. })));
+
+        for _ in MAX_CSG_NODES / 15..MAX_CSG_OBJECTS {
+            scene.add_csg(CsgObject::new(sphere(), 0)).unwrap();
+        }
+        assert!(matches!(scene.add_csg(CsgObject::new(sphere(), 0)), Err(VkError::SceneCapacity { what: "CSG objects", .. })));
+    }
+
+    #[test]
+    fn csg_objects_have_to_be_valid() {
+        let mut scene = empty_scene();
+        let sphere = || CsgNode::Shape(Shape::Sphere { center: cgmath::vec3(0.0, 0.0, 0.0), radius: 1.0 });
+        let disk = CsgNode::Shape(Shape::Disk { center: cgmath::vec3(0.0, 0.0, 0.0), normal: cgmath::vec3(0.0, 1.0, 0.0), radius: 1.0 });
+        // Every union of a sphere and a deeper subtree needs one more stack entry
+        let too_deep = (0..CSG_MAX_DEPTH).fold(sphere(), |node, _| CsgNode::union(sphere(), node));
+
+        assert!(matches!(scene.add_csg(CsgObject::new(CsgNode::union(sphere(), disk), 0)), Err(VkError::InvalidCsgOperand("disks"))));
+        assert!(matches!(scene.add_csg(CsgObject::new(too_deep, 0)), Err(VkError::SceneCapacity { what: "CSG stack", .. })));
+        assert_eq!(scene.csg_object_count[0], 0);
+    }
+
+    #[test]
+    fn sdf_objects_have_to_fit() {
+        let mut scene = empty_scene();
+        let sphere = || SdfNode::Sphere { center: cgmath::vec3(0.0, 0.0, 0.0), radius: 1.0 };
+        // 15 instructions each
+        let pair = || SdfNode::union(sphere(), sphere());
+        let object = || SdfObject::new(SdfNode::union(SdfNode::union(pair(), pair()), SdfNode::union(pair(), pair())), 0);
+
+        for _ in 0..MAX_SDF_INSTRUCTIONS / 15 {
+            scene.add_sdf(object()).unwrap();
+        }
+        assert!(matches!(scene.add_sdf(object()), Err(VkError::SceneCapacity { what: "SDF instructions", .. })));
+
+        for _ in MAX_SDF_INSTRUCTIONS / 15..MAX_SDF_OBJECTS {
+            scene.add_sdf(SdfObject::new(sphere(), 0)).unwrap();
         }
+        assert!(matches!(scene.add_sdf(SdfObject::new(sphere(), 0)), Err(VkError::SceneCapacity { what: "SDF objects", .. })));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sdf_objects_have_to_fit_the_stacks() {
+        let mut scene = empty_scene();
+        let sphere = || SdfNode::Sphere { center: cgmath::vec3(0.0, 0.0, 0.0), radius: 1.0 };
+        // Every union of a sphere and a deeper subtree needs one more stack entry
+        let too_deep = (0..SDF_MAX_DEPTH).fold(sphere(), |node, _| SdfNode::union(sphere(), node));
+        let too_nested = (0..=SDF_MAX_NESTING).fold(sphere(), |node, _| SdfNode::repeat(node, cgmath::vec3(4.0, 4.0, 4.0), [1, 1, 1]));
+
+        assert!(matches!(scene.add_sdf(SdfObject::new(too_deep, 0)), Err(VkError::SceneCapacity { what: "SDF stack", .. })));
+        assert!(matches!(scene.add_sdf(SdfObject::new(too_nested, 0)), Err(VkError::SceneCapacity { what: "nested SDF repetitions", .. })));
+        assert_eq!(scene.sdf_object_count[0], 0);
+    }
+
+    #[test]
+    fn singular_transforms_are_rejected() {
+        let mesh = Mesh::new(0, 0, 0, 0);
+        assert!(mesh.with_transform(cgmath::Matrix4::from_translation(cgmath::vec3(1.0, 2.0, 3.0))).is_some());
+        assert!(mesh.with_transform(cgmath::Matrix4::from_scale(0.0)).is_none());
+    }
+}
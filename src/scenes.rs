@@ -0,0 +1,381 @@
+use cgmath::InnerSpace;
+
+use crate::{
+    camera::Camera,
+    material::*,
+    medium::Medium,
+    obj_loader,
+    primitive::*,
+    scene::*,
+    vk::error::*,
+};
+
+// The model of the sphere scene, absolute so benchmarks and tests don't depend on the working directory
+pub const MODEL_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/res/model.obj");
+
+// The meshes are only rotated and moved
+const INVERTIBLE: &str = "Rotations and translations are invertible";
+
+// Scenes that come with OxiTrace, rendered by `oxitrace bench` and the image tests.
+// They only change together with their reference images and the benchmark history.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuiltinScene {
+    // The viewer's spheres and model on a ground plane
+    Spheres,
+    CornellBox,
+    // A torus with as many triangles as the scene buffer fits
+    HeavyMesh,
+    // Closed meshes seen through and from inside, one of them bounding a medium
+    Glass,
+}
+
+impl BuiltinScene {
+    pub const ALL: [BuiltinScene; 4] = [BuiltinScene::Spheres, BuiltinScene::CornellBox, BuiltinScene::HeavyMesh, BuiltinScene::Glass];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinScene::Spheres => "spheres",
+            BuiltinScene::CornellBox => "cornell-box",
+            BuiltinScene::HeavyMesh => "heavy-mesh",
+            BuiltinScene::Glass => "glass",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<BuiltinScene> {
+        Self::ALL.into_iter().find(|scene| scene.name() == name)
+    }
+
+    pub fn build(self) -> VkResult<Box<Scene>> {
+        match self {
+            BuiltinScene::Spheres => {
+                let (vertices, indices) = obj_loader::load_from_file(MODEL_PATH)?;
+                spheres(vertices, indices)
+            }
+            BuiltinScene::CornellBox => cornell_box(),
+            BuiltinScene::HeavyMesh => heavy_mesh(),
+            BuiltinScene::Glass => glass(),
+        }
+    }
+
+    pub fn camera(self, width: u32, height: u32) -> Camera {
+        let size = cgmath::vec2(width as f32, height as f32);
+
+        match self {
+            BuiltinScene::Spheres | BuiltinScene::HeavyMesh | BuiltinScene::Glass => Camera::new(cgmath::vec3(2.0, 0.5, 2.0), 15.0, 45.0, size, 80.0, 1.1),
+            BuiltinScene::CornellBox => Camera::new(cgmath::vec3(0.0, 1.0, 3.4), 0.0, 90.0, size, 80.0, 1.0),
+        }
+    }
+}
+
+// The mesh goes into the mirror material, the viewer passes the model it reloads from disk
+pub fn spheres(vertices: Vec<Vertex>, indices: Vec<u32>) -> VkResult<Box<Scene>> {
+    let spheres = vec![
+        Sphere::new(cgmath::vec3(50.0, 40.0, 50.0), 20.0, 5),
+        Sphere::new(cgmath::vec3(0.0, 0.0, 0.0), 0.5, 1),
+        Sphere::new(cgmath::vec3(-1.0, 0.0, 0.0), 0.5, 2),
+        Sphere::new(cgmath::vec3(1.0, 0.0, 0.0), 0.5, 3),
+    ];
+
+    let primitives = vec![
+        Primitive::new(Shape::Plane { point: cgmath::vec3(0.0, -0.5, 0.0), normal: cgmath::vec3(0.0, 1.0, 0.0) }, 0),
+    ];
+
+    let materials = vec![
+        Material::Lambertian(Lambertian {
+            color: cgmath::vec3(0.7, 0.7, 0.7)
+        }),
+        Material::Lambertian(Lambertian {
+            color: cgmath::vec3(0.9, 0.08, 0.1)
+        }),
+        Material::Metal(Metal {
+            color: cgmath::vec3(0.8, 0.8, 0.8),
+            fuzz: 0.3
+        }),
+        Material::Metal(Metal {
+            color: cgmath::vec3(0.8, 0.6, 0.2),
+            fuzz: 0.95
+        }),
+        Material::Metal(Metal {
+            color: cgmath::vec3(1.0, 1.0, 1.0),
+            fuzz: 0.0
+        }),
+        Material::Emmisive(Emmisive{
+            color: cgmath::vec3(1.0, 0.5, 0.2),
+            intensity: 80.0,
+        }),
+    ];
+
+    let meshes = if indices.is_empty() {
+        vec![]
+    } else {
+        vec![Mesh::new(vertices.len() as u32, indices.len() as u32, 0, 4)]
+    };
+
+    Scene::new(materials, vec![], spheres, primitives, vertices, indices, meshes)
+}
+
+// Two by two by two units with the open side facing +z, every wall faces into the box
+fn cornell_box() -> VkResult<Box<Scene>> {
+    let materials = vec![
+        Material::Lambertian(Lambertian { color: cgmath::vec3(0.73, 0.73, 0.73) }),
+        Material::Lambertian(Lambertian { color: cgmath::vec3(0.65, 0.05, 0.05) }),
+        Material::Lambertian(Lambertian { color: cgmath::vec3(0.12, 0.45, 0.15) }),
+        Material::Emmisive(Emmisive { color: cgmath::vec3(1.0, 0.85, 0.6), intensity: 15.0 }),
+    ];
+
+    let quad = |corner: [f32; 3], u: [f32; 3], v: [f32; 3], material: u32| {
+        Primitive::new(Shape::Quad { corner: corner.into(), u: u.into(), v: v.into() }, material)
+    };
+    let rotated_box = |center: [f32; 3], half_extents: [f32; 3], angle: f32, material: u32| {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let axes = [cgmath::vec3(cos, 0.0, -sin), cgmath::vec3(0.0, 1.0, 0.0), cgmath::vec3(sin, 0.0, cos)];
+        Primitive::new(Shape::OrientedBox { center: center.into(), half_extents: half_extents.into(), axes }, material)
+    };
+
+    let primitives = vec![
+        // Floor, ceiling and back wall
+        quad([-1.0, 0.0, -1.0], [0.0, 0.0, 2.0], [2.0, 0.0, 0.0], 0),
+        quad([-1.0, 2.0, -1.0], [2.0, 0.0, 0.0], [0.0, 0.0, 2.0], 0),
+        quad([-1.0, 0.0, -1.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0], 0),
+        // Left and right wall
+        quad([-1.0, 0.0, -1.0], [0.0, 2.0, 0.0], [0.0, 0.0, 2.0], 1),
+        quad([1.0, 0.0, -1.0], [0.0, 0.0, 2.0], [0.0, 2.0, 0.0], 2),
+        // Light just below the ceiling
+        quad([-0.25, 1.99, -0.25], [0.5, 0.0, 0.0], [0.0, 0.0, 0.5], 3),
+        rotated_box([-0.35, 0.6, -0.3], [0.3, 0.6, 0.3], 18.0, 0),
+        rotated_box([0.35, 0.3, 0.3], [0.3, 0.3, 0.3], -17.0, 0),
+    ];
+
+    Scene::new(materials, vec![], vec![], primitives, vec![], vec![], vec![])
+}
+
+fn heavy_mesh() -> VkResult<Box<Scene>> {
+    let materials = vec![
+        Material::Lambertian(Lambertian { color: cgmath::vec3(0.7, 0.7, 0.7) }),
+        Material::Metal(Metal { color: cgmath::vec3(0.8, 0.6, 0.2), fuzz: 0.2 }),
+        Material::Emmisive(Emmisive { color: cgmath::vec3(1.0, 0.5, 0.2), intensity: 80.0 }),
+    ];
+
+    let spheres = vec![
+        Sphere::new(cgmath::vec3(50.0, 40.0, 50.0), 20.0, 2),
+    ];
+
+    let primitives = vec![
+        Primitive::new(Shape::Plane { point: cgmath::vec3(0.0, -0.5, 0.0), normal: cgmath::vec3(0.0, 1.0, 0.0) }, 0),
+    ];
+
+    let (major_radius, minor_radius) = (0.6, 0.25);
+    let (vertices, indices) = torus(major_radius, minor_radius, 60, 40);
+
+    // Tilted towards the camera by 30 degrees and resting on the ground plane
+    let object_to_world = cgmath::Matrix4::from_translation(cgmath::vec3(0.0, major_radius * 0.5 + minor_radius - 0.5, 0.0))
+        * cgmath::Matrix4::from_angle_x(cgmath::Deg(30.0));
+    let meshes = vec![
+        Mesh::new(vertices.len() as u32, indices.len() as u32, 0, 1).with_transform(object_to_world).expect(INVERTIBLE)
+    ];
+
+    Scene::new(materials, vec![], spheres, primitives, vertices, indices, meshes)
+}
+
+// A tinted glass torus in front of a white and a red sphere, standing upright so rays pass through both of its sides,
+// next to a box of smoke bounded by an invisible mesh and a dispersive flint glass prism
+fn glass() -> VkResult<Box<Scene>> {
+    let materials = vec![
+        Material::Lambertian(Lambertian { color: cgmath::vec3(0.7, 0.7, 0.7) }),
+        Material::Dielectric(Dielectric {
+            color: cgmath::vec3(1.0, 1.0, 1.0),
+            ior: 1.5,
+            dispersion: Dispersion::None,
+            absorption: cgmath::vec3(1.2, 0.2, 0.6),
+        }),
+        Material::Lambertian(Lambertian { color: cgmath::vec3(0.9, 0.08, 0.1) }),
+        Material::Emmisive(Emmisive { color: cgmath::vec3(1.0, 0.5, 0.2), intensity: 80.0 }),
+        Material::Interface(Interface),
+        Material::Dielectric(Dielectric {
+            color: cgmath::vec3(1.0, 1.0, 1.0),
+            ior: 1.7,
+            dispersion: Dispersion::DENSE_FLINT,
+            absorption: cgmath::vec3(0.0, 0.0, 0.0),
+        }),
+    ];
+
+    let media = vec![
+        Medium { absorption: cgmath::vec3(0.2, 0.4, 0.8), scattering: cgmath::vec3(4.0, 4.0, 4.0), anisotropy: 0.3 },
+    ];
+
+    let spheres = vec![
+        Sphere::new(cgmath::vec3(50.0, 40.0, 50.0), 20.0, 3),
+        Sphere::new(cgmath::vec3(-0.6, -0.2, -0.8), 0.3, 0),
+        Sphere::new(cgmath::vec3(0.6, -0.2, -0.8), 0.3, 2),
+    ];
+
+    let primitives = vec![
+        Primitive::new(Shape::Plane { point: cgmath::vec3(0.0, -0.5, 0.0), normal: cgmath::vec3(0.0, 1.0, 0.0) }, 0),
+    ];
+
+    let (mut vertices, mut indices) = (vec![], vec![]);
+    let torus = add_mesh(&mut vertices, &mut indices, torus(0.35, 0.12, 32, 16), 1);
+
+    // Standing on its rim, facing the camera
+    let object_to_world = cgmath::Matrix4::from_translation(cgmath::vec3(0.0, 0.35 + 0.12 - 0.5, 0.0))
+        * cgmath::Matrix4::from_angle_y(cgmath::Deg(45.0))
+        * cgmath::Matrix4::from_angle_x(cgmath::Deg(90.0));
+    let smoke = add_mesh(&mut vertices, &mut indices, cuboid(cgmath::vec3(0.25, 0.25, 0.25)), 4).with_medium(0);
+    let prism = add_mesh(&mut vertices, &mut indices, prism(0.3, 0.4), 5);
+
+    let meshes = vec![
+        torus.with_transform(object_to_world).expect(INVERTIBLE),
+        smoke.with_transform(cgmath::Matrix4::from_translation(cgmath::vec3(-0.2, -0.25, 0.9))).expect(INVERTIBLE),
+        prism.with_transform(
+            cgmath::Matrix4::from_translation(cgmath::vec3(1.2, 0.2 - 0.5, -0.3)) * cgmath::Matrix4::from_angle_y(cgmath::Deg(30.0))
+        ).expect(INVERTIBLE),
+    ];
+
+    Scene::new(materials, media, spheres, primitives, vertices, indices, meshes)
+}
+
+// Adds the vertices and indices of one mesh to the ones of the scene, its indices are offset to match
+fn add_mesh(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, (mesh_vertices, mesh_indices): (Vec<Vertex>, Vec<u32>), material: u32) -> Mesh {
+    let (first_vertex, first_index) = (vertices.len() as u32, indices.len() as u32);
+    let mesh = Mesh::new(mesh_vertices.len() as u32, mesh_indices.len() as u32, first_index, material);
+
+    vertices.extend(mesh_vertices);
+    indices.extend(mesh_indices.into_iter().map(|index| first_vertex + index));
+
+    mesh
+}
+
+// Upright prism around the origin with an equilateral triangle of the given side as its cross section, flat shaded like `cuboid`
+fn prism(side: f32, height: f32) -> (Vec<Vertex>, Vec<u32>) {
+    let (mut vertices, mut indices) = (vec![], vec![]);
+
+    let radius = side / 3.0f32.sqrt();
+    let corners: Vec<cgmath::Vector3<f32>> = (0..3).map(|i| {
+        let angle = i as f32 * std::f32::consts::TAU / 3.0;
+        cgmath::vec3(radius * angle.cos(), 0.0, radius * angle.sin())
+    }).collect();
+    let up = cgmath::vec3(0.0, height * 0.5, 0.0);
+
+    for cap in [-up, up] {
+        let first = vertices.len() as u32;
+        vertices.extend(corners.iter().map(|&corner| Vertex::new(corner + cap, cap.normalize())));
+        indices.extend_from_slice(&[first, first + 1, first + 2]);
+    }
+
+    for i in 0..3 {
+        let (a, b) = (corners[i], corners[(i + 1) % 3]);
+        // The triangle is centered on the origin, so the middle of a side points straight out of it
+        let normal = ((a + b) * 0.5).normalize();
+
+        let first = vertices.len() as u32;
+        for corner in [a - up, b - up, b + up, a + up] {
+            vertices.push(Vertex::new(corner, normal));
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    (vertices, indices)
+}
+
+// Closed box around the origin, every face has vertices of its own so the normals stay flat
+fn cuboid(half_extents: cgmath::Vector3<f32>) -> (Vec<Vertex>, Vec<u32>) {
+    let (mut vertices, mut indices) = (vec![], vec![]);
+
+    for axis in 0..3 {
+        for sign in [-1.0, 1.0] {
+            let mut normal = cgmath::vec3(0.0, 0.0, 0.0);
+            normal[axis] = sign;
+
+            let (mut u, mut v) = (cgmath::vec3(0.0, 0.0, 0.0), cgmath::vec3(0.0, 0.0, 0.0));
+            u[(axis + 1) % 3] = half_extents[(axis + 1) % 3];
+            v[(axis + 2) % 3] = half_extents[(axis + 2) % 3];
+            let center = normal * half_extents[axis];
+
+            let first = vertices.len() as u32;
+            for (a, b) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                vertices.push(Vertex::new(center + u * a + v * b, normal));
+            }
+            indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+
+    (vertices, indices)
+}
+
+// Around the origin with the ring in the xz plane, `rings` * `sides` * 2 triangles
+fn torus(major_radius: f32, minor_radius: f32, rings: u32, sides: u32) -> (Vec<Vertex>, Vec<u32>) {
+    // The seams are duplicated so every ring and side has its own vertices
+    let mut vertices = Vec::with_capacity(((rings + 1) * (sides + 1)) as usize);
+    for ring in 0..=rings {
+        let (ring_sin, ring_cos) = (ring as f32 / rings as f32 * std::f32::consts::TAU).sin_cos();
+        for side in 0..=sides {
+            let (side_sin, side_cos) = (side as f32 / sides as f32 * std::f32::consts::TAU).sin_cos();
+
+            let normal = cgmath::vec3(ring_cos * side_cos, side_sin, ring_sin * side_cos);
+            let ring_center = cgmath::vec3(ring_cos, 0.0, ring_sin) * major_radius;
+            vertices.push(Vertex::new(ring_center + normal * minor_radius, normal.normalize()));
+        }
+    }
+
+    let mut indices = Vec::with_capacity((rings * sides * 6) as usize);
+    for ring in 0..rings {
+        for side in 0..sides {
+            let a = ring * (sides + 1) + side;
+            let b = a + sides + 1;
+            indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn torus_fits_into_the_scene() {
+        let (vertices, indices) = torus(0.6, 0.25, 60, 40);
+
+        assert!(vertices.len() <= MAX_VERTICES && indices.len() <= MAX_INDICES);
+        assert!(indices.iter().all(|&index| (index as usize) < vertices.len()));
+    }
+
+    #[test]
+    fn every_scene_builds() {
+        for scene in BuiltinScene::ALL {
+            scene.build().unwrap();
+        }
+
+        // What the benchmark is named for
+        assert_eq!(BuiltinScene::HeavyMesh.build().unwrap().triangle_count(), 60 * 40 * 2);
+    }
+
+    // Glass and media need closed meshes, the faces have vertices of their own so edges are compared by position
+    #[test]
+    fn glass_meshes_are_closed() {
+        for (vertices, indices) in [cuboid(cgmath::vec3(0.25, 0.5, 1.0)), prism(0.3, 0.4)] {
+            let key = |index: u32| {
+                let position = vertices[index as usize].position() * 1000.0;
+                (position.x.round() as i32, position.y.round() as i32, position.z.round() as i32)
+            };
+            let mut edges = std::collections::HashMap::new();
+
+            for triangle in indices.chunks(3) {
+                for i in 0..3 {
+                    let (a, b) = (key(triangle[i]), key(triangle[(i + 1) % 3]));
+                    *edges.entry(if a < b { (a, b) } else { (b, a) }).or_insert(0) += 1;
+                }
+            }
+
+            assert!(edges.values().all(|&count| count == 2));
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for scene in BuiltinScene::ALL {
+            assert_eq!(BuiltinScene::parse(scene.name()), Some(scene));
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use cgmath::{ElementWise, InnerSpace, Vector2, Vector3, vec2, vec3};
+
+use crate::scene::NO_MEDIUM;
+use crate::vk::error::*;
+
+// Distance stack and repetition nesting the evaluation in shaders/sdf.glsl has room for
+pub(crate) const SDF_MAX_DEPTH: usize = 8;
+pub(crate) const SDF_MAX_NESTING: usize = 2;
+
+// Instruction op codes, leaves push a distance and the rest work on what the earlier ones pushed
+pub(crate) const SDF_SPHERE: u32 = 0;
+pub(crate) const SDF_BOX: u32 = 1;
+pub(crate) const SDF_TORUS: u32 = 2;
+pub(crate) const SDF_UNION: u32 = 10;
+pub(crate) const SDF_REPEAT_BEGIN: u32 = 20;
+pub(crate) const SDF_REPEAT_END: u32 = 21;
+pub(crate) const SDF_DISPLACE: u32 = 30;
+
+gpu_struct! {
+    #[derive(Clone, Debug, Copy, Default)]
+    pub struct SdfInstructionRaw as "SdfInstruction" {
+        op: u32 => "uint op",
+        padding: [u32; 3] => "uint padding[3]",
+        data: [[f32; 4]; 2] => "vec4 data[2]",
+    }
+}
+
+impl SdfInstructionRaw {
+    fn new(op: u32, data0: [f32; 4], data1: [f32; 4]) -> SdfInstructionRaw {
+        SdfInstructionRaw {
+            op,
+            padding: [0; 3],
+            data: [data0, data1],
+        }
+    }
+}
+
+gpu_struct! {
+    #[derive(Clone, Debug, Copy, Default)]
+    pub struct SdfObjectRaw as "SdfObject" {
+        first_instruction: u32 => "uint firstInstruction",
+        instruction_count: u32 => "uint instructionCount",
+        material: u32 => "uint material",
+        medium: u32 => "uint medium",
+        bounds: [f32; 4] => "vec4 bounds",
+        step_scale: [f32; 4] => "float stepScale",
+    }
+}
+
+impl SdfObjectRaw {
+    // Index of the first instruction after this object's
+    pub fn end(&self) -> u32 {
+        self.first_instruction + self.instruction_count
+    }
+}
+
+// Expression tree of a signed distance field, see shaders/sdf.glsl for the evaluation
+#[derive(Clone, Debug, PartialEq)]
+pub enum SdfNode {
+    Sphere { center: Vector3<f32>, radius: f32 },
+    Box { center: Vector3<f32>, half_extents: Vector3<f32> },
+    // Lies in the xz plane
+    Torus { center: Vector3<f32>, major_radius: f32, minor_radius: f32 },
+    Union(Box<SdfNode>, Box<SdfNode>),
+    // Blends the two shapes within roughly 'k' of each other
+    SmoothUnion { a: Box<SdfNode>, b: Box<SdfNode>, k: f32 },
+    // Copies of the child every 'period', 'count' copies to each side of the origin along each axis.
+    // The child has to stay within half a period of the origin on the repeated axes, the field
+    // jumps at the cell borders otherwise and sphere tracing can step through the surface.
+    Repeat { child: Box<SdfNode>, period: Vector3<f32>, count: [u32; 3] },
+    // Adds amplitude * sin(frequency * x) * sin(frequency * y) * sin(frequency * z) to the distance
+    Displace { child: Box<SdfNode>, amplitude: f32, frequency: f32 },
+}
+
+// Sphere that encloses both spheres
+fn merge_bounds(a: (Vector3<f32>, f32), b: (Vector3<f32>, f32)) -> (Vector3<f32>, f32) {
+    let offset = b.0 - a.0;
+    let distance = offset.magnitude();
+
+    if distance + b.1 <= a.1 {
+        return a;
+    }
+    if distance + a.1 <= b.1 {
+        return b;
+    }
+
+    let radius = (distance + a.1 + b.1) * 0.5;
+    (a.0 + offset * ((radius - a.1) / distance), radius)
+}
+
+impl SdfNode {
+    pub fn union(a: SdfNode, b: SdfNode) -> SdfNode {
+        SdfNode::Union(Box::new(a), Box::new(b))
+    }
+
+    pub fn smooth_union(a: SdfNode, b: SdfNode, k: f32) -> SdfNode {
+        SdfNode::SmoothUnion { a: Box::new(a), b: Box::new(b), k }
+    }
+
+    pub fn repeat(child: SdfNode, period: Vector3<f32>, count: [u32; 3]) -> SdfNode {
+        SdfNode::Repeat { child: Box::new(child), period, count }
+    }
+
+    pub fn displace(child: SdfNode, amplitude: f32, frequency: f32) -> SdfNode {
+        SdfNode::Displace { child: Box::new(child), amplitude, frequency }
+    }
+
+    fn bounds(&self) -> (Vector3<f32>, f32) {
+        match self {
+            SdfNode::Sphere { center, radius } => (*center, *radius),
+            SdfNode::Box { center, half_extents } => (*center, half_extents.magnitude()),
+            SdfNode::Torus { center, major_radius, minor_radius } => (*center, major_radius + minor_radius),
+            SdfNode::Union(a, b) => merge_bounds(a.bounds(), b.bounds()),
+            SdfNode::SmoothUnion { a, b, k } => {
+                let (center, radius) = merge_bounds(a.bounds(), b.bounds());
+                (center, radius + k)
+            }
+            SdfNode::Repeat { child, period, count } => {
+                let (center, radius) = child.bounds();
+                let reach = vec3(period.x * count[0] as f32, period.y * count[1] as f32, period.z * count[2] as f32);
+                (center, radius + reach.magnitude())
+            }
+            SdfNode::Displace { child, amplitude, .. } => {
+                let (center, radius) = child.bounds();
+                (center, radius + amplitude.abs())
+            }
+        }
+    }
+
+    // Upper bound of the gradient length, sphere tracing has to slow down by this much to not overshoot
+    fn lipschitz(&self) -> f32 {
+        match self {
+            SdfNode::Sphere { .. } | SdfNode::Box { .. } | SdfNode::Torus { .. } => 1.0,
+            SdfNode::Union(a, b) | SdfNode::SmoothUnion { a, b, .. } => a.lipschitz().max(b.lipschitz()),
+            SdfNode::Repeat { child, .. } => child.lipschitz(),
+            SdfNode::Displace { child, amplitude, frequency } => child.lipschitz() + (amplitude * frequency).abs() * 3.0f32.sqrt(),
+        }
+    }
+
+    // Appends the tree in postfix order and returns the distance stack and point stack depths it needs
+    fn compile(&self, instructions: &mut Vec<SdfInstructionRaw>) -> (usize, usize) {
+        match self {
+            SdfNode::Sphere { center, radius } => {
+                instructions.push(SdfInstructionRaw::new(SDF_SPHERE, center.extend(*radius).into(), [0.0; 4]));
+                (1, 0)
+            }
+            SdfNode::Box { center, half_extents } => {
+                instructions.push(SdfInstructionRaw::new(SDF_BOX, center.extend(0.0).into(), half_extents.extend(0.0).into()));
+                (1, 0)
+            }
+            SdfNode::Torus { center, major_radius, minor_radius } => {
+                instructions.push(SdfInstructionRaw::new(SDF_TORUS, center.extend(*major_radius).into(), [*minor_radius, 0.0, 0.0, 0.0]));
+                (1, 0)
+            }
+            SdfNode::Union(a, b) | SdfNode::SmoothUnion { a, b, .. } => {
+                let (a_depth, a_nesting) = a.compile(instructions);
+                let (b_depth, b_nesting) = b.compile(instructions);
+
+                let k = if let SdfNode::SmoothUnion { k, .. } = self { *k } else { 0.0 };
+                instructions.push(SdfInstructionRaw::new(SDF_UNION, [k, 0.0, 0.0, 0.0], [0.0; 4]));
+
+                (a_depth.max(b_depth + 1), a_nesting.max(b_nesting))
+            }
+            SdfNode::Repeat { child, period, count } => {
+                // Axes without copies still need a non zero period for the shader's division
+                let period = vec3(
+                    if count[0] > 0 { period.x } else { 1.0 },
+                    if count[1] > 0 { period.y } else { 1.0 },
+                    if count[2] > 0 { period.z } else { 1.0 },
+                );
+
+                instructions.push(SdfInstructionRaw::new(SDF_REPEAT_BEGIN, period.extend(0.0).into(), [count[0] as f32, count[1] as f32, count[2] as f32, 0.0]));
+                let (depth, nesting) = child.compile(instructions);
+                instructions.push(SdfInstructionRaw::new(SDF_REPEAT_END, [0.0; 4], [0.0; 4]));
+
+                (depth, nesting + 1)
+            }
+            SdfNode::Displace { child, amplitude, frequency } => {
+                let result = child.compile(instructions);
+                instructions.push(SdfInstructionRaw::new(SDF_DISPLACE, [*amplitude, *frequency, 0.0, 0.0], [0.0; 4]));
+
+                result
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SdfObject {
+    pub root: SdfNode,
+    material: u32,
+    medium: u32,
+}
+
+impl SdfObject {
+    pub fn new(root: SdfNode, material: u32) -> SdfObject {
+        SdfObject {
+            root,
+            material,
+            medium: NO_MEDIUM,
+        }
+    }
+
+    pub fn with_medium(mut self, medium: u32) -> SdfObject {
+        self.medium = medium;
+        self
+    }
+
+    pub fn to_raw(&self, first_instruction: u32) -> VkResult<(SdfObjectRaw, Vec<SdfInstructionRaw>)> {
+        let mut instructions = Vec::new();
+        let (depth, nesting) = self.root.compile(&mut instructions);
+        if depth > SDF_MAX_DEPTH {
+            return Err(VkError::SceneCapacity { what: "SDF stack", capacity: SDF_MAX_DEPTH });
+        }
+        if nesting > SDF_MAX_NESTING {
+            return Err(VkError::SceneCapacity { what: "nested SDF repetitions", capacity: SDF_MAX_NESTING });
+        }
+
+        let (center, radius) = self.root.bounds();
+
+        let raw = SdfObjectRaw {
+            first_instruction,
+            instruction_count: instructions.len() as u32,
+            material: self.material,
+            medium: self.medium,
+            bounds: center.extend(radius).into(),
+            step_scale: [1.0 / self.root.lipschitz(); 4],
+        };
+
+        Ok((raw, instructions))
+    }
+}
+
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+// Runs compiled instructions the same way EvaluateSdf in shaders/sdf.glsl does, keep the two in sync
+pub fn evaluate(instructions: &[SdfInstructionRaw], position: Vector3<f32>) -> f32 {
+    let mut stack = Vec::with_capacity(SDF_MAX_DEPTH);
+    let mut points = Vec::with_capacity(SDF_MAX_NESTING);
+    let mut p = position;
+
+    for instruction in instructions {
+        let data0 = Vector3::new(instruction.data[0][0], instruction.data[0][1], instruction.data[0][2]);
+        let data1 = Vector3::new(instruction.data[1][0], instruction.data[1][1], instruction.data[1][2]);
+
+        match instruction.op {
+            SDF_SPHERE => stack.push((p - data0).magnitude() - instruction.data[0][3]),
+            SDF_BOX => {
+                let q = (p - data0).map(f32::abs) - data1;
+                stack.push(q.map(|x| x.max(0.0)).magnitude() + q.x.max(q.y.max(q.z)).min(0.0));
+            }
+            SDF_TORUS => {
+                let local = p - data0;
+                let q: Vector2<f32> = vec2(vec2(local.x, local.z).magnitude() - instruction.data[0][3], local.y);
+                stack.push(q.magnitude() - data1.x);
+            }
+            SDF_UNION => {
+                let b = stack.pop().unwrap();
+                let a = stack.pop().unwrap();
+                stack.push(smooth_min(a, b, instruction.data[0][0]));
+            }
+            SDF_REPEAT_BEGIN => {
+                points.push(p);
+                let cell = p.div_element_wise(data0).map(f32::round);
+                p -= data0.mul_element_wise(vec3(
+                    cell.x.clamp(-data1.x, data1.x),
+                    cell.y.clamp(-data1.y, data1.y),
+                    cell.z.clamp(-data1.z, data1.z),
+                ));
+            }
+            SDF_REPEAT_END => p = points.pop().unwrap(),
+            SDF_DISPLACE => {
+                let s = (p * instruction.data[0][1]).map(f32::sin);
+                *stack.last_mut().unwrap() += instruction.data[0][0] * s.x * s.y * s.z;
+            }
+            op => panic!("Unknown SDF op {}!", op),
+        }
+    }
+
+    stack[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(x: f32) -> SdfNode {
+        SdfNode::Sphere { center: vec3(x, 0.0, 0.0), radius: 0.5 }
+    }
+
+    fn scenes() -> Vec<SdfNode> {
+        vec![
+            sphere(0.0),
+            SdfNode::Box { center: vec3(0.2, 0.0, -0.1), half_extents: vec3(0.5, 0.3, 0.2) },
+            SdfNode::Torus { center: vec3(0.0, 0.3, 0.0), major_radius: 0.6, minor_radius: 0.1 },
+            SdfNode::smooth_union(sphere(-0.4), sphere(0.4), 0.3),
+            SdfNode::repeat(SdfNode::union(sphere(0.0), SdfNode::displace(sphere(0.1), 0.05, 12.0)), vec3(1.5, 1.0, 1.5), [2, 0, 1]),
+            SdfNode::displace(SdfNode::Box { center: vec3(0.0, 0.0, 0.0), half_extents: vec3(0.4, 0.4, 0.4) }, 0.1, 8.0),
+        ]
+    }
+
+    // Deterministic points in a cube of `size` around the origin
+    fn points(count: usize, size: f32) -> Vec<Vector3<f32>> {
+        let mut seed = 7u32;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed as f32 / u32::MAX as f32 - 0.5) * size
+        };
+
+        (0..count).map(|_| vec3(random(), random(), random())).collect()
+    }
+
+    #[test]
+    fn instructions_are_postfix_with_the_shader_op_codes() {
+        let object = SdfObject::new(SdfNode::repeat(SdfNode::smooth_union(sphere(0.0), SdfNode::displace(sphere(1.0), 0.1, 2.0), 0.25), vec3(3.0, 0.0, 0.0), [1, 0, 0]), 2);
+        let (raw, instructions) = object.to_raw(4).unwrap();
+
+        let ops: Vec<u32> = instructions.iter().map(|instruction| instruction.op).collect();
+        assert_eq!(ops, [SDF_REPEAT_BEGIN, SDF_SPHERE, SDF_SPHERE, SDF_DISPLACE, SDF_UNION, SDF_REPEAT_END]);
+        assert_eq!(instructions[4].data[0][0], 0.25);
+        // The axis without copies gets a period the shader can divide by
+        assert_eq!(instructions[0].data[0][..3], [3.0, 1.0, 1.0]);
+
+        assert_eq!((raw.first_instruction, raw.end(), raw.material, raw.medium), (4, 10, 2, NO_MEDIUM));
+        assert_eq!(raw.step_scale[0], 1.0 / (1.0 + 0.2 * 3.0f32.sqrt()));
+    }
+
+    #[test]
+    fn compiled_leaves_evaluate_to_their_distance() {
+        let torus = SdfNode::Torus { center: vec3(1.0, 0.0, 0.0), major_radius: 0.5, minor_radius: 0.1 };
+        let (_, instructions) = SdfObject::new(SdfNode::union(sphere(-1.0), torus), 0).to_raw(0).unwrap();
+
+        assert!((evaluate(&instructions, vec3(-1.0, 2.0, 0.0)) - 1.5).abs() < 1e-6);
+        assert!((evaluate(&instructions, vec3(1.5, 0.0, 0.0)) + 0.1).abs() < 1e-6);
+        assert!((evaluate(&instructions, vec3(1.0, 0.0, 0.0)) - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fields_change_no_faster_than_their_lipschitz_bound() {
+        for node in scenes() {
+            let (raw, instructions) = SdfObject::new(node.clone(), 0).to_raw(0).unwrap();
+            let lipschitz = 1.0 / raw.step_scale[0];
+
+            for pair in points(2001, 6.0).windows(2) {
+                // Short steps, where a missing term in the bound would show up
+                let (a, b) = (pair[0], pair[0] + (pair[1] - pair[0]) * 0.01);
+                let change = (evaluate(&instructions, a) - evaluate(&instructions, b)).abs();
+                assert!(change <= lipschitz * (a - b).magnitude() * 1.001 + 1e-5, "{:?} changes faster than {}", node, lipschitz);
+            }
+        }
+    }
+
+    #[test]
+    fn bounds_enclose_the_surface() {
+        for node in scenes() {
+            let (raw, instructions) = SdfObject::new(node.clone(), 0).to_raw(0).unwrap();
+            let center = vec3(raw.bounds[0], raw.bounds[1], raw.bounds[2]);
+
+            for point in points(5000, 10.0) {
+                if evaluate(&instructions, point) <= 0.0 {
+                    assert!((point - center).magnitude() <= raw.bounds[3], "{:?} reaches outside its bounds at {:?}", node, point);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+// GLSL to SPIR-V compilation, shared by build.rs and shader hot reloading
+
+use std::path::Path;
+
+pub const SHADER_DIR: &str = "shaders";
+
+pub fn shader_kind(path: &Path) -> Option<shaderc::ShaderKind> {
+    match path.extension()?.to_str()? {
+        "comp" => Some(shaderc::ShaderKind::Compute),
+        "vert" => Some(shaderc::ShaderKind::Vertex),
+        "frag" => Some(shaderc::ShaderKind::Fragment),
+        "rgen" => Some(shaderc::ShaderKind::RayGeneration),
+        "rchit" | "chit" => Some(shaderc::ShaderKind::ClosestHit),
+        "rmiss" => Some(shaderc::ShaderKind::Miss),
+        _ => None,
+    }
+}
+
+// "file" includes are looked up next to the including file first, <file> ones and fallbacks in shaders/
+fn resolve_include(name: &str, include_type: shaderc::IncludeType, including_file: &str, _depth: usize) -> shaderc::IncludeCallbackResult {
+    let mut candidates = vec![];
+    if include_type == shaderc::IncludeType::Relative {
+        if let Some(directory) = Path::new(including_file).parent() {
+            candidates.push(directory.join(name));
+        }
+    }
+    candidates.push(Path::new(SHADER_DIR).join(name));
+
+    for candidate in candidates {
+        if let Ok(content) = std::fs::read_to_string(&candidate) {
+            return Ok(shaderc::ResolvedInclude {
+                resolved_name: candidate.to_string_lossy().into_owned(),
+                content,
+            });
+        }
+    }
+
+    Err(format!("Can't find include \"{}\"", name))
+}
+
+// Errors are the compiler diagnostics, ready to be printed
+pub fn compile(path: &Path) -> Result<shaderc::CompilationArtifact, String> {
+    let kind = shader_kind(path).ok_or_else(|| format!("{}: unknown shader stage", path.display()))?;
+    let source = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+    let mut compiler = shaderc::Compiler::new().ok_or("Failed to create the shader compiler")?;
+    let mut options = shaderc::CompileOptions::new().ok_or("Failed to create the shader compile options")?;
+    options.set_target_env(shaderc::TargetEnv::Vulkan, shaderc::EnvVersion::Vulkan1_0 as u32);
+    options.set_optimization_level(shaderc::OptimizationLevel::Performance);
+    options.set_include_callback(resolve_include);
+
+    compiler.compile_into_spirv(&source, kind, &path.to_string_lossy(), "main", Some(&options)).map_err(|error| error.to_string())
+}
@@ -0,0 +1,56 @@
+// Watches shaders/ while the app runs. Changed sources are recompiled, and SPIR-V saved
+// next to them (main.spv, preview.spv) is picked up as is, e.g. when compiled with glslc.
+
+use std::path::Path;
+
+use crate::{
+    file_watcher::FileWatcher,
+    shader_compiler,
+    shaders::ComputeShader,
+};
+
+const SPIRV_MAGIC: u32 = 0x07230203;
+
+pub struct ShaderWatcher {
+    watcher: FileWatcher,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> notify::Result<ShaderWatcher> {
+        Ok(Self {
+            watcher: FileWatcher::new(Path::new(shader_compiler::SHADER_DIR))?,
+        })
+    }
+
+    // New SPIR-V for every shader touched by the changes since the last poll, or why there is none
+    pub fn poll(&self) -> Vec<(ComputeShader, Result<Vec<u8>, String>)> {
+        let changed = self.watcher.changed_files();
+
+        let mut reloads = vec![];
+        for shader in ComputeShader::ALL {
+            let source = Path::new(shader_compiler::SHADER_DIR).join(format!("{}.comp", shader.name()));
+            let spirv = Path::new(shader_compiler::SHADER_DIR).join(format!("{}.spv", shader.name()));
+
+            let is = |path: &Path, expected: &Path| path.ends_with(expected);
+            let is_include = |path: &Path| path.extension().is_some_and(|extension| extension == "glsl");
+
+            if changed.iter().any(|path| is(path, &spirv)) {
+                reloads.push((shader, load_spirv(&spirv)));
+            } else if changed.iter().any(|path| is(path, &source) || is_include(path)) {
+                reloads.push((shader, shader_compiler::compile(&source).map(|artifact| artifact.as_binary_u8().to_vec())));
+            }
+        }
+
+        reloads
+    }
+}
+
+fn load_spirv(path: &Path) -> Result<Vec<u8>, String> {
+    let bytes = std::fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+    if bytes.len() % 4 != 0 || bytes.len() < 4 || u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) != SPIRV_MAGIC {
+        return Err(format!("{}: not a SPIR-V module", path.display()));
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,27 @@
+// SPIR-V of the shaders in shaders/, compiled by build.rs
+
+macro_rules! spirv {
+    ($path:literal) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/", $path, ".spv"))
+    };
+}
+
+pub const MAIN: &[u8] = spirv!("main.comp");
+pub const PREVIEW: &[u8] = spirv!("preview.comp");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComputeShader {
+    Main,
+    Preview,
+}
+
+impl ComputeShader {
+    pub const ALL: [ComputeShader; 2] = [ComputeShader::Main, ComputeShader::Preview];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ComputeShader::Main => "main",
+            ComputeShader::Preview => "preview",
+        }
+    }
+}
@@ -5,7 +5,8 @@ use std::os::raw::c_char;
 use crate::vk::{
     command_pool::VkCommandPool,
     command_buffer::VkCommandBuffer,
-    queue::VkQueue
+    queue::VkQueue,
+    error::*
 };
 
 pub fn cchar_to_string(raw_string_array: &[c_char]) -> String {  
@@ -16,37 +17,38 @@ pub fn cchar_to_string(raw_string_array: &[c_char]) -> String {
     raw_string.to_str().expect("Failed to convert const char raw string").to_owned()
 }
 
-pub fn read_spirv(shader_path: &std::path::Path) -> Vec<u8> {
-    use std::fs::File;
-    use std::io::Read;
-
-    let spv_file = File::open(shader_path).expect(&format!("Failed to find spv file at {:?}", shader_path));
-
-    spv_file.bytes().filter_map(|byte| byte.ok()).collect()
+pub fn read_spirv(shader_path: &std::path::Path) -> VkResult<Vec<u8>> {
+    std::fs::read(shader_path).map_err(|error| VkError::Io { path: shader_path.to_path_buf(), error })
 }
-pub fn create_shader_module(device: &ash::Device, spirv: &Vec<u8>) -> ash::vk::ShaderModule {
+pub fn create_shader_module(device: &ash::Device, spirv: &[u8]) -> VkResult<ash::vk::ShaderModule> {
+    // Embedded bytes aren't guaranteed to be aligned for u32 words
+    let code = ash::util::read_spv(&mut std::io::Cursor::new(spirv)).map_err(VkError::InvalidSpirv)?;
+
     let shader_module_create_info = ash::vk::ShaderModuleCreateInfo {
         s_type: ash::vk::StructureType::SHADER_MODULE_CREATE_INFO,
         p_next: std::ptr::null(),
         flags: ash::vk::ShaderModuleCreateFlags::empty(),
-        code_size: spirv.len(),
-        p_code: spirv.as_ptr() as *const u32,
+        code_size: code.len() * 4,
+        p_code: code.as_ptr(),
     };
 
     unsafe {
-        device.create_shader_module(&shader_module_create_info, None).expect("Failed to create Shader Module!")
+        device.create_shader_module(&shader_module_create_info, None).or_fail_to("create Shader Module")
     }
 }
 
-pub fn begin_single_queue_submit(device: &ash::Device, command_pool: &VkCommandPool) -> VkCommandBuffer
+pub fn begin_single_queue_submit(device: &ash::Device, command_pool: &VkCommandPool) -> VkResult<VkCommandBuffer>
 {
-    let cmd = command_pool.allocate(device, 1)[0];
-    cmd.begin_recording(device, ash::vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-    cmd
+    let cmd = command_pool.allocate(device, 1)?[0];
+    cmd.begin_recording(device, ash::vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    Ok(cmd)
 }
-pub fn end_single_queue_submit(device: &ash::Device, command_pool: &VkCommandPool, queue: &VkQueue, command_buffer: VkCommandBuffer)
+pub fn end_single_queue_submit(device: &ash::Device, command_pool: &VkCommandPool, queue: &VkQueue, command_buffer: VkCommandBuffer) -> VkResult<()>
 {
-    command_buffer.end_recording(device);
-    queue.submit_once(device, &command_buffer);
+    // The command buffer is freed even when submitting it failed
+    let result = command_buffer.end_recording(device).and_then(
+        |_| queue.submit_once(device, &command_buffer)
+    );
     command_pool.deallocate(device, &command_buffer);
+    result
 }
\ No newline at end of file
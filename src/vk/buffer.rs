@@ -1,70 +1,74 @@
 use gpu_allocator::vulkan::*;
 use gpu_allocator::MemoryLocation;
 
+use std::sync::Arc;
+
 use crate::vk::command_buffer::VkCommandBuffer;
+use crate::vk::device::VkDevice;
+use crate::vk::error::*;
 use crate::vk::texture::VkTexture;
 
-#[derive(Debug)]
 pub struct VkBuffer {
     pub handle: ash::vk::Buffer,
     pub allocation: Option<Allocation>,
     pub size: ash::vk::DeviceSize,
+
+    device: Arc<VkDevice>,
 }
 
 impl VkBuffer {
-    pub fn new(device: &ash::Device, allocator: &mut Allocator, size: ash::vk::DeviceSize, usage: ash::vk::BufferUsageFlags, mem_location: MemoryLocation) -> Self {
+    pub fn new(device: &Arc<VkDevice>, size: ash::vk::DeviceSize, usage: ash::vk::BufferUsageFlags, mem_location: MemoryLocation) -> VkResult<Self> {
         let info = ash::vk::BufferCreateInfo::builder().size(size).usage(usage).build();
 
-        let buffer = unsafe { 
+        let handle = unsafe { 
             device.create_buffer(&info, None) 
-        }.unwrap();
+        }.or_fail_to("create Buffer")?;
+
+        // From here on dropping the buffer cleans up after a failure
+        let mut buffer = Self {
+            handle,
+            allocation: None,
+            size,
+            device: device.clone(),
+        };
+
         let requirements = unsafe { 
-            device.get_buffer_memory_requirements(buffer) 
+            device.get_buffer_memory_requirements(handle) 
         };
 
-        let allocation = allocator.allocate(&AllocationCreateDesc {
+        let allocation = device.allocator().allocate(&AllocationCreateDesc {
             name: "Memory Buffer Allocation",
             requirements,
             location: mem_location,
             linear: true,
             allocation_scheme: AllocationScheme::GpuAllocatorManaged,
-        }).expect("Failed to allocate a Buffer!");
+        }).or_fail_to("allocate a Buffer")?;
+        let allocation = buffer.allocation.insert(allocation);
         
         unsafe { 
-            device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset()).expect("Failed to bind buffer memory!") 
+            device.bind_buffer_memory(handle, allocation.memory(), allocation.offset()).or_fail_to("bind buffer memory")?
         };
 
-        Self {
-            handle: buffer,
-            allocation: Some(allocation),
-            size
-        }
+        Ok(buffer)
     }
 
-    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
-        let mut alloc: Option<Allocation> = None;
-        std::mem::swap(&mut alloc, &mut self.allocation);
-        
-        allocator.free(alloc.unwrap()).unwrap();
-
-        unsafe { 
-            device.destroy_buffer(self.handle, None) 
-        };
+    // Only shows up with validation enabled, see VkDebugMessenger::set_object_name
+    pub fn set_name(&self, name: &str) {
+        self.device.set_object_name(self.handle, name);
     }
 
     pub fn fill<T>(&mut self, data: &[T]) {
+        assert!(std::mem::size_of_val(data) as u64 <= self.size, "Data doesn't fit into the buffer!");
+
         unsafe {
             let dst_ptr = self.allocation.as_ref().unwrap().mapped_ptr().unwrap().cast().as_ptr();
 
             std::ptr::copy_nonoverlapping(data.as_ptr(), dst_ptr, data.len());
         }
     }
-    pub fn fill_raw<T>(&mut self, data: *const T, count: usize) {
-        unsafe {
-            let dst_ptr = self.allocation.as_ref().unwrap().mapped_ptr().unwrap().cast().as_ptr();
-
-            std::ptr::copy_nonoverlapping(data, dst_ptr, count);
-        }
+    // Only for buffers in host visible memory, e.g. MemoryLocation::GpuToCpu
+    pub fn read_bytes(&self) -> &[u8] {
+        self.allocation.as_ref().unwrap().mapped_slice().expect("Buffer isn't host visible!")
     }
     
     pub fn copy_to_buffer(&mut self, command_buffer: &VkCommandBuffer, other: &Self, device: &ash::Device) {
@@ -76,6 +80,16 @@ impl VkBuffer {
             }]);
         }
     }
+    // Copies the whole buffer into `other`, starting `dst_offset` bytes in
+    pub fn copy_to_buffer_at(&mut self, command_buffer: &VkCommandBuffer, other: &Self, dst_offset: ash::vk::DeviceSize, device: &ash::Device) {
+        unsafe {
+            device.cmd_copy_buffer(command_buffer.handle, self.handle, other.handle, &[ash::vk::BufferCopy{
+                src_offset: 0,
+                dst_offset,
+                size: self.size,
+            }]);
+        }
+    }
     pub fn copy_to_image(&mut self, command_buffer: &VkCommandBuffer, other: &VkTexture, device: &ash::Device) {
         let buffer_image_regions = [ash::vk::BufferImageCopy::builder()
         .image_subresource(ash::vk::ImageSubresourceLayers {
@@ -100,4 +114,17 @@ impl VkBuffer {
             );
         }
     }
-}
\ No newline at end of file
+}
+
+impl Drop for VkBuffer {
+    fn drop(&mut self) {
+        if let Some(allocation) = self.allocation.take() {
+            // Nothing left to recover when this fails while tearing down
+            let _ = self.device.allocator().free(allocation);
+        }
+
+        unsafe { 
+            self.device.destroy_buffer(self.handle, None) 
+        };
+    }
+}
@@ -4,7 +4,9 @@ use crate::vk::{
     //graphics_pipeline::VkGraphicsPipeline,
     compute_pipeline::VkComputePipeline,
     descriptor_pool::VkDescriptorSet,
-    renderpass::VkRenderPass
+    device::VkDevice,
+    renderpass::VkRenderPass,
+    error::*
 };
 
 
@@ -15,7 +17,7 @@ pub struct VkCommandBuffer {
 }
 
 impl VkCommandBuffer {
-    pub fn begin_recording(&self, device: &ash::Device, usage_flags: ash::vk::CommandBufferUsageFlags) {
+    pub fn begin_recording(&self, device: &ash::Device, usage_flags: ash::vk::CommandBufferUsageFlags) -> VkResult<()> {
         let command_buffer_begin_info = ash::vk::CommandBufferBeginInfo {
             s_type: ash::vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
             p_next: std::ptr::null(),
@@ -24,19 +26,19 @@ impl VkCommandBuffer {
         };
 
         unsafe {
-            device.begin_command_buffer(self.handle, &command_buffer_begin_info).expect("Failed to begin recording Command Buffer at beginning!");
+            device.begin_command_buffer(self.handle, &command_buffer_begin_info).or_fail_to("begin recording Command Buffer")
         }
     }
 
-    pub fn end_recording(&self, device: &ash::Device) {
+    pub fn end_recording(&self, device: &ash::Device) -> VkResult<()> {
         unsafe {
-            device.end_command_buffer(self.handle).expect("Failed to record Command Buffer at Ending!");
+            device.end_command_buffer(self.handle).or_fail_to("end recording Command Buffer")
         }
     }
 
-    pub fn reset(&self, device: &ash::Device) {
+    pub fn reset(&self, device: &ash::Device) -> VkResult<()> {
         unsafe {
-            device.reset_command_buffer(self.handle, ash::vk::CommandBufferResetFlags::RELEASE_RESOURCES).expect("Failed to reset command buffers");
+            device.reset_command_buffer(self.handle, ash::vk::CommandBufferResetFlags::RELEASE_RESOURCES).or_fail_to("reset command buffers")
         }
     }
 
@@ -183,6 +185,43 @@ impl VkCommandBuffer {
         }
     }
 
+    // Groups the commands recorded until end_label under `name` in validation messages and debuggers
+    pub fn begin_label(&self, device: &VkDevice, name: &str) {
+        device.debug_messenger().begin_label(self.handle, name);
+    }
+
+    pub fn end_label(&self, device: &VkDevice) {
+        device.debug_messenger().end_label(self.handle);
+    }
+
+    // Makes writes from `src_stage` visible to `dst_stage`, for images that stay in one layout
+    pub fn memory_barrier(
+        &self,
+        device: &ash::Device,
+        src_stage: ash::vk::PipelineStageFlags,
+        src_access_mask: ash::vk::AccessFlags,
+        dst_stage: ash::vk::PipelineStageFlags,
+        dst_access_mask: ash::vk::AccessFlags,
+    ) {
+        let memory_barriers = [ash::vk::MemoryBarrier::builder()
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .build()
+        ];
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                self.handle,
+                src_stage,
+                dst_stage,
+                ash::vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &[],
+                &[],
+            );
+        }
+    }
+
     pub fn transition_image_layout(
         &self,
         device: &ash::Device,
@@ -1,7 +1,7 @@
 
 use std::ptr;
 
-use crate::vk::command_buffer::VkCommandBuffer;
+use crate::vk::{command_buffer::VkCommandBuffer, error::*};
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct VkCommandPool {
@@ -9,7 +9,7 @@ pub struct VkCommandPool {
 }
 
 impl VkCommandPool {
-    pub fn new(device: &ash::Device, queue_family_index: u32) -> Self {
+    pub fn new(device: &ash::Device, queue_family_index: u32) -> VkResult<Self> {
         let command_pool_create_info = ash::vk::CommandPoolCreateInfo {
             s_type: ash::vk::StructureType::COMMAND_POOL_CREATE_INFO,
             p_next: ptr::null(),
@@ -18,12 +18,12 @@ impl VkCommandPool {
         };
 
         let command_pool = unsafe {
-            device.create_command_pool(&command_pool_create_info, None).expect("Failed to create Command Pool!")
+            device.create_command_pool(&command_pool_create_info, None).or_fail_to("create Command Pool")?
         };
 
-        VkCommandPool { 
+        Ok(VkCommandPool { 
             handle: command_pool
-        }
+        })
     }
 
     pub fn destroy(&self, device: &ash::Device) {
@@ -32,7 +32,7 @@ impl VkCommandPool {
         }
     }
 
-    pub fn allocate(&self, device: &ash::Device, count: u32) -> Vec<VkCommandBuffer> {
+    pub fn allocate(&self, device: &ash::Device, count: u32) -> VkResult<Vec<VkCommandBuffer>> {
         let command_buffer_allocate_info = ash::vk::CommandBufferAllocateInfo {
             s_type: ash::vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
             p_next: ptr::null(),
@@ -42,11 +42,11 @@ impl VkCommandPool {
         };
 
         unsafe {
-            device.allocate_command_buffers(&command_buffer_allocate_info).expect("Failed to allocate Command Buffers!").iter().map(
+            Ok(device.allocate_command_buffers(&command_buffer_allocate_info).or_fail_to("allocate Command Buffers")?.iter().map(
                 |&native| VkCommandBuffer {
                     handle: native
                 }
-            ).collect()
+            ).collect())
         }
     }
 
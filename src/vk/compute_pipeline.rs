@@ -1,25 +1,27 @@
 
 use std::ffi::CString;
+use std::sync::Arc;
 
 use crate::{
-    utilities
+    utilities,
+    vk::{device::VkDevice, error::*}
 };
 
 pub struct VkComputePipeline {
     pub handle: ash::vk::Pipeline,  
-    pub layout: ash::vk::PipelineLayout
+    pub layout: ash::vk::PipelineLayout,
+    device: Arc<VkDevice>,
 }
 
 impl VkComputePipeline {
-    pub fn new(device: &ash::Device,
-        compute_shader_path: &str,
+    pub fn new(device: &Arc<VkDevice>,
+        compute_shader_code: &[u8],
         descriptor_set_layouts: &Vec<ash::vk::DescriptorSetLayout>,
         push_constant_ranges: &Vec<ash::vk::PushConstantRange>,
-    ) -> Self {
+    ) -> VkResult<Self> {
         let shader_entrypoint_name = CString::new("main").unwrap();
 
-        let compute_shader_code = utilities::read_spirv(std::path::Path::new(compute_shader_path));
-        let compute_shader_module = utilities::create_shader_module(device, &compute_shader_code);
+        let compute_shader_module = utilities::create_shader_module(device, compute_shader_code)?;
 
         let compute_shader_stage = ash::vk::PipelineShaderStageCreateInfo::builder()
             .module(compute_shader_module)
@@ -33,7 +35,14 @@ impl VkComputePipeline {
             .build();
 
         let pipeline_layout = unsafe {
-            device.create_pipeline_layout(&pipeline_layout_create_info, None).expect("Failed to create pipeline layout!")
+            device.create_pipeline_layout(&pipeline_layout_create_info, None)
+        };
+        let pipeline_layout = match pipeline_layout {
+            Ok(pipeline_layout) => pipeline_layout,
+            Err(result) => {
+                unsafe { device.destroy_shader_module(compute_shader_module, None) };
+                return Err(VkError::Vulkan { action: "create pipeline layout", result });
+            }
         };
 
         let compute_pipeline_create_infos = [ash::vk::ComputePipelineCreateInfo::builder()
@@ -45,23 +54,38 @@ impl VkComputePipeline {
 
         let compute_pipelines = unsafe {
             device.create_compute_pipelines(ash::vk::PipelineCache::null(), &compute_pipeline_create_infos, None)
-                .expect("Failed to create Compute Pipeline!.")
         };
 
         unsafe {
             device.destroy_shader_module(compute_shader_module, None);
         }
+
+        let compute_pipelines = match compute_pipelines {
+            Ok(compute_pipelines) => compute_pipelines,
+            Err((_, result)) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                return Err(VkError::Vulkan { action: "create Compute Pipeline", result });
+            }
+        };
         
-        Self {  
+        Ok(Self {  
             handle: compute_pipelines[0],
-            layout: pipeline_layout
-        }
+            layout: pipeline_layout,
+            device: device.clone(),
+        })
     }
 
-    pub fn destroy(&self, device: &ash::Device) {
+    pub fn set_name(&self, name: &str) {
+        self.device.set_object_name(self.handle, name);
+        self.device.set_object_name(self.layout, &format!("{} layout", name));
+    }
+}
+
+impl Drop for VkComputePipeline {
+    fn drop(&mut self) {
         unsafe {
-            device.destroy_pipeline(self.handle, None);
-            device.destroy_pipeline_layout(self.layout, None);
+            self.device.destroy_pipeline(self.handle, None);
+            self.device.destroy_pipeline_layout(self.layout, None);
         }
     }
-}
\ No newline at end of file
+}
@@ -8,15 +8,15 @@ use crate::vk::{
     queue::*,
     command_pool::*,
     descriptor_pool::*,
+    device::VkDevice,
+    error::*,
 };
 
-use gpu_allocator::vulkan::*;
-
-
 use std::collections::HashSet;
 use std::ffi::CString;
 use std::os::raw::{c_void, c_char};
 use std::ptr;
+use std::sync::Arc;
 
 pub const APPLICATION_VERSION: u32 = ash::vk::make_api_version(0, 1, 0, 0);
 pub const ENGINE_VERSION: u32 = ash::vk::make_api_version(0, 1, 0, 0);
@@ -26,13 +26,11 @@ pub const WINDOW_TITLE: &'static str = "OxiTrace";
 
 pub struct VkContext {
     pub entry: ash::Entry,
-    pub instance: ash::Instance,
-    pub device: ash::Device,
-    
-    pub allocator: Option<Allocator>,
+    // Owns the instance and the allocator too, see VkDevice
+    pub device: Arc<VkDevice>,
 
-    pub surface: VkSurface,
-    pub debug_messenger: VkDebugMessenger,
+    // None when rendering headless
+    pub surface: Option<VkSurface>,
     pub physical_device: VkPhysicalDevice,
 
     pub graphics_queue: VkQueue,
@@ -42,61 +40,125 @@ pub struct VkContext {
     pub graphics_command_pool: VkCommandPool,
     pub transfer_command_pool: VkCommandPool,
 
-    pub descriptor_pool: VkDescriptorPool
+    pub descriptor_pool: Arc<VkDescriptorPool>
+}
+
+// What VkContext::new has created so far. When a step fails dropping it destroys everything in reverse order,
+// the surface before the instance. Whatever was moved out of it is owned by VkDevice or the VkContext instead.
+#[derive(Default)]
+struct PartialContext {
+    instance: Option<ash::Instance>,
+    debug_messenger: Option<VkDebugMessenger>,
+    surface: Option<VkSurface>,
+    logical_device: Option<ash::Device>,
+    // Destroys the instance and the logical device it took over when dropped, after Drop::drop below
+    device: Option<Arc<VkDevice>>,
+    graphics_command_pool: Option<VkCommandPool>,
+    transfer_command_pool: Option<VkCommandPool>,
+}
+
+impl Drop for PartialContext {
+    fn drop(&mut self) {
+        if let Some(device) = &self.device {
+            for command_pool in [&self.graphics_command_pool, &self.transfer_command_pool].into_iter().flatten() {
+                command_pool.destroy(device);
+            }
+        }
+
+        if let Some(surface) = &self.surface {
+            surface.destroy();
+        }
+
+        unsafe {
+            if let Some(logical_device) = &self.logical_device {
+                logical_device.destroy_device(None);
+            }
+            if let Some(debug_messenger) = &self.debug_messenger {
+                debug_messenger.destroy();
+            }
+            if let Some(instance) = &self.instance {
+                instance.destroy_instance(None);
+            }
+        }
+    }
 }
 
 impl VkContext {
-    pub fn new(window: &winit::window::Window) -> VkContext {
+    // Without a window there is no surface to present to, only compute and transfer work is possible
+    pub fn new(window: Option<&winit::window::Window>, device_selector: &DeviceSelector, validation: &ValidationSettings) -> VkResult<VkContext> {
         let entry = ash::Entry::linked();
-        let instance = Self::create_instance(&entry, window.raw_display_handle());
-        let debug_messenger = VkDebugMessenger::new(&entry, &instance);
+        let messenger_state = MessengerState::new(validation);
+        let mut partial = PartialContext::default();
 
-        let surface = VkSurface::new(&entry, &instance, &window);
-        let physical_device = VkPhysicalDevice::new(&instance, &surface);
+        let instance = partial.instance.insert(Self::create_instance(&entry, window.map(|window| window.raw_display_handle()), validation, &messenger_state)?);
+        partial.debug_messenger = Some(VkDebugMessenger::new(&entry, instance, validation, messenger_state)?);
+
+        partial.surface = window.map(|window| VkSurface::new(&entry, instance, window)).transpose()?;
+        let physical_device = VkPhysicalDevice::new(instance, partial.surface.as_ref(), device_selector)?;
+
+        let (logical_device, queue_families) = Self::create_logical_device(instance, &physical_device, partial.surface.as_ref(), validation)?;
+        let logical_device = partial.logical_device.insert(logical_device);
+        let allocator = VkDevice::create_allocator(instance, logical_device, physical_device.handle)?;
+
+        let device = partial.device.insert(VkDevice::new(
+            partial.instance.take().unwrap(),
+            partial.debug_messenger.take().unwrap(),
+            partial.logical_device.take().unwrap(),
+            allocator
+        )).clone();
 
-        let (device, queue_families) = Self::create_logical_device(&instance, &physical_device, &surface);
         let graphics_queue = VkQueue::new(&device, queue_families.graphics.unwrap());
         let present_queue = VkQueue::new(&device, queue_families.present.unwrap());
         let transfer_queue = VkQueue::new(&device, queue_families.transfer.unwrap());
 
-        let graphics_command_pool = VkCommandPool::new(&device, queue_families.graphics.unwrap());
-        let transfer_command_pool = VkCommandPool::new(&device, queue_families.transfer.unwrap());
-
-        let allocator = Allocator::new(&AllocatorCreateDesc {
-            instance: instance.clone(),
-            device: device.clone(),
-            physical_device: physical_device.handle,
-            debug_settings: Default::default(),
-            buffer_device_address: true,
-        }).expect("Failed to create a Vulkan Memory Allocator");
+        partial.graphics_command_pool = Some(VkCommandPool::new(&device, queue_families.graphics.unwrap())?);
+        partial.transfer_command_pool = Some(VkCommandPool::new(&device, queue_families.transfer.unwrap())?);
 
-        let descriptor_pool = VkDescriptorPool::new(&device);
+        let descriptor_pool = Arc::new(VkDescriptorPool::new(&device)?);
 
-        VkContext { 
+        Ok(VkContext { 
             entry, 
-            instance,
             device,
 
-            allocator: Some(allocator),
-
-            surface,
-            debug_messenger,
+            surface: partial.surface.take(),
             physical_device,
 
             graphics_queue,
             present_queue,
             transfer_queue,
 
-            graphics_command_pool,
-            transfer_command_pool,
+            graphics_command_pool: partial.graphics_command_pool.take().unwrap(),
+            transfer_command_pool: partial.transfer_command_pool.take().unwrap(),
 
             descriptor_pool
-        }
+        })
     }
 
-    fn create_instance(entry: &ash::Entry, raw_display_handle: raw_window_handle::RawDisplayHandle) -> ash::Instance {
-        if ENABLE_VALIDATION_LAYERS && check_validation_layer_support(entry) == false {
-            panic!("Validation layers requested, but not available!");
+    // Only creates what is needed to query the devices, with a window presenting to its surface is part of being suitable.
+    // Returns the table of VkPhysicalDevice::format_device_list
+    pub fn list_devices(window: Option<&winit::window::Window>) -> VkResult<String> {
+        let entry = ash::Entry::linked();
+        let validation = ValidationSettings::disabled();
+        let messenger_state = MessengerState::new(&validation);
+        // Destroys the surface and the instance again
+        let mut partial = PartialContext::default();
+
+        let instance = partial.instance.insert(Self::create_instance(&entry, window.map(|window| window.raw_display_handle()), &validation, &messenger_state)?);
+        partial.surface = window.map(|window| VkSurface::new(&entry, instance, window)).transpose()?;
+
+        let physical_devices = VkPhysicalDevice::enumerate(instance, partial.surface.as_ref())?;
+        Ok(VkPhysicalDevice::format_device_list(&physical_devices))
+    }
+
+    // `messenger_state` has to outlive the instance, it receives the messages of creating and destroying it
+    fn create_instance(
+        entry: &ash::Entry,
+        raw_display_handle: Option<raw_window_handle::RawDisplayHandle>,
+        validation: &ValidationSettings,
+        messenger_state: &MessengerState
+    ) -> VkResult<ash::Instance> {
+        if validation.enabled && !check_validation_layer_support(entry)? {
+            return Err(VkError::ValidationLayersUnavailable);
         }
 
         let app_name = CString::new(WINDOW_TITLE).unwrap();
@@ -112,14 +174,17 @@ impl VkContext {
         };
 
         // This create info used to debug issues in ash::vk::createInstance and ash::vk::destroyInstance.
-        let debug_utils_create_info = populate_debug_messenger_create_info();
+        let debug_utils_create_info = populate_debug_messenger_create_info(validation, messenger_state);
 
         // VK_EXT debug utils has been requested here.
-        let mut extension_names = ash_window::enumerate_required_extensions(raw_display_handle)
-            .expect("Failed to enumerate required instance extensions.")
-            .to_vec();
+        let mut extension_names = match raw_display_handle {
+            Some(raw_display_handle) => ash_window::enumerate_required_extensions(raw_display_handle)
+                .or_fail_to("enumerate required instance extensions")?
+                .to_vec(),
+            None => vec![],
+        };
         
-        if ENABLE_VALIDATION_LAYERS {
+        if validation.enabled {
             extension_names.push(ash::extensions::ext::DebugUtils::name().as_ptr());
         }
 
@@ -132,19 +197,19 @@ impl VkContext {
 
         let create_info = ash::vk::InstanceCreateInfo {
             s_type: ash::vk::StructureType::INSTANCE_CREATE_INFO,
-            p_next: if ENABLE_VALIDATION_LAYERS {
+            p_next: if validation.enabled {
                 &debug_utils_create_info as *const ash::vk::DebugUtilsMessengerCreateInfoEXT as *const c_void
             } else {
                 ptr::null()
             },
             flags: ash::vk::InstanceCreateFlags::empty(),
             p_application_info: &app_info,
-            pp_enabled_layer_names: if ENABLE_VALIDATION_LAYERS {
+            pp_enabled_layer_names: if validation.enabled {
                 enabled_layer_names.as_ptr()
             } else {
                 ptr::null()
             },
-            enabled_layer_count: if ENABLE_VALIDATION_LAYERS {
+            enabled_layer_count: if validation.enabled {
                 enabled_layer_names.len()
             } else {
                 0
@@ -153,18 +218,17 @@ impl VkContext {
             enabled_extension_count: extension_names.len() as u32,
         };
 
-        let instance: ash::Instance = unsafe {
-            entry.create_instance(&create_info, None).expect("Failed to create Instance!")
-        };
-
-        instance
+        unsafe {
+            entry.create_instance(&create_info, None).or_fail_to("create Instance")
+        }
     }
 
     fn create_logical_device(
         instance: &ash::Instance,
         physical_device: &VkPhysicalDevice,
-        surface: &VkSurface
-    ) -> (ash::Device, VkQueueFamilyIndices) {
+        surface: Option<&VkSurface>,
+        validation: &ValidationSettings
+    ) -> VkResult<(ash::Device, VkQueueFamilyIndices)> {
         let indices = VkQueueFamilyIndices::find(instance, physical_device.handle, surface);
 
         let mut unique_queue_families = HashSet::new();
@@ -189,14 +253,6 @@ impl VkContext {
             sampler_anisotropy: 1,
             ..Default::default()
         };
-        let physical_device_buffer_features = ash::vk::PhysicalDeviceBufferDeviceAddressFeatures {
-            s_type: ash::vk::StructureType::PHYSICAL_DEVICE_BUFFER_DEVICE_ADDRESS_FEATURES,
-            p_next: std::ptr::null_mut(),
-            buffer_device_address: 1,
-            buffer_device_address_capture_replay: 0,
-            buffer_device_address_multi_device: 0,
-            ..Default::default()
-        };
 
         let required_validation_layers_raw_names: Vec<CString> = VALIDATION_LAYERS.iter().map(
             |layer_name| CString::new(*layer_name).unwrap()
@@ -206,7 +262,9 @@ impl VkContext {
             |layer_name| layer_name.as_ptr()
         ).collect();
 
-        let device_extensions_raw_names: Vec<CString> = DEVICE_EXTENSIONS.iter().map(
+        // The swapchain extension is only needed to present
+        let device_extensions: &[&str] = if surface.is_some() { &DEVICE_EXTENSIONS } else { &[] };
+        let device_extensions_raw_names: Vec<CString> = device_extensions.iter().map(
             |extension_name| CString::new(*extension_name).unwrap()
         ).collect();
 
@@ -216,16 +274,16 @@ impl VkContext {
 
         let device_create_info = ash::vk::DeviceCreateInfo {
             s_type: ash::vk::StructureType::DEVICE_CREATE_INFO,
-            p_next: &physical_device_buffer_features as *const ash::vk::PhysicalDeviceBufferDeviceAddressFeatures as *const c_void,
+            p_next: ptr::null(),
             flags: ash::vk::DeviceCreateFlags::empty(),
             queue_create_info_count: queue_create_infos.len() as u32,
             p_queue_create_infos: queue_create_infos.as_ptr(),
-            enabled_layer_count: if ENABLE_VALIDATION_LAYERS {
+            enabled_layer_count: if validation.enabled {
                 enable_validation_layers_names.len()
             } else {
                 0
             } as u32,
-            pp_enabled_layer_names: if ENABLE_VALIDATION_LAYERS {
+            pp_enabled_layer_names: if validation.enabled {
                 enable_validation_layers_names.as_ptr()
             } else {
                 ptr::null()
@@ -236,30 +294,27 @@ impl VkContext {
         };
 
         let device: ash::Device = unsafe {
-            instance.create_device(physical_device.handle, &device_create_info, None).expect("Failed to create logical Device!")
+            instance.create_device(physical_device.handle, &device_create_info, None).or_fail_to("create logical Device")?
         };
 
-        (device, indices)
+        Ok((device, indices))
     }
 }
 
 impl Drop for VkContext {
+    // Whatever was created from the device and is still alive keeps it alive, the device, allocator and instance
+    // go with the last Arc<VkDevice>. Command buffers go with their pools and the swapchain has to be gone already.
     fn drop(&mut self) {
         unsafe {
-            self.descriptor_pool.destroy(&self.device);
+            // Nothing left to recover when this fails while tearing down
+            let _ = self.device.device_wait_idle();
 
             self.graphics_command_pool.destroy(&self.device);
             self.transfer_command_pool.destroy(&self.device);
 
-            let mut alloc: Option<Allocator> = None;
-            std::mem::swap(&mut alloc, &mut self.allocator);
-            drop(alloc);
-
-            self.surface.destroy();
-            self.debug_messenger.destroy();
-            self.device.destroy_device(None);
-
-            self.instance.destroy_instance(None);
+            if let Some(surface) = &self.surface {
+                surface.destroy();
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,32 +1,89 @@
+use crate::{utilities, vk::error::*};
 
-
-use crate::utilities;
-
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::os::raw::c_void;
 use std::ptr;
+use std::sync::{Mutex, PoisonError};
 
 pub const VALIDATION_LAYERS: [&'static str; 1] = [
     "VK_LAYER_KHRONOS_validation"
 ];
 
-#[cfg(not(debug_assertions))]
-pub const ENABLE_VALIDATION_LAYERS: bool = false;
-#[cfg(debug_assertions)]
-pub const ENABLE_VALIDATION_LAYERS: bool = true;
+// Chosen when the instance is created, messages are logged under the "vulkan" target
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidationSettings {
+    pub enabled: bool,
+    // Less severe messages are not requested from the layers, the `log` filter applies on top of this
+    pub level: log::Level,
+    // Validation errors are kept and panic at the next VkDevice::check_validation, meant for tests
+    pub panic_on_error: bool,
+}
+
+impl Default for ValidationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: cfg!(debug_assertions),
+            level: log::Level::Warn,
+            panic_on_error: false,
+        }
+    }
+}
+
+impl ValidationSettings {
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+
+    fn message_severity(&self) -> ash::vk::DebugUtilsMessageSeverityFlagsEXT {
+        let mut severity = ash::vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+        if self.level >= log::Level::Warn {
+            severity |= ash::vk::DebugUtilsMessageSeverityFlagsEXT::WARNING;
+        }
+        if self.level >= log::Level::Info {
+            severity |= ash::vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+        }
+        if self.level >= log::Level::Debug {
+            severity |= ash::vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+        }
+        severity
+    }
+}
+
+// Handed to the callback through p_user_data, it has to stay at the same address until the instance is destroyed
+pub struct MessengerState {
+    panic_on_error: bool,
+    first_error: Mutex<Option<String>>,
+}
+
+impl MessengerState {
+    pub fn new(settings: &ValidationSettings) -> Box<Self> {
+        Box::new(Self {
+            panic_on_error: settings.panic_on_error,
+            first_error: Mutex::new(None),
+        })
+    }
+
+    fn record_error(&self, message: &str) {
+        if self.panic_on_error {
+            self.first_error.lock().unwrap_or_else(PoisonError::into_inner).get_or_insert_with(|| message.to_owned());
+        }
+    }
+}
 
 unsafe extern "system" fn vulkan_debug_utils_callback(
     message_severity: ash::vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: ash::vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const ash::vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut c_void,
+    p_user_data: *mut c_void,
 ) -> ash::vk::Bool32 {
-    let severity = match message_severity {
-        ash::vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => "[Verbose]",
-        ash::vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => "[Warning]",
-        ash::vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => "[Error]",
-        ash::vk::DebugUtilsMessageSeverityFlagsEXT::INFO => "[Info]",
-        _ => "[Unknown]",
+    let level = match message_severity {
+        ash::vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => log::Level::Error,
+        ash::vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => log::Level::Warn,
+        ash::vk::DebugUtilsMessageSeverityFlagsEXT::INFO => log::Level::Info,
+        _ => log::Level::Debug,
     };
     let types = match message_type {
         ash::vk::DebugUtilsMessageTypeFlagsEXT::GENERAL => "[General]",
@@ -34,18 +91,23 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
         ash::vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION => "[Validation]",
         _ => "[Unknown]",
     };
-    let message = CStr::from_ptr((*p_callback_data).p_message);
-    println!("[Debug]{}{}{:?}", severity, types, message);
+    let message = CStr::from_ptr((*p_callback_data).p_message).to_string_lossy();
+    log::log!(target: "vulkan", level, "{}{}", types, message);
+
+    // Panicking here would unwind into the driver, the error is raised by check_errors instead
+    if level == log::Level::Error && message_type.contains(ash::vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION) && !p_user_data.is_null() {
+        (*(p_user_data as *const MessengerState)).record_error(&message);
+    }
 
     ash::vk::FALSE
 }
 
-pub fn check_validation_layer_support(entry: &ash::Entry) -> bool {
-    let layer_properties = entry.enumerate_instance_layer_properties().expect("Failed to enumerate Instance Layers Properties!");
+pub fn check_validation_layer_support(entry: &ash::Entry) -> VkResult<bool> {
+    let layer_properties = entry.enumerate_instance_layer_properties().or_fail_to("enumerate Instance Layers Properties")?;
 
     if layer_properties.len() <= 0 {
-        eprintln!("No available layers.");
-        return false;
+        log::warn!("No available layers.");
+        return Ok(false);
     }
 
     for required_layer_name in VALIDATION_LAYERS.iter() {
@@ -60,63 +122,117 @@ pub fn check_validation_layer_support(entry: &ash::Entry) -> bool {
         }
 
         if is_layer_found == false {
-            return false;
+            return Ok(false);
         }
     }
 
-    true
+    Ok(true)
 }
 
-pub fn populate_debug_messenger_create_info() -> ash::vk::DebugUtilsMessengerCreateInfoEXT {
+pub fn populate_debug_messenger_create_info(settings: &ValidationSettings, state: &MessengerState) -> ash::vk::DebugUtilsMessengerCreateInfoEXT {
     ash::vk::DebugUtilsMessengerCreateInfoEXT {
         s_type: ash::vk::StructureType::DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
         p_next: ptr::null(),
         flags: ash::vk::DebugUtilsMessengerCreateFlagsEXT::empty(),
-        message_severity: ash::vk::DebugUtilsMessageSeverityFlagsEXT::WARNING |
-            // ash::vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE |
-            // ash::vk::DebugUtilsMessageSeverityFlagsEXT::INFO |
-            ash::vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+        message_severity: settings.message_severity(),
         message_type: ash::vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
             | ash::vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
             | ash::vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
         pfn_user_callback: Some(vulkan_debug_utils_callback),
-        p_user_data: ptr::null_mut(),
+        p_user_data: state as *const MessengerState as *mut c_void,
     }
 }
 
-#[derive(Clone)]
+// Also names objects and labels command buffer regions, which only does something with validation enabled
 pub struct VkDebugMessenger {
     debug_utils: ash::extensions::ext::DebugUtils,
-    messenger: ash::vk::DebugUtilsMessengerEXT
+    messenger: ash::vk::DebugUtilsMessengerEXT,
+    enabled: bool,
+    state: Box<MessengerState>,
 }
 
 impl VkDebugMessenger {
-    pub fn new(entry: &ash::Entry, instance: &ash::Instance) -> Self {
+    // `state` has to be the one the instance was created with
+    pub fn new(entry: &ash::Entry, instance: &ash::Instance, settings: &ValidationSettings, state: Box<MessengerState>) -> VkResult<Self> {
         let debug_utils = ash::extensions::ext::DebugUtils::new(entry, instance);
 
-        if ENABLE_VALIDATION_LAYERS == false {
-            Self {
+        if !settings.enabled {
+            Ok(Self {
                 debug_utils,
-                messenger: ash::vk::DebugUtilsMessengerEXT::null()
-            }
+                messenger: ash::vk::DebugUtilsMessengerEXT::null(),
+                enabled: false,
+                state,
+            })
         } else {
-            let messenger_ci = self::populate_debug_messenger_create_info();
+            let messenger_ci = self::populate_debug_messenger_create_info(settings, &state);
 
             let messenger = unsafe {
-                debug_utils.create_debug_utils_messenger(&messenger_ci, None).expect("Debug Utils Callback")
+                debug_utils.create_debug_utils_messenger(&messenger_ci, None).or_fail_to("create Debug Utils Messenger")?
             };
-            Self {
-                debug_utils, 
-                messenger
-            }
+            Ok(Self {
+                debug_utils,
+                messenger,
+                enabled: true,
+                state,
+            })
         }
     }
 
     pub fn destroy(&self) {
-        if ENABLE_VALIDATION_LAYERS {
+        if self.enabled {
             unsafe {
                 self.debug_utils.destroy_debug_utils_messenger(self.messenger, None);
             }
         }
     }
-}
\ No newline at end of file
+
+    // Panics with the first validation error since the last check when ValidationSettings::panic_on_error is set
+    pub fn check_errors(&self) {
+        let error = self.state.first_error.lock().unwrap_or_else(PoisonError::into_inner).take();
+        if let Some(error) = error {
+            panic!("Vulkan validation error: {}", error);
+        }
+    }
+
+    // Shows up in validation messages and in debuggers like RenderDoc
+    pub fn set_object_name<H: ash::vk::Handle>(&self, device: &ash::Device, handle: H, name: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        let name = CString::new(name).unwrap_or_default();
+        let name_info = ash::vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(H::TYPE)
+            .object_handle(handle.as_raw())
+            .object_name(&name)
+            .build();
+
+        unsafe {
+            // Only a debugging aid, rendering works the same without the name
+            let _ = self.debug_utils.set_debug_utils_object_name(device.handle(), &name_info);
+        }
+    }
+
+    pub fn begin_label(&self, command_buffer: ash::vk::CommandBuffer, name: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        let name = CString::new(name).unwrap_or_default();
+        let label = ash::vk::DebugUtilsLabelEXT::builder()
+            .label_name(&name)
+            .build();
+
+        unsafe {
+            self.debug_utils.cmd_begin_debug_utils_label(command_buffer, &label);
+        }
+    }
+
+    pub fn end_label(&self, command_buffer: ash::vk::CommandBuffer) {
+        if self.enabled {
+            unsafe {
+                self.debug_utils.cmd_end_debug_utils_label(command_buffer);
+            }
+        }
+    }
+}
@@ -1,14 +1,19 @@
+use std::sync::Arc;
+
+use crate::vk::{device::VkDevice, error::*};
+
 const DESCRIPTOR_POOL_SIZES_COUNT: u32 = 64; 
 
-#[derive(Clone, Copy, Debug, Default)]
 pub struct VkDescriptorPool {
     pub handle: ash::vk::DescriptorPool,
+    device: Arc<VkDevice>,
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+// Freed back to the pool when dropped, the pool stays alive as long as any of its sets
 pub struct VkDescriptorSet {
     pub handle: ash::vk::DescriptorSet,
-    pub layout: ash::vk::DescriptorSetLayout
+    pub layout: ash::vk::DescriptorSetLayout,
+    pool: Arc<VkDescriptorPool>,
 }
 #[derive(Clone, Copy, Debug, Default)]
 pub struct VkDescriptorSetSlot {
@@ -18,7 +23,7 @@ pub struct VkDescriptorSetSlot {
 }
 
 impl VkDescriptorSet {
-    pub fn update(&self, device: &ash::Device, slots: &Vec<VkDescriptorSetSlot>) {
+    pub fn update(&self, slots: &Vec<VkDescriptorSetSlot>) {
         let mut descriptor_write_sets = vec![];
         let mut image_infos = vec![];
         let mut buffer_infos = vec![];
@@ -67,14 +72,24 @@ impl VkDescriptorSet {
         }
 
         unsafe {
-            device.update_descriptor_sets(&descriptor_write_sets, &[]);
+            self.pool.device.update_descriptor_sets(&descriptor_write_sets, &[]);
         }
 
     }
 }
 
+impl Drop for VkDescriptorSet {
+    fn drop(&mut self) {
+        unsafe {
+            self.pool.device.destroy_descriptor_set_layout(self.layout, None);
+            // Nothing left to recover when this fails while tearing down
+            let _ = self.pool.device.free_descriptor_sets(self.pool.handle, &[self.handle]);
+        }
+    }
+}
+
 impl VkDescriptorPool {
-    pub fn new(device: &ash::Device) -> Self {
+    pub fn new(device: &Arc<VkDevice>) -> VkResult<Self> {
         let pool_sizes = [
             ash::vk::DescriptorPoolSize {
                 ty: ash::vk::DescriptorType::UNIFORM_BUFFER,
@@ -109,21 +124,18 @@ impl VkDescriptorPool {
             .build();
 
         let descriptor_pool = unsafe {
-            device.create_descriptor_pool(&descriptor_pool_create_info, None).expect("Failed to create Descriptor pool!")
+            device.create_descriptor_pool(&descriptor_pool_create_info, None).or_fail_to("create Descriptor pool")?
         };
 
-        Self {
-            handle: descriptor_pool
-        }
+        Ok(Self {
+            handle: descriptor_pool,
+            device: device.clone(),
+        })
     }
 
-    pub fn destroy(&self, device: &ash::Device) {
-        unsafe {
-            device.destroy_descriptor_pool(self.handle, None);
-        }
-    }
+    pub fn allocate(self: &Arc<Self>, slots: &Vec<VkDescriptorSetSlot>) -> VkResult<VkDescriptorSet> {
+        let device = &self.device;
 
-    pub fn allocate(&self, device: &ash::Device, slots: &Vec<VkDescriptorSetSlot>) -> VkDescriptorSet {
         let layout_bindings: Vec<ash::vk::DescriptorSetLayoutBinding> = slots.iter().map(
             |slot| slot.binding
         ).collect();
@@ -133,7 +145,7 @@ impl VkDescriptorPool {
             .build();
 
         let layout = unsafe {
-            device.create_descriptor_set_layout(&layout_create_info, None).expect("Failed to create Descriptor Set Layout!")
+            device.create_descriptor_set_layout(&layout_create_info, None).or_fail_to("create Descriptor Set Layout")?
         };
 
         let allocate_info = ash::vk::DescriptorSetAllocateInfo::builder()
@@ -141,21 +153,26 @@ impl VkDescriptorPool {
             .set_layouts(&[layout])
             .build();
 
-        let handle = unsafe {
-            device.allocate_descriptor_sets(&allocate_info).expect("Failed to allocate a descriptor set!")[0]
+        let handle = match unsafe { device.allocate_descriptor_sets(&allocate_info) } {
+            Ok(handles) => handles[0],
+            Err(result) => {
+                unsafe { device.destroy_descriptor_set_layout(layout, None) };
+                return Err(VkError::Vulkan { action: "allocate a descriptor set", result });
+            }
         };
 
-        let descriptor_set = VkDescriptorSet { handle, layout };
+        let descriptor_set = VkDescriptorSet { handle, layout, pool: self.clone() };
 
-        descriptor_set.update(device, slots);
+        descriptor_set.update(slots);
 
-        descriptor_set
+        Ok(descriptor_set)
     }
+}
 
-    pub fn deallocate(&self, device: &ash::Device, target: &VkDescriptorSet) {
+impl Drop for VkDescriptorPool {
+    fn drop(&mut self) {
         unsafe {
-            device.free_descriptor_sets(self.handle, &[target.handle]).expect("Failed to free descriptor sets!");
-            device.destroy_descriptor_set_layout(target.layout, None);
+            self.device.destroy_descriptor_pool(self.handle, None);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+use gpu_allocator::vulkan::*;
+
+use std::mem::ManuallyDrop;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::vk::{debug_messenger::VkDebugMessenger, error::*};
+
+// The logical device together with what has to outlive everything created from it. Buffers, textures,
+// pipelines, sync objects and descriptor sets each hold an Arc of this and free themselves when dropped,
+// so the allocator, device, debug messenger and instance are destroyed after the last of them is gone.
+pub struct VkDevice {
+    pub instance: ash::Instance,
+    handle: ash::Device,
+    allocator: ManuallyDrop<Mutex<Allocator>>,
+    debug_messenger: VkDebugMessenger,
+}
+
+impl VkDevice {
+    // Created before the VkDevice, so a failure leaves the caller owning the instance and the device to clean up
+    pub fn create_allocator(instance: &ash::Instance, device: &ash::Device, physical_device: ash::vk::PhysicalDevice) -> VkResult<Allocator> {
+        Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: device.clone(),
+            physical_device,
+            debug_settings: Default::default(),
+            // Buffers are only bound through descriptors, Vulkan 1.0 has no device addresses
+            buffer_device_address: false,
+        }).or_fail_to("create a Vulkan Memory Allocator")
+    }
+
+    // Takes ownership of the instance and the device, both are destroyed when the last Arc is dropped
+    pub fn new(
+        instance: ash::Instance,
+        debug_messenger: VkDebugMessenger,
+        device: ash::Device,
+        allocator: Allocator
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            instance,
+            handle: device,
+            allocator: ManuallyDrop::new(Mutex::new(allocator)),
+            debug_messenger,
+        })
+    }
+
+    pub fn allocator(&self) -> MutexGuard<'_, Allocator> {
+        // A panic while allocating doesn't leave the allocator in a state worth refusing to free memory over
+        self.allocator.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn debug_messenger(&self) -> &VkDebugMessenger {
+        &self.debug_messenger
+    }
+
+    pub fn set_object_name<H: ash::vk::Handle>(&self, handle: H, name: &str) {
+        self.debug_messenger.set_object_name(&self.handle, handle, name);
+    }
+
+    // See ValidationSettings::panic_on_error
+    pub fn check_validation(&self) {
+        self.debug_messenger.check_errors();
+    }
+}
+
+impl std::ops::Deref for VkDevice {
+    type Target = ash::Device;
+
+    fn deref(&self) -> &ash::Device {
+        &self.handle
+    }
+}
+
+impl Drop for VkDevice {
+    fn drop(&mut self) {
+        unsafe {
+            // The allocator frees its memory blocks through the device
+            ManuallyDrop::drop(&mut self.allocator);
+            self.handle.destroy_device(None);
+
+            // Kept until here so leaked objects are still reported when the device is destroyed
+            self.debug_messenger.destroy();
+            self.instance.destroy_instance(None);
+        }
+
+        // Leaks are reported while destroying the device
+        if !std::thread::panicking() {
+            self.debug_messenger.check_errors();
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+// Everything in `vk` that can fail returns this instead of panicking, so the caller decides whether it is fatal
+#[derive(Debug)]
+pub enum VkError {
+    // A Vulkan call returned an error, `action` describes what was being done
+    Vulkan { action: &'static str, result: ash::vk::Result },
+    Allocation { action: &'static str, error: gpu_allocator::AllocationError },
+    ValidationLayersUnavailable,
+    NoSuitableDevice(String),
+    InvalidSpirv(std::io::Error),
+    InvalidImage(&'static str),
+    Io { path: std::path::PathBuf, error: std::io::Error },
+    Model { path: std::path::PathBuf, reason: String },
+    // One of the fixed size arrays of the Scene buffer is full, `capacity` is its size
+    SceneCapacity { what: &'static str, capacity: usize },
+    // Shapes without an inside, like disks, in a CSG tree
+    InvalidCsgOperand(&'static str),
+}
+
+pub type VkResult<T> = Result<T, VkError>;
+
+impl fmt::Display for VkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VkError::Vulkan { action, result } => write!(f, "Failed to {}: {}", action, result),
+            VkError::Allocation { action, error } => write!(f, "Failed to {}: {}", action, error),
+            VkError::ValidationLayersUnavailable => write!(f, "Validation layers requested, but not available"),
+            VkError::NoSuitableDevice(reason) => write!(f, "No suitable GPU: {}", reason),
+            VkError::InvalidSpirv(error) => write!(f, "Invalid SPIR-V: {}", error),
+            VkError::InvalidImage(reason) => write!(f, "Invalid image: {}", reason),
+            VkError::Io { path, error } => write!(f, "Failed to read {}: {}", path.display(), error),
+            VkError::Model { path, reason } => write!(f, "Failed to load {}: {}", path.display(), reason),
+            VkError::SceneCapacity { what, capacity } => write!(f, "The scene has no room left for {}, it holds {}", what, capacity),
+            VkError::InvalidCsgOperand(shapes) => write!(f, "CSG needs solid shapes, {} have no inside", shapes),
+        }
+    }
+}
+
+impl std::error::Error for VkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VkError::Vulkan { result, .. } => Some(result),
+            VkError::Allocation { error, .. } => Some(error),
+            VkError::InvalidSpirv(error) | VkError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Turns the error of an ash or gpu_allocator call into a `VkError`, replaces `.expect("Failed to ...")`
+pub trait VkResultExt<T> {
+    fn or_fail_to(self, action: &'static str) -> VkResult<T>;
+}
+
+impl<T> VkResultExt<T> for Result<T, ash::vk::Result> {
+    fn or_fail_to(self, action: &'static str) -> VkResult<T> {
+        self.map_err(|result| VkError::Vulkan { action, result })
+    }
+}
+
+impl<T> VkResultExt<T> for Result<T, gpu_allocator::AllocationError> {
+    fn or_fail_to(self, action: &'static str) -> VkResult<T> {
+        self.map_err(|error| VkError::Allocation { action, error })
+    }
+}
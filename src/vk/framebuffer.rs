@@ -1,6 +1,6 @@
 
 
-use crate::vk::renderpass::*;
+use crate::vk::{renderpass::*, error::*};
 
 pub struct VkFramebuffer {
     pub handle: ash::vk::Framebuffer,
@@ -8,7 +8,7 @@ pub struct VkFramebuffer {
 }
 
 impl VkFramebuffer {
-    pub fn new(device: &ash::Device, extent: ash::vk::Extent2D, views: &Vec<ash::vk::ImageView>, render_pass: &VkRenderPass) -> Self {
+    pub fn new(device: &ash::Device, extent: ash::vk::Extent2D, views: &[ash::vk::ImageView], render_pass: &VkRenderPass) -> VkResult<Self> {
         let create_info = ash::vk::FramebufferCreateInfo {
             s_type: ash::vk::StructureType::FRAMEBUFFER_CREATE_INFO,
             p_next: std::ptr::null(),
@@ -22,13 +22,13 @@ impl VkFramebuffer {
         };
 
         let framebuffer = unsafe {
-            device.create_framebuffer(&create_info, None).expect("Failed to create a framebuffer!")
+            device.create_framebuffer(&create_info, None).or_fail_to("create a framebuffer")?
         };
 
-        Self {
+        Ok(Self {
             handle: framebuffer,
             extent
-        }
+        })
     }
     pub fn destroy(&self, device: &ash::Device) {
         unsafe {
@@ -6,7 +6,8 @@ use crate::{
     utilities,
     vk::{
         renderpass::*,
-        vertex::Vertex
+        vertex::Vertex,
+        error::*
     }
 };
 
@@ -24,15 +25,15 @@ impl VkGraphicsPipeline {
         render_pass: &VkRenderPass,
         descriptor_set_layouts: &Vec<ash::vk::DescriptorSetLayout>,
         push_constant_ranges: &Vec<ash::vk::PushConstantRange>,
-    ) -> Self {
+    ) -> VkResult<Self> {
         let mut shader_stages = Vec::new();
         let mut shader_modules = Vec::new();
         
         let main_function_name = CString::new("main").unwrap();
 
         if let Some(path) = vert_shader_path {
-            let vert_shader_code = utilities::read_spirv(std::path::Path::new(path));
-            let vert_shader_module = utilities::create_shader_module(device, &vert_shader_code);
+            let vert_shader_code = utilities::read_spirv(std::path::Path::new(path))?;
+            let vert_shader_module = utilities::create_shader_module(device, &vert_shader_code)?;
 
             shader_modules.push(vert_shader_module.clone());
             shader_stages.push(ash::vk::PipelineShaderStageCreateInfo {
@@ -47,8 +48,8 @@ impl VkGraphicsPipeline {
         }
 
         if let Some(path) = frag_shader_path {
-            let frag_shader_code = utilities::read_spirv(std::path::Path::new(path));
-            let frag_shader_module = utilities::create_shader_module(device, &frag_shader_code);
+            let frag_shader_code = utilities::read_spirv(std::path::Path::new(path))?;
+            let frag_shader_module = utilities::create_shader_module(device, &frag_shader_code)?;
 
             shader_modules.push(frag_shader_module.clone());
             shader_stages.push(ash::vk::PipelineShaderStageCreateInfo {
@@ -213,7 +214,7 @@ impl VkGraphicsPipeline {
         };
 
         let pipeline_layout = unsafe {
-            device.create_pipeline_layout(&pipeline_layout_create_info, None).expect("Failed to create pipeline layout!")
+            device.create_pipeline_layout(&pipeline_layout_create_info, None).or_fail_to("create pipeline layout")?
         };
 
         let graphic_pipeline_create_infos = [ash::vk::GraphicsPipelineCreateInfo {
@@ -240,7 +241,8 @@ impl VkGraphicsPipeline {
 
         let graphics_pipelines = unsafe {
             device.create_graphics_pipelines(ash::vk::PipelineCache::null(), &graphic_pipeline_create_infos, None)
-                .expect("Failed to create Graphics Pipeline!.")
+                .map_err(|(_, result)| result)
+                .or_fail_to("create Graphics Pipeline")?
         };
 
         unsafe {
@@ -249,10 +251,10 @@ impl VkGraphicsPipeline {
             }
         }
 
-        Self {  
+        Ok(Self {  
             handle: graphics_pipelines[0],
             layout: pipeline_layout
-        }
+        })
     }
     pub fn destroy(&self, device: &ash::Device) {
         unsafe {
@@ -1,4 +1,6 @@
 pub mod context;
+pub mod device;
+pub mod error;
 pub mod swapchain;
 pub mod surface;
 pub mod physical_device;
@@ -16,4 +18,4 @@ pub mod texture;
 pub mod descriptor_pool;
 pub mod graphics_pipeline;
 pub mod compute_pipeline;
-pub mod raytracing_pipeline;
\ No newline at end of file
+pub mod query_pool;
@@ -6,61 +6,202 @@ use crate::{
     vk::{
         swapchain::*,
         queue_family_indices::*,
-        surface::*
+        surface::*,
+        error::*
     }
 };
 
-pub const DEVICE_EXTENSIONS: [&'static str; 1] = [
+pub const DEVICE_EXTENSIONS: [&str; 1] = [
     "VK_KHR_swapchain",
 ];
 
+// Not required, but devices that have them are preferred
+pub const RAY_TRACING_DEVICE_EXTENSIONS: [&str; 3] = [
+    "VK_KHR_acceleration_structure",
+    "VK_KHR_ray_tracing_pipeline",
+    "VK_KHR_deferred_host_operations",
+];
+
+// Format of the render target the compute shaders write to
+const STORAGE_IMAGE_FORMAT: ash::vk::Format = ash::vk::Format::B8G8R8A8_UNORM;
+
+// Which device to render on, from `--device <index|name>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Best,
+    Index(usize),
+    Name(String),
+}
+
+impl DeviceSelector {
+    pub fn parse(value: &str) -> DeviceSelector {
+        match value.parse::<usize>() {
+            Ok(index) => DeviceSelector::Index(index),
+            Err(_) => DeviceSelector::Name(value.to_string()),
+        }
+    }
+
+    // Names match when they contain the given text, ignoring case
+    fn matches(&self, index: usize, physical_device: &VkPhysicalDevice) -> bool {
+        match self {
+            DeviceSelector::Best => true,
+            DeviceSelector::Index(i) => *i == index,
+            DeviceSelector::Name(name) => physical_device.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct VkPhysicalDevice {
     pub handle: ash::vk::PhysicalDevice,
     pub name: String,
-    pub queue_family_indices: VkQueueFamilyIndices
+    pub device_type: ash::vk::PhysicalDeviceType,
+    pub queue_family_indices: VkQueueFamilyIndices,
+    pub is_suitable: bool,
+    pub supports_storage_images: bool,
+    pub supports_ray_tracing: bool,
+    // Whether graphics and compute queues can write timestamps, and how many nanoseconds one tick is
+    pub supports_timestamps: bool,
+    pub timestamp_period: f32,
+    // How many of the low bits of the graphics queue's timestamps are meaningful, the rest is garbage
+    pub timestamp_valid_bits: u32,
 }
 
 impl VkPhysicalDevice {
-    pub fn new(instance: &ash::Instance, surface: &VkSurface) -> Self {
-        let physical_devices: Vec<VkPhysicalDevice> = unsafe {
-            instance.enumerate_physical_devices().expect("Failed to enumerate Physical Devices!").into_iter().map(
+    pub fn new(instance: &ash::Instance, surface: Option<&VkSurface>, selector: &DeviceSelector) -> VkResult<Self> {
+        let physical_devices = Self::enumerate(instance, surface)?;
+
+        log::info!("Found {} devices (GPU) with vulkan support.", physical_devices.len());
+
+        let mut candidates: Vec<&VkPhysicalDevice> = physical_devices.iter().enumerate().filter(
+            |(index, physical_device)| selector.matches(*index, physical_device)
+        ).map(|(_, physical_device)| physical_device).collect();
+
+        if candidates.is_empty() {
+            log::warn!("Available devices:\n{}", Self::format_device_list(&physical_devices));
+            return Err(VkError::NoSuitableDevice(format!("no device matches {:?}", selector)));
+        }
+
+        // Stable, so equally ranked devices keep the driver's order
+        candidates.sort_by_key(|physical_device| std::cmp::Reverse(physical_device.rank()));
+
+        let result = match candidates.into_iter().find(|physical_device| physical_device.is_suitable) {
+            Some(physical_device) => physical_device.clone(),
+            None => {
+                log::warn!("Available devices:\n{}", Self::format_device_list(&physical_devices));
+                return Err(VkError::NoSuitableDevice(format!("none of the devices matching {:?} can present and run the compute shaders", selector)));
+            }
+        };
+
+        log::info!("Picked {} as the vulkan physical device.", result.name);
+
+        log::debug!("\tGraphics queue index: {}", result.queue_family_indices.graphics.unwrap());
+        log::debug!("\tPresent queue index: {}", result.queue_family_indices.present.unwrap());
+        log::debug!("\tTransfer queue index: {}", result.queue_family_indices.transfer.unwrap());
+
+        Ok(result)
+    }
+
+    // In the order the driver reports them, which is what `Index` refers to
+    pub fn enumerate(instance: &ash::Instance, surface: Option<&VkSurface>) -> VkResult<Vec<VkPhysicalDevice>> {
+        unsafe {
+            instance.enumerate_physical_devices().or_fail_to("enumerate Physical Devices")?.into_iter().map(
                 |physical_device| Self::from_native(instance, physical_device, surface)
             ).collect()
+        }
+    }
+
+    pub fn from_native(instance: &ash::Instance, native_physical_device: ash::vk::PhysicalDevice, surface: Option<&VkSurface>) -> VkResult<Self> {
+        let device_properties = unsafe { 
+            instance.get_physical_device_properties(native_physical_device) 
+        };
+        let available_extensions = unsafe {
+            instance.enumerate_device_extension_properties(native_physical_device).or_fail_to("get device extension properties")?
         };
 
-        println!("Found {} devices (GPU) with vulkan support.", physical_devices.len());
+        let queue_family_indices = VkQueueFamilyIndices::find(instance, native_physical_device, surface);
+        let timestamp_valid_bits = queue_family_indices.graphics.map_or(0, |graphics| unsafe {
+            instance.get_physical_device_queue_family_properties(native_physical_device)[graphics as usize].timestamp_valid_bits
+        });
 
-        let result = physical_devices.iter().find(|&physical_device| {
-            Self::is_physical_device_suitable(instance, physical_device, surface)
-        }).expect("Failed to find a suitable GPU!").clone();
+        let mut physical_device = VkPhysicalDevice { 
+            handle: native_physical_device, 
+            name: utilities::cchar_to_string(&device_properties.device_name),
+            device_type: device_properties.device_type,
+            queue_family_indices,
+            is_suitable: false,
+            supports_storage_images: Self::query_storage_image_support(instance, native_physical_device),
+            supports_ray_tracing: Self::query_extensions_support(&available_extensions, &RAY_TRACING_DEVICE_EXTENSIONS),
+            supports_timestamps: device_properties.limits.timestamp_compute_and_graphics == ash::vk::TRUE && timestamp_valid_bits > 0,
+            timestamp_period: device_properties.limits.timestamp_period,
+            timestamp_valid_bits,
+        };
+        physical_device.is_suitable = Self::is_physical_device_suitable(instance, &physical_device, surface, &available_extensions);
 
-        println!("Picked {} as the vulkan physical device.", result.name);
+        Ok(physical_device)
+    }
 
-        println!("\tGraphics queue index: {}", result.queue_family_indices.graphics.unwrap());
-        println!("\tPresent queue index: {}", result.queue_family_indices.present.unwrap());
-        println!("\tTransfer queue index: {}", result.queue_family_indices.transfer.unwrap());
+    // Higher is better, the device type weighs more than ray tracing support
+    pub fn rank(&self) -> (u32, bool) {
+        let type_rank = match self.device_type {
+            ash::vk::PhysicalDeviceType::DISCRETE_GPU => 3,
+            ash::vk::PhysicalDeviceType::INTEGRATED_GPU => 2,
+            ash::vk::PhysicalDeviceType::VIRTUAL_GPU => 1,
+            _ => 0,
+        };
 
-        result 
+        (type_rank, self.supports_ray_tracing)
     }
 
-    pub fn from_native(instance: &ash::Instance, native_physical_device: ash::vk::PhysicalDevice, surface: &VkSurface) -> Self {
-        VkPhysicalDevice { 
-            handle: native_physical_device, 
-            name: Self::get_physical_device_name(instance, native_physical_device),
-            queue_family_indices: VkQueueFamilyIndices::find(instance, native_physical_device, surface)
+    pub fn device_type_name(&self) -> &'static str {
+        match self.device_type {
+            ash::vk::PhysicalDeviceType::CPU => "Cpu",
+            ash::vk::PhysicalDeviceType::INTEGRATED_GPU => "Integrated GPU",
+            ash::vk::PhysicalDeviceType::DISCRETE_GPU => "Discrete GPU",
+            ash::vk::PhysicalDeviceType::VIRTUAL_GPU => "Virtual GPU",
+            _ => "Unknown",
         }
     }
 
-    fn get_physical_device_name(instance: &ash::Instance, physical_device: ash::vk::PhysicalDevice) -> String {
-        let device_properties = unsafe { 
-            instance.get_physical_device_properties(physical_device) 
+    // One line per device, `--list-devices` prints it and failing to pick a device logs it
+    pub fn format_device_list(physical_devices: &[VkPhysicalDevice]) -> String {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+
+        let mut lines = vec!["Index | Name | Type | Suitable | Compute storage images | Ray tracing".to_string()];
+        for (index, physical_device) in physical_devices.iter().enumerate() {
+            lines.push(format!(
+                "{} | {} | {} | {} | {} | {}",
+                index,
+                physical_device.name,
+                physical_device.device_type_name(),
+                yes_no(physical_device.is_suitable),
+                yes_no(physical_device.supports_storage_images),
+                yes_no(physical_device.supports_ray_tracing)
+            ));
+        }
+
+        lines.join("\n")
+    }
+
+    // The render target is written from compute shaders on the graphics queue
+    fn query_storage_image_support(instance: &ash::Instance, physical_device: ash::vk::PhysicalDevice) -> bool {
+        let device_queue_families = unsafe { 
+            instance.get_physical_device_queue_family_properties(physical_device)
         };
-        
-        utilities::cchar_to_string(&device_properties.device_name)
+        let format_properties = unsafe {
+            instance.get_physical_device_format_properties(physical_device, STORAGE_IMAGE_FORMAT)
+        };
+
+        let has_compute_queue = device_queue_families.iter().any(|queue_family| {
+            queue_family.queue_count > 0 && queue_family.queue_flags.contains(ash::vk::QueueFlags::GRAPHICS | ash::vk::QueueFlags::COMPUTE)
+        });
+
+        has_compute_queue && format_properties.optimal_tiling_features.contains(
+            ash::vk::FormatFeatureFlags::STORAGE_IMAGE | ash::vk::FormatFeatureFlags::BLIT_SRC
+        )
     }
 
-    fn is_physical_device_suitable(instance: &ash::Instance, physical_device: &VkPhysicalDevice, surface: &VkSurface) -> bool {
+    fn is_physical_device_suitable(instance: &ash::Instance, physical_device: &VkPhysicalDevice, surface: Option<&VkSurface>, available_extensions: &[ash::vk::ExtensionProperties]) -> bool {
         let device_properties = unsafe { 
             instance.get_physical_device_properties(physical_device.handle) 
         };
@@ -70,37 +211,43 @@ impl VkPhysicalDevice {
         let device_queue_families = unsafe { 
             instance.get_physical_device_queue_family_properties(physical_device.handle)
         };
-        let available_extensions = unsafe {
-            instance.enumerate_device_extension_properties(physical_device.handle).expect("Failed to get device extension properties.")
-        };
         
-        Self::print_device_info(
+        Self::log_device_info(
+            physical_device,
             &device_properties,
             &device_features,
             &device_queue_families,
-            &available_extensions
+            available_extensions
         );
 
         let indices = VkQueueFamilyIndices::find(instance, physical_device.handle, surface);
 
-        let is_device_extension_supported = Self::query_device_extensions_support(&available_extensions);
-        let is_swapchain_supported = if is_device_extension_supported {
-            let swapchain_support = VkSwapchain::query_swapchain_support(physical_device, surface);
+        // Headless devices only need to run the compute shaders
+        let surface = match surface {
+            Some(surface) => surface,
+            None => return indices.is_complete() && physical_device.supports_storage_images,
+        };
 
-            !swapchain_support.formats.is_empty() && !swapchain_support.present_modes.is_empty()
+        let is_device_extension_supported = Self::query_extensions_support(available_extensions, &DEVICE_EXTENSIONS);
+        let is_swapchain_supported = if is_device_extension_supported {
+            // A device whose surface can't be queried can't present either
+            match VkSwapchain::query_swapchain_support(physical_device, surface) {
+                Ok(swapchain_support) => !swapchain_support.formats.is_empty() && !swapchain_support.present_modes.is_empty(),
+                Err(_) => false,
+            }
         } else {
             false
         };
 
-        return indices.is_complete() && is_device_extension_supported && is_swapchain_supported;
+        indices.is_complete() && is_device_extension_supported && is_swapchain_supported && physical_device.supports_storage_images
     }
 
-    fn query_device_extensions_support(available_extensions: &Vec<ash::vk::ExtensionProperties>) -> bool {
+    fn query_extensions_support(available_extensions: &[ash::vk::ExtensionProperties], extensions: &[&str]) -> bool {
         let available_extension_names: Vec<String> = available_extensions.iter().map(
             |extension| utilities::cchar_to_string(&extension.extension_name)
         ).collect();
 
-        let mut required_extensions: HashSet<String> = HashSet::from_iter(DEVICE_EXTENSIONS.iter().map(
+        let mut required_extensions: HashSet<String> = HashSet::from_iter(extensions.iter().map(
             |extension| extension.to_string()
         ));
 
@@ -108,36 +255,27 @@ impl VkPhysicalDevice {
             required_extensions.remove(extension_name);
         }
 
-        return required_extensions.is_empty();
+        required_extensions.is_empty()
     }
 
-    #[cfg(debug_assertions)]
-    fn print_device_info(
+    // Only at debug level, it is long
+    fn log_device_info(
+        physical_device: &VkPhysicalDevice,
         device_properties: &ash::vk::PhysicalDeviceProperties,
         device_features: &ash::vk::PhysicalDeviceFeatures,
-        device_queue_families: &Vec<ash::vk::QueueFamilyProperties>,
-        available_extensions: &Vec<ash::vk::ExtensionProperties>,
+        device_queue_families: &[ash::vk::QueueFamilyProperties],
+        available_extensions: &[ash::vk::ExtensionProperties],
     ) {
-        let device_type = match device_properties.device_type {
-            ash::vk::PhysicalDeviceType::CPU => "Cpu",
-            ash::vk::PhysicalDeviceType::INTEGRATED_GPU => "Integrated GPU",
-            ash::vk::PhysicalDeviceType::DISCRETE_GPU => "Discrete GPU",
-            ash::vk::PhysicalDeviceType::VIRTUAL_GPU => "Virtual GPU",
-            ash::vk::PhysicalDeviceType::OTHER => "Unknown",
-            _ => panic!(),
-        };
-    
-        let device_name = utilities::cchar_to_string(&device_properties.device_name);
-        println!("\tDevice Name: {}, id: {}, type: {}", device_name, device_properties.device_id, device_type);
+        log::debug!("\tDevice Name: {}, id: {}, type: {}", physical_device.name, device_properties.device_id, physical_device.device_type_name());
     
         let major_version = ash::vk::api_version_major(device_properties.api_version);
         let minor_version = ash::vk::api_version_minor(device_properties.api_version);
         let patch_version = ash::vk::api_version_patch(device_properties.api_version);
     
-        println!("\tAPI Version: {}.{}.{}",major_version, minor_version, patch_version);
+        log::debug!("\tAPI Version: {}.{}.{}",major_version, minor_version, patch_version);
     
-        println!("\tSupport Queue Family: {}", device_queue_families.len());
-        println!("\t\tQueue Count |  Graphics,    Compute,   Transfer,   Sparse Binding");
+        log::debug!("\tSupport Queue Family: {}", device_queue_families.len());
+        log::debug!("\t\tQueue Count |  Graphics,    Compute,   Transfer,   Sparse Binding");
         
         for queue_family in device_queue_families.iter() {
             let is_graphics_support = if queue_family.queue_flags.contains(ash::vk::QueueFlags::GRAPHICS) {
@@ -161,7 +299,7 @@ impl VkPhysicalDevice {
                 "unsupport"
             };
         
-            println!(
+            log::debug!(
                 "\t\t{}\t    | {},  {},  {},  {}",
                 queue_family.queue_count,
                 is_graphics_support,
@@ -171,26 +309,16 @@ impl VkPhysicalDevice {
             );
         }
 
-        println!("\tAvailable Device Extensions: ");
+        log::debug!("\tAvailable Device Extensions: ");
         for extension in available_extensions.iter() {
             let extension_name = utilities::cchar_to_string(&extension.extension_name);
-            println!(
+            log::debug!(
                 "\t\tName: {}, Version: {}",
                 extension_name, extension.spec_version
             );
         }
 
-        println!("\tAvailable Device Features:\n\t{:?}\n", device_features);
-    }
-
-    #[cfg(not(debug_assertions))]
-    fn print_device_info(
-        device_properties: &ash::vk::PhysicalDeviceProperties,
-        device_features: &ash::vk::PhysicalDeviceFeatures,
-        device_queue_families: &Vec<ash::vk::QueueFamilyProperties>,
-        available_extensions: &Vec<ash::vk::ExtensionProperties>,
-    ) {
-        
+        log::debug!("\tAvailable Device Features:\n\t{:?}\n", device_features);
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use crate::vk::{command_buffer::VkCommandBuffer, device::VkDevice, error::*};
+
+pub struct VkTimestampQueryPool {
+    pub handle: ash::vk::QueryPool,
+    pub count: u32,
+    // Nanoseconds per timestamp tick, see VkPhysicalDevice::timestamp_period
+    timestamp_period: f32,
+    // See VkPhysicalDevice::timestamp_valid_bits
+    timestamp_valid_bits: u32,
+    device: Arc<VkDevice>,
+}
+
+impl VkTimestampQueryPool {
+    pub fn new(device: &Arc<VkDevice>, count: u32, timestamp_period: f32, timestamp_valid_bits: u32) -> VkResult<Self> {
+        let create_info = ash::vk::QueryPoolCreateInfo::builder()
+            .query_type(ash::vk::QueryType::TIMESTAMP)
+            .query_count(count)
+            .build();
+
+        Ok(Self {
+            handle: unsafe { device.create_query_pool(&create_info, None).or_fail_to("create Query Pool")? },
+            count,
+            timestamp_period,
+            timestamp_valid_bits,
+            device: device.clone(),
+        })
+    }
+
+    // Has to be recorded before the timestamps are written again
+    pub fn reset(&self, command_buffer: &VkCommandBuffer) {
+        unsafe {
+            self.device.cmd_reset_query_pool(command_buffer.handle, self.handle, 0, self.count);
+        }
+    }
+
+    // Written once all commands recorded before it have finished `stage`
+    pub fn write(&self, command_buffer: &VkCommandBuffer, stage: ash::vk::PipelineStageFlags, query: u32) {
+        unsafe {
+            self.device.cmd_write_timestamp(command_buffer.handle, stage, self.handle, query);
+        }
+    }
+
+    // Milliseconds since the first timestamp for every query, the command buffer has to be done executing
+    pub fn read_ms(&self) -> VkResult<Vec<f64>> {
+        let mut timestamps = vec![0u64; self.count as usize];
+        unsafe {
+            self.device.get_query_pool_results(
+                self.handle,
+                0,
+                self.count,
+                &mut timestamps,
+                ash::vk::QueryResultFlags::TYPE_64 | ash::vk::QueryResultFlags::WAIT
+            ).or_fail_to("get query pool results")?;
+        }
+
+        Ok(timestamps.iter().map(
+            |&timestamp| ticks_between(timestamps[0], timestamp, self.timestamp_valid_bits) as f64 * self.timestamp_period as f64 / 1_000_000.0
+        ).collect())
+    }
+}
+
+// Ticks from `first` to `timestamp`, only the low `valid_bits` count and they can wrap around in between
+fn ticks_between(first: u64, timestamp: u64, valid_bits: u32) -> u64 {
+    let mask = if valid_bits >= 64 { u64::MAX } else { (1 << valid_bits) - 1 };
+    (timestamp & mask).wrapping_sub(first & mask) & mask
+}
+
+impl Drop for VkTimestampQueryPool {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_query_pool(self.handle, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_ignore_invalid_bits_and_wrap_around() {
+        assert_eq!(ticks_between(10, 25, 64), 15);
+        assert_eq!(ticks_between(u64::MAX - 4, 5, 64), 10);
+
+        // The upper bits hold garbage, the counter wrapped at 2^36
+        let garbage = 0xabcd << 48;
+        assert_eq!(ticks_between(garbage | 100, garbage | 300, 36), 200);
+        assert_eq!(ticks_between(garbage | ((1 << 36) - 5), 0xfff0 << 48 | 5, 36), 10);
+    }
+}
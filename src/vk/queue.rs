@@ -1,6 +1,6 @@
 
 
-use crate::vk::{sync_objects::*, command_buffer::VkCommandBuffer};
+use crate::vk::{sync_objects::*, command_buffer::VkCommandBuffer, error::*};
 
 #[derive(Clone, Debug)]
 pub struct VkQueue {
@@ -22,15 +22,19 @@ impl VkQueue {
         device: &ash::Device,
         command_buffer: &VkCommandBuffer,
         wait_stages: ash::vk::PipelineStageFlags,
-        wait_semaphore: &VkSemaphore,
-        signal_semaphore: &VkSemaphore,
+        wait_semaphore: Option<&VkSemaphore>,
+        signal_semaphore: Option<&VkSemaphore>,
         fence: &VkFence
-    ) {
+    ) -> VkResult<()> {
+        let wait_semaphores: Vec<ash::vk::Semaphore> = wait_semaphore.iter().map(|semaphore| semaphore.handle).collect();
+        let wait_dst_stage_masks: Vec<ash::vk::PipelineStageFlags> = wait_semaphore.iter().map(|_| wait_stages).collect();
+        let signal_semaphores: Vec<ash::vk::Semaphore> = signal_semaphore.iter().map(|semaphore| semaphore.handle).collect();
+
         let submit_infos = [ash::vk::SubmitInfo::builder()
-            .wait_semaphores(&[wait_semaphore.handle]) 
-            .wait_dst_stage_mask(&[wait_stages])
+            .wait_semaphores(&wait_semaphores) 
+            .wait_dst_stage_mask(&wait_dst_stage_masks)
             .command_buffers(&[command_buffer.handle])
-            .signal_semaphores(&[signal_semaphore.handle])
+            .signal_semaphores(&signal_semaphores)
             .build()
         ];
 
@@ -39,11 +43,11 @@ impl VkQueue {
                 self.handle,
                 &submit_infos,
                 fence.handle,
-            ).expect("Failed to execute queue submit.");
+            ).or_fail_to("execute queue submit")
         }
     }
 
-    pub fn submit_once(&self,device: &ash::Device, command_buffer: &VkCommandBuffer) {
+    pub fn submit_once(&self,device: &ash::Device, command_buffer: &VkCommandBuffer) -> VkResult<()> {
         let submit_infos = [ash::vk::SubmitInfo {
             s_type: ash::vk::StructureType::SUBMIT_INFO,
             p_next: std::ptr::null(),
@@ -57,8 +61,8 @@ impl VkQueue {
         }];
 
         unsafe {
-            device.queue_submit(self.handle, &submit_infos, ash::vk::Fence::null()).expect("Failed to execute single queue submit.");
-            device.queue_wait_idle(self.handle).expect("Failed to wait for a queue to go idle!");
+            device.queue_submit(self.handle, &submit_infos, ash::vk::Fence::null()).or_fail_to("execute single queue submit")?;
+            device.queue_wait_idle(self.handle).or_fail_to("wait for a queue to go idle")
         }
     }
 }
\ No newline at end of file
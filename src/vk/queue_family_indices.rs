@@ -20,7 +20,7 @@ impl VkQueueFamilyIndices {
         }
     }
 
-    pub fn find(instance: &ash::Instance, physical_device: ash::vk::PhysicalDevice, surface: &VkSurface) -> VkQueueFamilyIndices {
+    pub fn find(instance: &ash::Instance, physical_device: ash::vk::PhysicalDevice, surface: Option<&VkSurface>) -> VkQueueFamilyIndices {
         let queue_families = unsafe { 
             instance.get_physical_device_queue_family_properties(physical_device) 
         };
@@ -36,8 +36,12 @@ impl VkQueueFamilyIndices {
                 queue_family_indices.transfer = Some(index);
             }
 
-            let is_present_support = unsafe {
-                surface.loader.get_physical_device_surface_support(physical_device,index as u32, surface.handle).unwrap()
+            // Nothing is presented without a surface, the graphics queue stands in for the present queue
+            let is_present_support = match surface {
+                Some(surface) => unsafe {
+                    surface.loader.get_physical_device_surface_support(physical_device, index, surface.handle).unwrap_or(false)
+                },
+                None => queue_family.queue_flags.contains(ash::vk::QueueFlags::GRAPHICS),
             };
             if queue_family.queue_count > 0 && is_present_support {
                 queue_family_indices.present = Some(index);
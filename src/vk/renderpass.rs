@@ -1,3 +1,5 @@
+use crate::vk::error::*;
+
 
 
 #[derive(Clone, Default)]
@@ -6,7 +8,7 @@ pub struct VkRenderPass {
 }
 
 impl VkRenderPass {
-    pub fn new(device: &ash::Device, color_attachments: Option<&Vec<(ash::vk::AttachmentDescription, ash::vk::AttachmentReference)>>, depth_attachment: Option<(ash::vk::AttachmentDescription, ash::vk::AttachmentReference)>) -> Self {
+    pub fn new(device: &ash::Device, color_attachments: Option<&Vec<(ash::vk::AttachmentDescription, ash::vk::AttachmentReference)>>, depth_attachment: Option<(ash::vk::AttachmentDescription, ash::vk::AttachmentReference)>) -> VkResult<Self> {
         let (mut render_pass_attachments, render_pass_attachment_refs): (Vec<ash::vk::AttachmentDescription>, Vec<ash::vk::AttachmentReference>) = if color_attachments.is_some() {
             (color_attachments.unwrap().iter().map(|(attachment, _attachment_ref)| attachment.clone()).collect(),
             color_attachments.unwrap().iter().map(|(_attachment, attachment_ref)| attachment_ref.clone()).collect())
@@ -37,12 +39,12 @@ impl VkRenderPass {
             .build();
 
         let render_pass = unsafe {
-            device.create_render_pass(&renderpass_create_info, None).expect("Failed to create render pass!")
+            device.create_render_pass(&renderpass_create_info, None).or_fail_to("create render pass")?
         };
 
-        VkRenderPass {
+        Ok(VkRenderPass {
             handle: render_pass
-        }
+        })
     }
 
     pub fn destroy(&self, device: &ash::Device) {
@@ -1,6 +1,8 @@
 
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
+use crate::vk::error::*;
+
 #[derive(Clone)]
 pub struct VkSurface {
     pub handle: ash::vk::SurfaceKHR,
@@ -8,7 +10,7 @@ pub struct VkSurface {
 }
 
 impl VkSurface {
-    pub fn new(entry: &ash::Entry, instance: &ash::Instance, window: &winit::window::Window) -> Self {
+    pub fn new(entry: &ash::Entry, instance: &ash::Instance, window: &winit::window::Window) -> VkResult<Self> {
         let surface = unsafe {
             ash_window::create_surface(
                 entry,
@@ -16,15 +18,15 @@ impl VkSurface {
                 window.raw_display_handle(),
                 window.raw_window_handle(),
                 None
-            ).expect("Failed to create surface.")   
+            ).or_fail_to("create surface")?
         };
         
         let surface_loader = ash::extensions::khr::Surface::new(entry, instance);
 
-        VkSurface {
+        Ok(VkSurface {
             handle: surface,
             loader: surface_loader
-        }
+        })
     }
 
     pub fn destroy(&self) {
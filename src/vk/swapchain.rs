@@ -1,7 +1,8 @@
 use crate::vk::{
     surface::*,
     physical_device::*,
-    sync_objects::VkSemaphore, queue::VkQueue
+    sync_objects::VkSemaphore, queue::VkQueue,
+    error::*
 };
 
 
@@ -30,9 +31,11 @@ impl VkSwapchain {
         physical_device: &VkPhysicalDevice,
         surface: &VkSurface,
         desired_extent: ash::vk::Extent2D
-    ) -> VkSwapchain {
-        let swapchain_support = Self::query_swapchain_support(physical_device, surface);
-        let swapchain_format = Self::choose_swapchain_format(&swapchain_support.formats);
+    ) -> VkResult<VkSwapchain> {
+        let swapchain_support = Self::query_swapchain_support(physical_device, surface)?;
+        let swapchain_format = Self::choose_swapchain_format(&swapchain_support.formats).ok_or(
+            VkError::Vulkan { action: "find a Swapchain format", result: ash::vk::Result::ERROR_FORMAT_NOT_SUPPORTED }
+        )?;
         let present_mode = Self::choose_swapchain_present_mode(&swapchain_support.present_modes);
         let extent = Self::choose_swapchain_extent(desired_extent, &swapchain_support.capabilities);
 
@@ -73,23 +76,23 @@ impl VkSwapchain {
 
         let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, device);
         let swapchain = unsafe {
-            swapchain_loader.create_swapchain(&swapchain_create_info, None).expect("Failed to create Swapchain!")
+            swapchain_loader.create_swapchain(&swapchain_create_info, None).or_fail_to("create Swapchain")?
         };
 
         let swapchain_images = unsafe {
-            swapchain_loader.get_swapchain_images(swapchain).expect("Failed to get Swapchain Images.")
+            swapchain_loader.get_swapchain_images(swapchain).or_fail_to("get Swapchain Images")?
         };
 
-        let image_views = Self::create_image_views(device, swapchain_format.format, &swapchain_images);
+        let image_views = Self::create_image_views(device, swapchain_format.format, &swapchain_images)?;
 
-        VkSwapchain {
+        Ok(VkSwapchain {
             loader: swapchain_loader,
             handle: swapchain,
             format: swapchain_format.format,
             extent: extent,
             images: swapchain_images,
             image_views
-        }
+        })
     }
     pub fn destroy(&self, device: &ash::Device) {
         unsafe {
@@ -118,24 +121,24 @@ impl VkSwapchain {
         }
     }
 
-    pub fn query_swapchain_support(physical_device: &VkPhysicalDevice, surface: &VkSurface) -> VkSwapchainSupportDetails {
+    pub fn query_swapchain_support(physical_device: &VkPhysicalDevice, surface: &VkSurface) -> VkResult<VkSwapchainSupportDetails> {
         unsafe {
             let capabilities = surface.loader.get_physical_device_surface_capabilities(physical_device.handle, surface.handle)
-                .expect("Failed to query for surface capabilities.");
+                .or_fail_to("query for surface capabilities")?;
             let formats = surface.loader.get_physical_device_surface_formats(physical_device.handle, surface.handle)
-                .expect("Failed to query for surface formats.");
+                .or_fail_to("query for surface formats")?;
             let present_modes = surface.loader.get_physical_device_surface_present_modes(physical_device.handle, surface.handle)
-                .expect("Failed to query for surface present mode.");
+                .or_fail_to("query for surface present mode")?;
     
-            VkSwapchainSupportDetails {
+            Ok(VkSwapchainSupportDetails {
                 capabilities,
                 formats,
                 present_modes,
-            }
+            })
         }
     }
 
-    fn create_image_views(device: &ash::Device, format: ash::vk::Format,images: &Vec<ash::vk::Image>) -> Vec<ash::vk::ImageView> {
+    fn create_image_views(device: &ash::Device, format: ash::vk::Format,images: &Vec<ash::vk::Image>) -> VkResult<Vec<ash::vk::ImageView>> {
         let swapchain_image_views = images.iter().map(|&image| {
             let create_info = ash::vk::ImageViewCreateInfo::builder()
                 .view_type(ash::vk::ImageViewType::TYPE_2D) 
@@ -156,21 +159,21 @@ impl VkSwapchain {
                 .build();
 
             unsafe {
-                device.create_image_view(&create_info, None).expect("Failed to create Image View!")
+                device.create_image_view(&create_info, None).or_fail_to("create Image View")
             }
         }).collect();
 
         swapchain_image_views
     }
 
-    fn choose_swapchain_format(available_formats: &Vec<ash::vk::SurfaceFormatKHR>) -> ash::vk::SurfaceFormatKHR {
+    fn choose_swapchain_format(available_formats: &Vec<ash::vk::SurfaceFormatKHR>) -> Option<ash::vk::SurfaceFormatKHR> {
         if let Some(format) = available_formats.iter().find(
             |&format| format.format == ash::vk::Format::B8G8R8A8_SRGB && format.color_space == ash::vk::ColorSpaceKHR::SRGB_NONLINEAR
         ) {
-            format
+            Some(format)
         } else {
-            available_formats.first().unwrap()
-        }.clone()
+            available_formats.first()
+        }.cloned()
     }
 
     fn choose_swapchain_present_mode(available_present_modes: &Vec<ash::vk::PresentModeKHR>) -> ash::vk::PresentModeKHR {
@@ -1,52 +1,66 @@
+use std::sync::Arc;
+
+use crate::vk::{device::VkDevice, error::*};
+
 
 pub struct VkSemaphore {
-    pub handle: ash::vk::Semaphore
+    pub handle: ash::vk::Semaphore,
+    device: Arc<VkDevice>,
 }
 
 impl VkSemaphore {
-    pub fn new(device: &ash::Device) -> Self {
+    pub fn new(device: &Arc<VkDevice>) -> VkResult<Self> {
         let create_info = ash::vk::SemaphoreCreateInfo::builder().build();
 
-        Self {
-            handle: unsafe { device.create_semaphore(&create_info, None).expect("Failed to create Semaphore Object!") }
-        }
+        Ok(Self {
+            handle: unsafe { device.create_semaphore(&create_info, None).or_fail_to("create Semaphore Object")? },
+            device: device.clone(),
+        })
     }
-    pub fn destroy(&self, device: &ash::Device) {
+}
+
+impl Drop for VkSemaphore {
+    fn drop(&mut self) {
         unsafe {
-            device.destroy_semaphore(self.handle, None);
+            self.device.destroy_semaphore(self.handle, None);
         }
     }
 }
 
 pub struct VkFence {
-    pub handle: ash::vk::Fence
+    pub handle: ash::vk::Fence,
+    device: Arc<VkDevice>,
 }
 
 impl VkFence {
-    pub fn new(device: &ash::Device, create_flags: ash::vk::FenceCreateFlags) -> Self {
+    pub fn new(device: &Arc<VkDevice>, create_flags: ash::vk::FenceCreateFlags) -> VkResult<Self> {
         let create_info = ash::vk::FenceCreateInfo::builder().flags(create_flags).build();
 
-        Self {
-            handle: unsafe { 
-                device.create_fence(&create_info, None).expect("Failed to create Fence Object!") 
-            }
-        }
+        Ok(Self {
+            handle: unsafe {
+                device.create_fence(&create_info, None).or_fail_to("create Fence Object")?
+            },
+            device: device.clone(),
+        })
     }
-    pub fn destroy(&self, device: &ash::Device) {
+
+    pub fn wait(&self) -> VkResult<()> {
         unsafe {
-            device.destroy_fence(self.handle, None);
+            self.device.wait_for_fences(&[self.handle], true, u64::MAX).or_fail_to("wait for Fence")
         }
     }
 
-    pub fn wait(&self, device: &ash::Device) {
+    pub fn reset(&self) -> VkResult<()> {
         unsafe {
-            device.wait_for_fences(&[self.handle], true, std::u64::MAX).expect("Failed to wait for Fence!");
+            self.device.reset_fences(&[self.handle]).or_fail_to("reset Fence")
         }
     }
+}
 
-    pub fn reset(&self, device: &ash::Device) {
+impl Drop for VkFence {
+    fn drop(&mut self) {
         unsafe {
-            device.reset_fences(&[self.handle]).expect("Failed to reset Fence!");
+            self.device.destroy_fence(self.handle, None);
         }
     }
-}
\ No newline at end of file
+}
@@ -3,13 +3,17 @@ use gpu_allocator::MemoryLocation;
 
 use image::DynamicImage;
 
+use std::sync::Arc;
+
 use crate::{
     utilities,
     vk::{
         buffer::VkBuffer,
         command_buffer::VkCommandBuffer,
         command_pool::VkCommandPool,
-        queue::VkQueue
+        device::VkDevice,
+        queue::VkQueue,
+        error::*
     }
 };
 
@@ -22,18 +26,19 @@ pub struct VkTexture {
 
     pub aspect: ash::vk::ImageAspectFlags,
     pub layout: ash::vk::ImageLayout,
+
+    device: Arc<VkDevice>,
 }
 
 impl VkTexture {
     pub fn new(
-        device: &ash::Device,
-        allocator: &mut Allocator,
+        device: &Arc<VkDevice>,
         img_format: ash::vk::Format,
         extent: ash::vk::Extent2D,
         tiling: ash::vk::ImageTiling,
         usage: ash::vk::ImageUsageFlags,
         aspect: ash::vk::ImageAspectFlags
-    ) -> Self {
+    ) -> VkResult<Self> {
         let image_info = ash::vk::ImageCreateInfo::builder()
             .image_type(ash::vk::ImageType::TYPE_2D)
             .format(img_format)
@@ -50,24 +55,39 @@ impl VkTexture {
             .array_layers(1)
             .build();
 
-        let image = unsafe { 
+        let handle = unsafe { 
             device.create_image(&image_info, None) 
-        }.unwrap();
+        }.or_fail_to("create Image")?;
+
+        // From here on dropping the texture cleans up after a failure
+        let mut texture = Self {
+            handle,
+            view: ash::vk::ImageView::null(),
+            sampler: None,
+            allocation: None,
+            extent,
+
+            aspect,
+            layout: ash::vk::ImageLayout::UNDEFINED,
+
+            device: device.clone(),
+        };
 
         let requirements = unsafe { 
-            device.get_image_memory_requirements(image) 
+            device.get_image_memory_requirements(handle) 
         };
 
-        let allocation = allocator.allocate(&AllocationCreateDesc {
+        let allocation = device.allocator().allocate(&AllocationCreateDesc {
             name: "Image Allocation",
             requirements,
             location: MemoryLocation::GpuOnly,
             linear: false,
             allocation_scheme: AllocationScheme::GpuAllocatorManaged,
-        }).expect("Failed to allocate an Image!");
+        }).or_fail_to("allocate an Image")?;
+        let allocation = texture.allocation.insert(allocation);
         
         unsafe { 
-            device.bind_image_memory(image, allocation.memory(), 0).expect("Failed to bind image memory!");
+            device.bind_image_memory(handle, allocation.memory(), 0).or_fail_to("bind image memory")?;
         };
 
         let image_view_info = ash::vk::ImageViewCreateInfo::builder()
@@ -85,26 +105,22 @@ impl VkTexture {
                 .level_count(1)
                 .build()
             )
-            .image(image)
+            .image(handle)
             .build();
 
-        let image_view = unsafe { 
+        texture.view = unsafe { 
             device.create_image_view(&image_view_info, None) 
-        }.unwrap();
+        }.or_fail_to("create Image View")?;
 
-        Self {
-            handle: image,
-            view: image_view,
-            sampler: None,
-            allocation: Some(allocation),
-            extent,
+        Ok(texture)
+    }
 
-            aspect,
-            layout: ash::vk::ImageLayout::UNDEFINED
-        }
+    pub fn set_name(&self, name: &str) {
+        self.device.set_object_name(self.handle, name);
+        self.device.set_object_name(self.view, &format!("{} view", name));
     }
 
-    pub fn create_sampler(&mut self, device: &ash::Device, anisotropy: Option<f32>) {
+    pub fn create_sampler(&mut self, anisotropy: Option<f32>) -> VkResult<()> {
         let sampler_create_info = ash::vk::SamplerCreateInfo::builder()
             .min_filter(ash::vk::Filter::LINEAR)
             .mag_filter(ash::vk::Filter::LINEAR)
@@ -118,11 +134,13 @@ impl VkTexture {
             .build();
 
         self.sampler = Some(unsafe {
-            device.create_sampler(&sampler_create_info, None).expect("Failed to create Sampler!")
-        })
+            self.device.create_sampler(&sampler_create_info, None).or_fail_to("create Sampler")?
+        });
+
+        Ok(())
     }
 
-    pub fn fill_from_file(&mut self, device: &ash::Device, allocator: &mut Allocator, image_object: &DynamicImage, command_pool: &VkCommandPool, queue: &VkQueue) {  
+    pub fn fill_from_file(&mut self, image_object: &DynamicImage, command_pool: &VkCommandPool, queue: &VkQueue) -> VkResult<()> {  
         let (image_width, image_height) = (image_object.width(), image_object.height());
         let image_size = (image_width * image_height * 4) as ash::vk::DeviceSize;
         let image_data = match &image_object {
@@ -130,48 +148,31 @@ impl VkTexture {
             | image::DynamicImage::ImageRgb8(_)
             | image::DynamicImage::ImageLumaA8(_)
             | image::DynamicImage::ImageRgba8(_) => image_object.to_rgba8().into_raw(),
-            _ => panic!("Unknown texture format!")
+            _ => return Err(VkError::InvalidImage("unknown texture format"))
         };
 
         if image_size <= 0 {
-            panic!("Failed to load texture image!")
+            return Err(VkError::InvalidImage("empty texture image"));
         }
 
+        let device = self.device.clone();
+
         let mut staging_buffer = VkBuffer::new(
-            device,
-            allocator,
+            &device,
             image_size,
             ash::vk::BufferUsageFlags::TRANSFER_SRC,
             MemoryLocation::CpuToGpu
-        );
+        )?;
 
         staging_buffer.fill(&image_data);
 
-        let cmd = utilities::begin_single_queue_submit(device, command_pool);
-        self.transition_layout(device, ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL, &cmd);
-
-        staging_buffer.copy_to_image(&cmd, &self, device);
-
-        self.transition_layout(device, ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, &cmd);
-        utilities::end_single_queue_submit(device, command_pool, queue, cmd);
+        let cmd = utilities::begin_single_queue_submit(&device, command_pool)?;
+        self.transition_layout(&device, ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL, &cmd);
 
-        staging_buffer.destroy(device, allocator);
+        staging_buffer.copy_to_image(&cmd, self, &device);
 
-    }
-
-    pub fn destroy(&mut self, device: &ash::Device, allocator: &mut Allocator) {
-        unsafe {
-            if let Some(sampler) = self.sampler {
-                device.destroy_sampler(sampler, None);
-            }
-
-            device.destroy_image_view(self.view, None);
-            device.destroy_image(self.handle, None);
-
-            let mut alloc: Option<Allocation> = None;
-            std::mem::swap(&mut alloc, &mut self.allocation);
-            allocator.free(alloc.unwrap()).expect("Failed to free allocated texture memory!");
-        }
+        self.transition_layout(&device, ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, &cmd);
+        utilities::end_single_queue_submit(&device, command_pool, queue, cmd)
     }
 
     pub fn transition_layout(
@@ -212,6 +213,56 @@ impl VkTexture {
         }
     }
 
+    // Tightly packed, the buffer needs to hold width * height texels
+    pub fn copy_to_buffer(&self, device: &ash::Device, command_buffer: &VkCommandBuffer, target: &VkBuffer) {
+        let region = ash::vk::BufferImageCopy::builder()
+            .image_subresource(ash::vk::ImageSubresourceLayers::builder()
+                .aspect_mask(self.aspect)
+                .layer_count(1)
+                .build()
+            )
+            .image_extent(ash::vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1
+            })
+            .build();
+
+        unsafe {
+            device.cmd_copy_image_to_buffer(command_buffer.handle, self.handle, self.layout, target.handle, &[region]);
+        }
+    }
+
+    // Unlike copy_to_image the sizes may differ, the image is scaled with `filter`
+    pub fn blit_to_image(&self, device: &ash::Device, command_buffer: &VkCommandBuffer, target: &BlitTarget, filter: ash::vk::Filter) {
+        let src_subresource = ash::vk::ImageSubresourceLayers::builder()
+            .aspect_mask(self.aspect)
+            .layer_count(1)
+            .build();
+        
+        let dst_subresource = ash::vk::ImageSubresourceLayers::builder()
+            .aspect_mask(target.aspect)
+            .layer_count(1)
+            .build();
+
+        let corner = |extent: ash::vk::Extent2D| ash::vk::Offset3D {
+            x: extent.width as i32,
+            y: extent.height as i32,
+            z: 1
+        };
+
+        let region = ash::vk::ImageBlit::builder()
+            .src_subresource(src_subresource)
+            .src_offsets([ash::vk::Offset3D::default(), corner(self.extent)])
+            .dst_subresource(dst_subresource)
+            .dst_offsets([ash::vk::Offset3D::default(), corner(target.extent)])
+            .build();
+
+        unsafe {
+            device.cmd_blit_image(command_buffer.handle, self.handle, self.layout, target.image, target.layout, &[region], filter);
+        }
+    }
+
     pub fn clear(&self, device: &ash::Device, command_buffer: &VkCommandBuffer, clear_color: cgmath::Vector4<f32>) {
         let range = ash::vk::ImageSubresourceRange::builder()
             .aspect_mask(self.aspect)
@@ -227,4 +278,31 @@ impl VkTexture {
             device.cmd_clear_color_image(command_buffer.handle, self.handle, self.layout, &clear_value, &[range]);
         }
     }
-}
\ No newline at end of file
+}
+
+// An image that isn't a VkTexture, like a swapchain image, to blit a texture into
+#[derive(Clone, Copy, Debug)]
+pub struct BlitTarget {
+    pub image: ash::vk::Image,
+    pub extent: ash::vk::Extent2D,
+    pub layout: ash::vk::ImageLayout,
+    pub aspect: ash::vk::ImageAspectFlags,
+}
+
+impl Drop for VkTexture {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(sampler) = self.sampler {
+                self.device.destroy_sampler(sampler, None);
+            }
+
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.handle, None);
+        }
+
+        if let Some(allocation) = self.allocation.take() {
+            // Nothing left to recover when this fails while tearing down
+            let _ = self.device.allocator().free(allocation);
+        }
+    }
+}
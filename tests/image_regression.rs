@@ -0,0 +1,166 @@
+// Renders the built-in scenes headless and compares them against the images in tests/references.
+// Monte Carlo noise differs between devices and drivers, so images only have to be close, not equal.
+//
+// The references are rendered on lavapipe, OXITRACE_TEST_DEVICE picks another device by index or name.
+// Without any Vulkan device the tests are skipped, a missing reference fails them. Run them with
+// OXITRACE_UPDATE_REFERENCES=1 to render the references after an intended change, and look at them
+// before committing.
+
+use oxitrace::{scene::Mesh, BuiltinScene, DeviceSelector, Renderer, SamplerType, ValidationSettings, VkError};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+// 80 samples per pixel, starting at sample index 0 so every run draws the same samples
+const FRAMES: u32 = 1;
+
+// Root mean square error over the RGB channels scaled to 0..1
+const MAX_RMSE: f64 = 0.03;
+// Differences are scaled up in the diff images to be visible at all
+const DIFF_SCALE: f32 = 8.0;
+// Removing the torus from heavy-mesh has to change the image by at least this much
+const MIN_MESH_RMSE: f64 = 0.05;
+
+#[test]
+fn spheres() {
+    check_scene(BuiltinScene::Spheres);
+}
+
+#[test]
+fn cornell_box() {
+    check_scene(BuiltinScene::CornellBox);
+}
+
+#[test]
+fn heavy_mesh() {
+    check_scene(BuiltinScene::HeavyMesh);
+}
+
+#[test]
+fn glass() {
+    check_scene(BuiltinScene::Glass);
+}
+
+// Catches a path tracer that skips triangles, which the preview and the references could hide
+#[test]
+fn heavy_mesh_shows_the_mesh() {
+    let scene = BuiltinScene::HeavyMesh;
+    let mut renderer = match create_renderer(scene) {
+        Some(renderer) => renderer,
+        None => return,
+    };
+
+    let with_mesh = render(&mut renderer, scene);
+    renderer.update_mesh(0, Mesh::new(0, 0, 0, 0)).unwrap();
+    let without_mesh = renderer.render_to_image(&scene.camera(WIDTH, HEIGHT), FRAMES).unwrap();
+
+    let difference = rmse(&with_mesh, &without_mesh);
+    assert!(difference > MIN_MESH_RMSE, "Removing the mesh only changed {} by an RMSE of {:.4}", scene.name(), difference);
+}
+
+fn render(renderer: &mut Renderer, scene: BuiltinScene) -> image::RgbaImage {
+    renderer.sampler_type = SamplerType::Sobol;
+    renderer.bind_scene(&scene.build().unwrap()).unwrap();
+    renderer.render_to_image(&scene.camera(WIDTH, HEIGHT), FRAMES).unwrap()
+}
+
+fn check_scene(scene: BuiltinScene) {
+    let mut renderer = match create_renderer(scene) {
+        Some(renderer) => renderer,
+        None => return,
+    };
+
+    // Rendered either way, so validation errors still fail the test without a reference
+    let actual = render(&mut renderer, scene);
+
+    let reference_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/references")
+        .join(format!("{}.png", scene.name()));
+
+    if std::env::var("OXITRACE_UPDATE_REFERENCES").is_ok() {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        actual.save(&reference_path).unwrap();
+        return;
+    }
+
+    assert!(reference_path.exists(), "{} is missing, see the top of this file", reference_path.display());
+
+    let reference = image::open(&reference_path).unwrap().to_rgba8();
+    assert_eq!(reference.dimensions(), actual.dimensions(), "{} has the wrong size", reference_path.display());
+
+    let error = rmse(&reference, &actual);
+    if error > MAX_RMSE {
+        let output = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("image_regression");
+        std::fs::create_dir_all(&output).unwrap();
+
+        let actual_path = output.join(format!("{}-actual.png", scene.name()));
+        let diff_path = output.join(format!("{}-diff.png", scene.name()));
+        actual.save(&actual_path).unwrap();
+        diff_image(&reference, &actual).save(&diff_path).unwrap();
+
+        panic!(
+            "{} differs from its reference by an RMSE of {:.4} (at most {} is allowed), see {} and {}",
+            scene.name(), error, MAX_RMSE, actual_path.display(), diff_path.display()
+        );
+    }
+}
+
+// Validation errors fail the test when the layers are installed. None when there is no device to render
+// on, any other error fails the test.
+fn create_renderer(scene: BuiltinScene) -> Option<Renderer> {
+    let device_selector = DeviceSelector::parse(&std::env::var("OXITRACE_TEST_DEVICE").unwrap_or_else(|_| "llvmpipe".to_string()));
+    let validation = ValidationSettings {
+        enabled: true,
+        panic_on_error: true,
+        ..Default::default()
+    };
+
+    let result = match Renderer::headless(&device_selector, &validation, WIDTH, HEIGHT) {
+        Err(VkError::ValidationLayersUnavailable) => Renderer::headless(&device_selector, &ValidationSettings::disabled(), WIDTH, HEIGHT),
+        result => result,
+    };
+
+    match result {
+        Ok(renderer) => Some(renderer),
+        Err(error) if is_missing_device(&error) => {
+            eprintln!("Skipping {}, no device to render on: {}", scene.name(), error);
+            None
+        }
+        Err(error) => panic!("Failed to create the renderer for {}: {}", scene.name(), error),
+    }
+}
+
+// No driver is installed, or none of the devices it reports matches OXITRACE_TEST_DEVICE
+fn is_missing_device(error: &VkError) -> bool {
+    match error {
+        VkError::NoSuitableDevice(_) => true,
+        VkError::Vulkan { result, .. } => *result == ash::vk::Result::ERROR_INCOMPATIBLE_DRIVER,
+        _ => false,
+    }
+}
+
+fn rmse(a: &image::RgbaImage, b: &image::RgbaImage) -> f64 {
+    let (sum, count) = a.pixels().zip(b.pixels()).fold((0.0, 0usize), |(sum, count), (a, b)| {
+        let squared: f64 = (0..3).map(|c| ((a[c] as f64 - b[c] as f64) / 255.0).powi(2)).sum();
+        (sum + squared, count + 3)
+    });
+
+    if count == 0 { 0.0 } else { (sum / count as f64).sqrt() }
+}
+
+fn diff_image(a: &image::RgbaImage, b: &image::RgbaImage) -> image::RgbaImage {
+    image::RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let (a, b) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let channel = |c: usize| ((a[c] as f32 - b[c] as f32).abs() * DIFF_SCALE).min(255.0) as u8;
+        image::Rgba([channel(0), channel(1), channel(2), 255])
+    })
+}
+
+#[test]
+fn rmse_of_uniform_difference() {
+    let black = image::RgbaImage::new(4, 4);
+    let gray = image::RgbaImage::from_pixel(4, 4, image::Rgba([51, 51, 51, 255]));
+
+    assert_eq!(rmse(&black, &black), 0.0);
+    assert!((rmse(&black, &gray) - 0.2).abs() < 1e-9);
+    assert_eq!(diff_image(&black, &gray).get_pixel(0, 0)[0], 255);
+}